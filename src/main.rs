@@ -2,7 +2,7 @@
 
 pub mod routines;
 
-use routines::user_interface::run_gui;
+use routines::{cli::run_command, user_interface::run_gui};
 
 pub fn main() {
     #[cfg(debug_assertions)]
@@ -10,7 +10,7 @@ pub fn main() {
         use glam::{Vec3A, vec3a};
         use routines::{
             graph::{Tree, find_rna_path},
-            sequencer::generate_sequence,
+            sequencer::{GeneratedSequence, SequencerSettings, generate_sequence},
         };
 
         simple_logger::init_with_level(log::Level::Debug).unwrap();
@@ -47,12 +47,18 @@ pub fn main() {
         let coordinates: Vec<Vec3A> = _create_tree_vertices();
         let path: Vec<usize> = find_rna_path(&mut tree, &coordinates);
         log::info!("RNA Path: {path:?}",);
-        let sequence: String = generate_sequence(&path, &tree, &coordinates);
+        let sequence: GeneratedSequence =
+            generate_sequence(&path, &tree, &coordinates, &SequencerSettings::default()).unwrap();
         log::info!("Sequence: {sequence}");
+        log::info!("Constraint violations: {:?}", sequence.violations);
     }
 
     #[cfg(not(debug_assertions))]
     simple_logger::init_with_level(log::Level::Info).unwrap();
 
-    run_gui();
+    // Command line subcommands run without opening the GUI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !run_command(&args) {
+        run_gui();
+    }
 }
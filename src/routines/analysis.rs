@@ -0,0 +1,529 @@
+use std::fmt::{Display, Formatter};
+
+use rustc_hash::FxHashMap;
+
+use super::{
+    nucleotide::Nucleotide,
+    sequencer::{NucleotideRecord, Source},
+};
+
+// Shortest loop that can close a hairpin
+pub const MIN_LOOP_LENGTH: usize = 3;
+
+// Two antiparallel stretches that can pair although they were not designed to
+// first..first + length pairs with second..second + length, first with second + length - 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OffTargetMatch {
+    pub first: usize,
+    pub second: usize,
+    pub length: usize,
+}
+
+impl OffTargetMatch {
+    // Paired indices, from the outermost pair inwards
+    pub fn pairs(&self) -> impl Iterator<Item = (usize, usize)> {
+        (0..self.length).map(|offset| (self.first + offset, self.second + self.length - 1 - offset))
+    }
+}
+
+impl Display for OffTargetMatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} nt: {}-{} with {}-{}",
+            self.length,
+            self.first,
+            self.first + self.length - 1,
+            self.second,
+            self.second + self.length - 1
+        )
+    }
+}
+
+// Off-target stretch, with what it involves in the design
+#[derive(Debug, Clone)]
+pub struct OffTargetReport {
+    pub off_target: OffTargetMatch,
+    pub wobbles: usize,              // Number of G·U pairs in the stretch
+    pub first_sources: Vec<Source>,  // Helices and motifs covered by the first stretch, in order
+    pub second_sources: Vec<Source>, // Helices and motifs covered by the second stretch, in order
+}
+
+impl Display for OffTargetReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.off_target)?;
+        if self.wobbles > 0 {
+            write!(f, " ({} G·U)", self.wobbles)?;
+        }
+        let describe = |sources: &[Source]| -> String {
+            sources
+                .iter()
+                .map(|source| source.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        if !self.first_sources.is_empty() || !self.second_sources.is_empty() {
+            write!(
+                f,
+                ", {} with {}",
+                describe(&self.first_sources),
+                describe(&self.second_sources)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotBracketError {
+    InvalidCharacter { position: usize, character: char },
+    UnmatchedBracket(usize),
+}
+
+impl Display for DotBracketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DotBracketError::InvalidCharacter {
+                position,
+                character,
+            } => write!(f, "Invalid character '{character}' at {position}"),
+            DotBracketError::UnmatchedBracket(position) => {
+                write!(f, "Unmatched bracket at {position}")
+            }
+        }
+    }
+}
+
+// Bracket pairs, in order of use for successive pseudoknot levels
+pub const BRACKETS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+
+// Partner of every position of a dot-bracket structure, whitespace is ignored
+pub fn parse_dot_bracket(structure: &str) -> Result<Vec<Option<usize>>, DotBracketError> {
+    let mut partners: Vec<Option<usize>> = Vec::new();
+    let mut open: [Vec<usize>; BRACKETS.len()] = Default::default();
+    structure
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .enumerate()
+        .try_for_each(|(position, character)| {
+            partners.push(None);
+            if character == '.' {
+                return Ok(());
+            }
+            if let Some(level) = BRACKETS
+                .iter()
+                .position(|(opening, _)| *opening == character)
+            {
+                open.get_mut(level).unwrap().push(position);
+            } else if let Some(level) = BRACKETS
+                .iter()
+                .position(|(_, closing)| *closing == character)
+            {
+                let opening: usize = open
+                    .get_mut(level)
+                    .unwrap()
+                    .pop()
+                    .ok_or(DotBracketError::UnmatchedBracket(position))?;
+                *partners.get_mut(opening).unwrap() = Some(position);
+                *partners.get_mut(position).unwrap() = Some(opening);
+            } else {
+                return Err(DotBracketError::InvalidCharacter {
+                    position,
+                    character,
+                });
+            }
+            Ok(())
+        })?;
+
+    match open.iter().flatten().min() {
+        Some(&position) => Err(DotBracketError::UnmatchedBracket(position)),
+        None => Ok(partners),
+    }
+}
+
+// Dot-bracket notation of a set of pairs
+// Each pair uses the first bracket type it does not cross, so pseudoknots use [], then {} and <>
+pub fn format_dot_bracket(partners: &[Option<usize>]) -> String {
+    let mut open: [Vec<usize>; BRACKETS.len()] = Default::default(); // Closing positions of open pairs
+    let mut levels: Vec<Option<usize>> = vec![None; partners.len()];
+    partners
+        .iter()
+        .enumerate()
+        .map(|(position, partner)| match *partner {
+            Some(closing) if closing > position => {
+                // Pairs of a level are nested, so only the innermost open pair can be crossed
+                let level: Option<usize> = open
+                    .iter()
+                    .position(|stack| stack.last().is_none_or(|&last| closing < last));
+                match level {
+                    Some(level) => {
+                        open.get_mut(level).unwrap().push(closing);
+                        *levels.get_mut(closing).unwrap() = Some(level);
+                        BRACKETS.get(level).unwrap().0
+                    }
+                    // Too many crossing pairs for the notation
+                    None => '.',
+                }
+            }
+            Some(_) => match *levels.get(position).unwrap() {
+                Some(level) => {
+                    open.get_mut(level).unwrap().pop();
+                    BRACKETS.get(level).unwrap().1
+                }
+                None => '.',
+            },
+            None => '.',
+        })
+        .collect()
+}
+
+// List the unintended complementary stretches of a sequence, G·U pairs included, longest first
+// The design provenance, if given, tells which helices and motifs are involved
+pub fn report_off_targets(
+    sequence: &[Nucleotide],
+    partners: &[Option<usize>],
+    min_length: usize,
+    provenance: Option<&[NucleotideRecord]>,
+) -> Vec<OffTargetReport> {
+    let sources = |start: usize, length: usize| -> Vec<Source> {
+        let mut sources: Vec<Source> = Vec::new();
+        if let Some(records) = provenance {
+            records.iter().skip(start).take(length).for_each(|record| {
+                if sources.last() != Some(&record.source) {
+                    sources.push(record.source);
+                }
+            });
+        }
+        sources
+    };
+
+    let mut reports: Vec<OffTargetReport> = find_off_targets(sequence, partners, min_length, true)
+        .into_iter()
+        .map(|off_target| OffTargetReport {
+            off_target,
+            wobbles: off_target
+                .pairs()
+                .filter(|&(first, second)| {
+                    sequence
+                        .get(first)
+                        .unwrap()
+                        .is_wobble(*sequence.get(second).unwrap())
+                })
+                .count(),
+            first_sources: sources(off_target.first, off_target.length),
+            second_sources: sources(off_target.second, off_target.length),
+        })
+        .collect();
+    reports.sort_by_key(|report| std::cmp::Reverse(report.off_target.length));
+    reports
+}
+
+// Find every maximal stretch of at least min_length consecutive unintended pairs
+// Identical stretches are found too, as each is complementary to the partner strand of the other
+pub fn find_off_targets(
+    sequence: &[Nucleotide],
+    partners: &[Option<usize>],
+    min_length: usize,
+    wobbles: bool,
+) -> Vec<OffTargetMatch> {
+    let mut matches: Vec<OffTargetMatch> = Vec::new();
+    scan_off_targets(sequence, partners, wobbles, |off_target| {
+        if off_target.length >= min_length.max(1) {
+            matches.push(off_target);
+        }
+    });
+    matches
+}
+
+// Longest unintended complementary stretch, None if no two nucleotides can pair unintentionally
+pub fn longest_off_target(
+    sequence: &[Nucleotide],
+    partners: &[Option<usize>],
+    wobbles: bool,
+) -> Option<OffTargetMatch> {
+    let mut longest: Option<OffTargetMatch> = None;
+    scan_off_targets(sequence, partners, wobbles, |off_target| {
+        if longest.is_none_or(|current| off_target.length > current.length) {
+            longest = Some(off_target);
+        }
+    });
+    longest
+}
+
+// Update the stretches found by find_off_targets once the nucleotides at the changed positions are replaced
+// Only the anti-diagonals through the changed positions are walked, from each change to the ends of its runs
+// Stretches are kept in the order find_off_targets gives them
+pub fn rescan_off_targets(
+    sequence: &[Nucleotide],
+    partners: &[Option<usize>],
+    min_length: usize,
+    wobbles: bool,
+    matches: &mut Vec<OffTargetMatch>,
+    changed: &[usize],
+) {
+    let length: usize = sequence.len();
+    let can_pair = |i: usize, j: usize| can_pair(sequence, partners, wobbles, i, j);
+    let inside = |diagonal: usize, i: usize| diagonal - i > i + MIN_LOOP_LENGTH;
+
+    // Stretch of each anti-diagonal that may have changed, between two cells that cannot pair
+    let mut segments: FxHashMap<usize, Vec<(usize, usize)>> = FxHashMap::default();
+    changed.iter().for_each(|&position| {
+        (position..position + length).for_each(|diagonal| {
+            let cell: usize = position.min(diagonal - position);
+            if !inside(diagonal, cell) {
+                return;
+            }
+            let mut start: usize = cell;
+            while start > diagonal.saturating_sub(length - 1)
+                && can_pair(start - 1, diagonal - start + 1)
+            {
+                start -= 1;
+            }
+            let mut end: usize = cell;
+            while inside(diagonal, end + 1) && can_pair(end + 1, diagonal - end - 1) {
+                end += 1;
+            }
+            segments.entry(diagonal).or_default().push((start, end));
+        });
+    });
+    segments.values_mut().for_each(|ranges| {
+        ranges.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+        ranges
+            .iter()
+            .for_each(|&(start, end)| match merged.last_mut() {
+                Some((_, last_end)) if start <= *last_end + 1 => *last_end = end.max(*last_end),
+                _ => merged.push((start, end)),
+            });
+        *ranges = merged;
+    });
+
+    // Replace the stretches within each changed segment
+    matches.retain(|off_target| {
+        let last: usize = off_target.first + off_target.length - 1;
+        segments
+            .get(&(off_target.second + last))
+            .is_none_or(|ranges| {
+                ranges
+                    .iter()
+                    .all(|&(start, end)| last < start || off_target.first > end)
+            })
+    });
+    segments.iter().for_each(|(&diagonal, ranges)| {
+        ranges.iter().for_each(|&(start, end)| {
+            let mut run_start: Option<usize> = None;
+            (start..=end + 1).for_each(|i| {
+                if i <= end && can_pair(i, diagonal - i) {
+                    run_start.get_or_insert(i);
+                } else if let Some(first) = run_start.take()
+                    && i - first >= min_length.max(1)
+                {
+                    matches.push(OffTargetMatch {
+                        first,
+                        second: diagonal - (i - 1),
+                        length: i - first,
+                    });
+                }
+            });
+        });
+    });
+    matches.sort_unstable_by_key(|off_target| {
+        (
+            off_target.first + off_target.second + off_target.length - 1,
+            off_target.first,
+        )
+    });
+}
+
+// Whether two nucleotides can pair although they were not designed to
+fn can_pair(
+    sequence: &[Nucleotide],
+    partners: &[Option<usize>],
+    wobbles: bool,
+    i: usize,
+    j: usize,
+) -> bool {
+    let (first, second): (Nucleotide, Nucleotide) =
+        (*sequence.get(i).unwrap(), *sequence.get(j).unwrap());
+    let complementary: bool = if wobbles {
+        first.pairs_with(second)
+    } else {
+        first.is_watson_crick(second)
+    };
+    complementary && partners.get(i).copied().flatten() != Some(j)
+}
+
+// Antiparallel stretches lie on the anti-diagonals i + j = constant of the pairing matrix
+// Each anti-diagonal is walked inwards, until the loop between i and j becomes too short
+fn scan_off_targets(
+    sequence: &[Nucleotide],
+    partners: &[Option<usize>],
+    wobbles: bool,
+    mut on_match: impl FnMut(OffTargetMatch),
+) {
+    let length: usize = sequence.len();
+    let can_pair = |i: usize, j: usize| can_pair(sequence, partners, wobbles, i, j);
+
+    (0..(2 * length).saturating_sub(1)).for_each(|diagonal| {
+        let mut run_start: Option<usize> = None;
+        let mut i: usize = diagonal.saturating_sub(length - 1);
+        loop {
+            let inside: bool = diagonal - i > i + MIN_LOOP_LENGTH;
+            if inside && can_pair(i, diagonal - i) {
+                run_start.get_or_insert(i);
+            } else if let Some(start) = run_start.take() {
+                on_match(OffTargetMatch {
+                    first: start,
+                    second: diagonal - (i - 1),
+                    length: i - start,
+                });
+            }
+            if !inside {
+                break;
+            }
+            i += 1;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use nanorand::{Rng, WyRand};
+
+    use super::*;
+    use crate::routines::{nucleotide::RnaSequence, sequencer::Role};
+
+    #[test]
+    fn crossing_pairs_take_the_next_brackets() {
+        [
+            "((..[[..))..]]",
+            "(([[{{..))]]}}",
+            "((..))..((..))",
+            "((..[[..))..((..]]..))",
+        ]
+        .into_iter()
+        .for_each(|structure| {
+            let partners: Vec<Option<usize>> = parse_dot_bracket(structure).unwrap();
+            assert_eq!(format_dot_bracket(&partners), structure);
+        });
+        // Rewritten with the first bracket type each pair does not cross
+        let partners: Vec<Option<usize>> = parse_dot_bracket("[[..((..]]..))").unwrap();
+        assert_eq!(format_dot_bracket(&partners), "((..[[..))..]]");
+        let partners: Vec<Option<usize>> = parse_dot_bracket("((..))[[..]]").unwrap();
+        assert_eq!(format_dot_bracket(&partners), "((..))((..))");
+        // Five mutually crossing pairs, one more than there are bracket types
+        let partners: Vec<Option<usize>> =
+            (0..10).map(|position| Some((position + 5) % 10)).collect();
+        assert_eq!(format_dot_bracket(&partners), "([{<.)]}>.");
+    }
+
+    #[test]
+    fn rescans_match_full_scans() {
+        let rng: &mut WyRand = &mut WyRand::new_seed(0);
+        (0..20).for_each(|_| {
+            let length: usize = rng.generate_range(20usize..120usize);
+            let mut sequence: Vec<Nucleotide> = (0..length)
+                .map(|_| Nucleotide::ALL[rng.generate_range(0usize..4usize)])
+                .collect();
+            // A designed helix closing the whole sequence
+            let mut partners: Vec<Option<usize>> = vec![None; length];
+            (0..5).for_each(|offset| {
+                *partners.get_mut(offset).unwrap() = Some(length - 1 - offset);
+                *partners.get_mut(length - 1 - offset).unwrap() = Some(offset);
+            });
+
+            let min_length: usize = rng.generate_range(2usize..5usize);
+            let mut matches: Vec<OffTargetMatch> =
+                find_off_targets(&sequence, &partners, min_length, false);
+            (0..10).for_each(|_| {
+                let changed: Vec<usize> = (0..rng.generate_range(1usize..4usize))
+                    .map(|_| rng.generate_range(0..length))
+                    .collect();
+                changed.iter().for_each(|&position| {
+                    *sequence.get_mut(position).unwrap() =
+                        Nucleotide::ALL[rng.generate_range(0usize..4usize)];
+                });
+                rescan_off_targets(
+                    &sequence,
+                    &partners,
+                    min_length,
+                    false,
+                    &mut matches,
+                    &changed,
+                );
+                assert_eq!(
+                    matches,
+                    find_off_targets(&sequence, &partners, min_length, false)
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn off_target_reports_skip_intended_pairs() {
+        // A complementary stretch with one G·U pair, and a designed helix, in an A background
+        let mut sequence: Vec<Nucleotide> = vec![Nucleotide::A; 50];
+        [(2, "CUGGAG"), (12, "GCAC"), (26, "UUCCAG"), (40, "GUGC")]
+            .into_iter()
+            .for_each(|(start, stretch)| {
+                let stretch: RnaSequence = stretch.parse().unwrap();
+                sequence[start..start + stretch.len()].copy_from_slice(&stretch);
+            });
+        let mut partners: Vec<Option<usize>> = vec![None; 50];
+        (0..4).for_each(|offset| {
+            *partners.get_mut(12 + offset).unwrap() = Some(43 - offset);
+            *partners.get_mut(43 - offset).unwrap() = Some(12 + offset);
+        });
+        let provenance: Vec<NucleotideRecord> = (0..50)
+            .map(|index| NucleotideRecord {
+                base: *sequence.get(index).unwrap(),
+                path_index: 0,
+                source: match index {
+                    0..5 => Source::Node(1),
+                    5..20 => Source::Edge((0, 1)),
+                    20..30 => Source::Node(2),
+                    _ => Source::Edge((1, 2)),
+                },
+                role: Role::Sense,
+                partner: *partners.get(index).unwrap(),
+            })
+            .collect();
+
+        let reports: Vec<OffTargetReport> =
+            report_off_targets(&sequence, &partners, 4, Some(&provenance));
+        assert_eq!(reports.len(), 1);
+        let report: &OffTargetReport = reports.first().unwrap();
+        assert_eq!(
+            report.off_target,
+            OffTargetMatch {
+                first: 2,
+                second: 26,
+                length: 6
+            }
+        );
+        assert_eq!(report.wobbles, 1);
+        assert_eq!(
+            report.first_sources,
+            vec![Source::Node(1), Source::Edge((0, 1))]
+        );
+        assert_eq!(
+            report.second_sources,
+            vec![Source::Node(2), Source::Edge((1, 2))]
+        );
+
+        // Without provenance there are no sources, and without the designed pairs the helix is reported
+        let reports: Vec<OffTargetReport> = report_off_targets(&sequence, &vec![None; 50], 4, None);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports.first().unwrap().off_target.length, 6);
+        assert!(reports.first().unwrap().first_sources.is_empty());
+        assert_eq!(
+            reports.get(1).unwrap().off_target,
+            OffTargetMatch {
+                first: 12,
+                second: 40,
+                length: 4
+            }
+        );
+        assert_eq!(reports.get(1).unwrap().wobbles, 0);
+    }
+}
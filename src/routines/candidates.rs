@@ -0,0 +1,207 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Display, Formatter},
+    thread,
+};
+
+use glam::Vec3A;
+
+use super::{
+    analysis::{find_off_targets, longest_off_target},
+    ensemble::design_defect,
+    graph::Tree,
+    nucleotide::RnaSequence,
+    sequencer::{GeneratedSequence, SequencerError, SequencerSettings, generate_sequence},
+    thermodynamics::{StabilitySettings, helix_stabilities},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchSettings {
+    pub count: usize,             // Number of candidates to generate
+    pub first_seed: u64,          // Candidates use consecutive seeds starting from this one
+    pub threads: usize,           // 0 uses every available core
+    pub off_target_length: usize, // Shortest unintended stretch counted as an off-target
+    pub ensemble_defect: bool, // Compute the ensemble defect of each candidate, by far the slowest check
+    pub stability: StabilitySettings,
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        BatchSettings {
+            count: 16,
+            first_seed: 0,
+            threads: 0,
+            off_target_length: 6,
+            ensemble_defect: true,
+            stability: StabilitySettings::default(),
+        }
+    }
+}
+
+// Lower is better, fields are compared in order of importance
+#[derive(Debug, Clone, Copy)]
+pub struct CandidateScore {
+    pub violations: usize,         // Unsatisfied sequence constraints
+    pub weak_helices: usize,       // Helices too unstable to form
+    pub ensemble_defect: f32, // Expected nucleotides out of their target state, 0 if not computed
+    pub longest_off_target: usize, // Longest unintended stretch, G·U pairs included
+    pub off_targets: usize,   // Unintended stretches of at least the batch length
+}
+
+impl Ord for CandidateScore {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.violations
+            .cmp(&other.violations)
+            .then(self.weak_helices.cmp(&other.weak_helices))
+            .then(self.ensemble_defect.total_cmp(&other.ensemble_defect))
+            .then(self.longest_off_target.cmp(&other.longest_off_target))
+            .then(self.off_targets.cmp(&other.off_targets))
+    }
+}
+
+impl PartialOrd for CandidateScore {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for CandidateScore {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CandidateScore {}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub seed: u64,
+    pub score: CandidateScore,
+    pub sequence: GeneratedSequence,
+}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "seed {}: {} violations, {} weak helices, ensemble defect {:.1} nt, longest off-target {} nt, {} off-targets",
+            self.seed,
+            self.score.violations,
+            self.score.weak_helices,
+            self.score.ensemble_defect,
+            self.score.longest_off_target,
+            self.score.off_targets
+        )
+    }
+}
+
+// Generate one candidate per seed in parallel, best candidates first
+pub fn generate_candidates(
+    rna_path: &[usize],
+    tree: &Tree,
+    node_coordinates: &[Vec3A],
+    settings: &SequencerSettings,
+    batch: &BatchSettings,
+) -> Result<Vec<Candidate>, SequencerError> {
+    let seeds: Vec<u64> = (0..batch.count as u64)
+        .map(|index| batch.first_seed.wrapping_add(index))
+        .collect();
+    let threads: usize = match batch.threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+
+    // Each thread generates a contiguous chunk of seeds
+    let mut candidates: Vec<Candidate> = thread::scope(|scope| {
+        seeds
+            .chunks(seeds.len().div_ceil(threads).max(1))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&seed| {
+                            generate_candidate(
+                                rna_path,
+                                tree,
+                                node_coordinates,
+                                settings,
+                                batch,
+                                seed,
+                            )
+                        })
+                        .collect::<Vec<Result<Candidate, SequencerError>>>()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Result<Vec<Candidate>, SequencerError>>()
+    })?;
+
+    candidates.sort_by_key(|candidate| (candidate.score, candidate.seed));
+    Ok(candidates)
+}
+
+fn generate_candidate(
+    rna_path: &[usize],
+    tree: &Tree,
+    node_coordinates: &[Vec3A],
+    settings: &SequencerSettings,
+    batch: &BatchSettings,
+    seed: u64,
+) -> Result<Candidate, SequencerError> {
+    let mut settings: SequencerSettings = settings.clone();
+    settings.seed = Some(seed);
+    let sequence: GeneratedSequence =
+        generate_sequence(rna_path, tree, node_coordinates, &settings)?;
+
+    Ok(Candidate {
+        seed,
+        score: score_candidate(&sequence, batch),
+        sequence,
+    })
+}
+
+pub fn score_candidate(sequence: &GeneratedSequence, batch: &BatchSettings) -> CandidateScore {
+    let bases: RnaSequence = sequence.sequence();
+    let partners: Vec<Option<usize>> = sequence.partners();
+    CandidateScore {
+        violations: sequence.violations.len(),
+        weak_helices: helix_stabilities(sequence, &batch.stability)
+            .iter()
+            .filter(|helix| helix.weak)
+            .count(),
+        ensemble_defect: if batch.ensemble_defect {
+            design_defect(sequence).defect
+        } else {
+            0.0
+        },
+        longest_off_target: longest_off_target(&bases, &partners, true)
+            .map_or(0, |off_target| off_target.length),
+        off_targets: find_off_targets(&bases, &partners, batch.off_target_length, true).len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_rank_the_ensemble_defect_before_off_targets() {
+        let score = |ensemble_defect: f32, off_targets: usize| CandidateScore {
+            violations: 0,
+            weak_helices: 0,
+            ensemble_defect,
+            longest_off_target: 8,
+            off_targets,
+        };
+        assert!(score(2.5, 40) < score(3.0, 10));
+        assert!(score(3.0, 10) < score(3.0, 11));
+        assert_eq!(score(3.0, 10), score(3.0, 10));
+        let weak: CandidateScore = CandidateScore {
+            weak_helices: 1,
+            ..score(0.0, 0)
+        };
+        assert!(score(50.0, 100) < weak);
+    }
+}
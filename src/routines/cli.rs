@@ -0,0 +1,644 @@
+use glam::Vec3A;
+
+use super::{
+    candidates::{BatchSettings, Candidate, generate_candidates},
+    constraints::GcWindow,
+    geometry::ScaleTarget,
+    graph::{Tree, construct_tree, find_rna_path},
+    graph_file::{import_graph, is_graph_file},
+    io::{
+        EdgeRow, ImportSettings, OxrnaFiles, PdbAtoms, StructureFormat, Wireframe, edge_modules,
+        edge_pins, format_edge_table, format_fasta, format_genbank, format_oxrna, format_pdb,
+        format_structure, format_vertex_table, import_mesh, is_mesh_file, parse_edges,
+        parse_node_coordinates, parse_structure,
+    },
+    model::{NucleotideFrame, build_trace},
+    motifs::module_library,
+    nucleotide::RnaSequence,
+    primers::{PrimerPair, PrimerSettings, design_primers},
+    sequencer::{GeneratedSequence, SequencerSettings, generate_sequence},
+    structure::{StructureComparison, TargetStructure},
+    template::{DnaTemplate, Promoter, TemplateSettings, build_template},
+};
+
+const USAGE: &str = "Usage:
+  rrna-cad candidates <structure> [--count N] [--seed N] [--threads N] [--off-target-length N] [--no-ensemble-defect]
+  rrna-cad template <structure> [--seed N] [--promoter t7|sp6|none] [--hammerhead] [--hdv] [--terminator] [--format fasta|genbank]
+  rrna-cad primers <structure> [--seed N] [--promoter t7|sp6|none] [--hammerhead] [--hdv] [--terminator] [--annealing-temperature °C]
+  rrna-cad structure <structure> [--seed N] [--format ct|bpseq|dbn]
+  rrna-cad model <structure> [--seed N] [--atoms single|coarse]
+  rrna-cad oxrna <structure> --output PREFIX [--seed N]
+  rrna-cad compare <target structure file> <predicted structure file>
+  rrna-cad convert <structure> --vertices <CSV or TSV file> --edges <CSV or TSV file>
+The structure is a vertices file and an edges file, an OBJ or PLY mesh, or a GraphML or node-link
+JSON graph, and takes the options
+  [--recentre] [--bounding-box SIZE | --mean-edge-length LENGTH | --sequence-length N]
+Every subcommand generating a sequence takes the options
+  [--gc MIN,MAX] [--gc-window SIZE,MIN,MAX|none] [--max-homopolymer N] [--forbidden MOTIF,...|none]
+  [--wobble-fraction F] [--max-wobbles-per-helix N|none] [--max-consecutive-wobbles N]
+  [--wobble-junction-distance N] [--wobble-kissing-loop-distance N]
+  [--max-match N] [--symmetry-iterations N]";
+
+const TEMPLATE_FLAGS: [&str; 3] = ["--hammerhead", "--hdv", "--terminator"];
+const IMPORT_FLAGS: [&str; 1] = ["--recentre"];
+
+// Run a command line subcommand, returns false if the arguments are not a command (the GUI is started instead)
+pub fn run_command(args: &[String]) -> bool {
+    let run: fn(&[String]) -> Result<(), String> = match args.first().map(String::as_str) {
+        Some("candidates") => run_candidates,
+        Some("template") => run_template,
+        Some("primers") => run_primers,
+        Some("structure") => run_structure,
+        Some("model") => run_model,
+        Some("oxrna") => run_oxrna,
+        Some("compare") => run_compare,
+        Some("convert") => run_convert,
+        _ => return false,
+    };
+    #[cfg(windows)]
+    attach_console();
+    if let Err(error) = run(args.get(1..).unwrap()) {
+        eprintln!("Error: {error}\n{USAGE}");
+        std::process::exit(1);
+    }
+    true
+}
+
+// Windows builds are GUI programs without a console of their own
+// Subcommands print to the console they were started from, if any
+#[cfg(windows)]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Without a parent console (e.g. started from the file explorer) there is nothing to print to
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+// Structure read from vertex and edge files, with its tree and RNA path
+struct Structure {
+    node_coordinates: Vec<Vec3A>,
+    tree: Tree,
+    path: Vec<usize>,
+    edge_rows: Vec<EdgeRow>, // Edges as read, with their design options
+}
+
+impl Structure {
+    // Pins and module insertions given with the edges
+    fn apply_edge_options(&self, settings: &mut SequencerSettings) -> Result<(), String> {
+        settings.pins.extend(edge_pins(&self.edge_rows));
+        settings.modules.extend(
+            edge_modules(&self.edge_rows, &module_library())
+                .map_err(|_| "Unknown module in the edge options".to_string())?,
+        );
+        Ok(())
+    }
+}
+
+// Number of input files before the options, a mesh or graph being a single file
+fn input_count(args: &[String]) -> usize {
+    if args
+        .first()
+        .is_some_and(|path| is_mesh_file(path) || is_graph_file(path))
+    {
+        1
+    } else {
+        2
+    }
+}
+
+// Wireframe of a mesh, a graph, or vertex and edge files, with the edges in the order the tree is grown
+fn read_wireframe(
+    args: &[String],
+    settings: &ImportSettings,
+) -> Result<(Wireframe, Vec<EdgeRow>), String> {
+    let read = |path: &String| {
+        std::fs::read_to_string(path).map_err(|error| format!("Cannot read {path}: {error}"))
+    };
+
+    match args {
+        [mesh_path, ..] if is_mesh_file(mesh_path) => {
+            let wireframe: Wireframe = import_mesh(&read(mesh_path)?, settings)
+                .map_err(|error| format!("{mesh_path}: {error}"))?;
+            let edge_rows: Vec<EdgeRow> = wireframe
+                .edges
+                .iter()
+                .map(|&edge| EdgeRow {
+                    edge,
+                    options: Vec::new(),
+                })
+                .collect();
+            Ok((wireframe, edge_rows))
+        }
+        [graph_path, ..] if is_graph_file(graph_path) => import_graph(&read(graph_path)?, settings)
+            .map_err(|error| format!("{graph_path}: {error}")),
+        [vertices_path, edges_path, ..] => {
+            let node_coordinates: Vec<Vec3A> = parse_node_coordinates(&read(vertices_path)?)
+                .map_err(|error| format!("{vertices_path}: {error}"))?;
+            let mut edge_rows: Vec<EdgeRow> =
+                parse_edges(&read(edges_path)?, node_coordinates.len())
+                    .map_err(|error| format!("{edges_path}: {error}"))?;
+            if edge_rows.is_empty() {
+                return Err(format!("{edges_path}: No edges found"));
+            }
+
+            let mut wireframe: Wireframe = Wireframe::from_rows(node_coordinates, &mut edge_rows)
+                .ok_or_else(|| {
+                format!("{edges_path}: The edges are in several disconnected parts")
+            })?;
+            wireframe
+                .adjust(settings)
+                .map_err(|error| format!("{edges_path}: {error}"))?;
+            Ok((wireframe, edge_rows))
+        }
+        _ => Err("Missing input files".to_string()),
+    }
+}
+
+fn read_structure(args: &[String], settings: &ImportSettings) -> Result<Structure, String> {
+    let (wireframe, edge_rows) = read_wireframe(args, settings)?;
+    let mut tree: Tree = construct_tree(&wireframe.edges);
+    let path: Vec<usize> = find_rna_path(&mut tree, &wireframe.vertices);
+    Ok(Structure {
+        node_coordinates: wireframe.vertices,
+        tree,
+        path,
+        edge_rows,
+    })
+}
+
+// Options following the input files, as (option, value) pairs
+// Flags take no value, so they are returned with an empty one
+fn parse_options<'a>(
+    args: &'a [String],
+    flags: &[&str],
+) -> Result<Vec<(&'a str, &'a str)>, String> {
+    let mut options = args.get(input_count(args)..).unwrap_or_default().iter();
+    let mut parsed: Vec<(&str, &str)> = Vec::new();
+    while let Some(option) = options.next() {
+        if flags.contains(&option.as_str()) || IMPORT_FLAGS.contains(&option.as_str()) {
+            parsed.push((option, ""));
+        } else {
+            let value: &String = options
+                .next()
+                .ok_or(format!("Missing value for {option}"))?;
+            parsed.push((option, value));
+        }
+    }
+    Ok(parsed)
+}
+
+fn invalid_value(option: &str, value: &str) -> String {
+    format!("Invalid value for {option}: {value}")
+}
+
+// Options of the structure input, shared by every subcommand reading one
+fn apply_import_option(
+    option: &str,
+    value: &str,
+    settings: &mut ImportSettings,
+) -> Result<(), String> {
+    let invalid = || invalid_value(option, value);
+    match option {
+        "--recentre" => settings.recentre = true,
+        "--bounding-box" => {
+            settings.scale = Some(ScaleTarget::BoundingBox(
+                value.parse().map_err(|_| invalid())?,
+            ))
+        }
+        "--mean-edge-length" => {
+            settings.scale = Some(ScaleTarget::MeanEdgeLength(
+                value.parse().map_err(|_| invalid())?,
+            ))
+        }
+        "--sequence-length" => {
+            settings.scale = Some(ScaleTarget::SequenceLength(
+                value.parse().map_err(|_| invalid())?,
+            ))
+        }
+        _ => return Err(format!("Unknown option {option}")),
+    }
+    Ok(())
+}
+
+// Sequence constraint, wobble and symmetry options, shared by every subcommand generating a sequence
+fn apply_sequencer_option(
+    option: &str,
+    value: &str,
+    settings: &mut SequencerSettings,
+    import_settings: &mut ImportSettings,
+) -> Result<(), String> {
+    let invalid = || invalid_value(option, value);
+    let fraction = |value: &str| {
+        value
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|fraction| (0.0..=1.0).contains(fraction))
+            .ok_or_else(invalid)
+    };
+    let count = |value: &str| value.trim().parse::<usize>().map_err(|_| invalid());
+    let values: Vec<&str> = value.split(',').collect();
+    match option {
+        "--gc" => {
+            let [min, max] = values.as_slice() else {
+                return Err(invalid());
+            };
+            let (min, max): (f32, f32) = (fraction(min)?, fraction(max)?);
+            if min > max {
+                return Err(invalid());
+            }
+            settings.constraints.gc_range = (min, max);
+        }
+        "--gc-window" => {
+            settings.constraints.window = match values.as_slice() {
+                ["none"] => None,
+                [size, min, max] => {
+                    let window: GcWindow = GcWindow {
+                        size: count(size)?,
+                        min: fraction(min)?,
+                        max: fraction(max)?,
+                    };
+                    if window.size == 0 || window.min > window.max {
+                        return Err(invalid());
+                    }
+                    Some(window)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        "--max-homopolymer" => settings.constraints.max_homopolymer = count(value)?,
+        "--forbidden" => {
+            settings.constraints.forbidden = match value {
+                "none" => Vec::new(),
+                _ => values
+                    .iter()
+                    .map(|motif| motif.parse::<RnaSequence>())
+                    .collect::<Result<Vec<RnaSequence>, _>>()
+                    .map_err(|_| invalid())?,
+            }
+        }
+        "--wobble-fraction" => settings.wobble.fraction = fraction(value)?,
+        "--max-wobbles-per-helix" => {
+            settings.wobble.max_per_helix = match value {
+                "none" => None,
+                _ => Some(count(value)?),
+            }
+        }
+        "--max-consecutive-wobbles" => settings.wobble.max_consecutive = count(value)?,
+        "--wobble-junction-distance" => settings.wobble.junction_exclusion = count(value)?,
+        "--wobble-kissing-loop-distance" => settings.wobble.kissing_loop_exclusion = count(value)?,
+        "--max-match" => settings.symmetry.max_match = count(value)?,
+        "--symmetry-iterations" => settings.symmetry.max_iterations = count(value)?,
+        _ => apply_import_option(option, value, import_settings)?,
+    }
+    Ok(())
+}
+
+// Options shared by the subcommands building a transcription template
+fn apply_template_option(
+    option: &str,
+    value: &str,
+    settings: &mut SequencerSettings,
+    template_settings: &mut TemplateSettings,
+    import_settings: &mut ImportSettings,
+) -> Result<(), String> {
+    match (option, value) {
+        ("--seed", _) => {
+            settings.seed = Some(value.parse().map_err(|_| invalid_value(option, value))?)
+        }
+        ("--promoter", "t7") => template_settings.promoter = Some(Promoter::T7),
+        ("--promoter", "sp6") => template_settings.promoter = Some(Promoter::Sp6),
+        ("--promoter", "none") => template_settings.promoter = None,
+        ("--promoter", _) => return Err(invalid_value(option, value)),
+        ("--hammerhead", _) => template_settings.hammerhead = true,
+        ("--hdv", _) => template_settings.hdv = true,
+        ("--terminator", _) => template_settings.terminator = true,
+        _ => apply_sequencer_option(option, value, settings, import_settings)?,
+    }
+    Ok(())
+}
+
+// Generate ranked candidate sequences for the structure in the given files
+fn run_candidates(args: &[String]) -> Result<(), String> {
+    let mut batch: BatchSettings = BatchSettings::default();
+    let mut settings: SequencerSettings = SequencerSettings::default();
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    parse_options(args, &["--no-ensemble-defect"])?
+        .into_iter()
+        .try_for_each(|(option, value)| -> Result<(), String> {
+            let invalid = |_| invalid_value(option, value);
+            match option {
+                "--count" => batch.count = value.parse().map_err(invalid)?,
+                "--seed" => batch.first_seed = value.parse().map_err(invalid)?,
+                "--threads" => batch.threads = value.parse().map_err(invalid)?,
+                "--off-target-length" => {
+                    batch.off_target_length = value.parse().map_err(invalid)?
+                }
+                "--no-ensemble-defect" => batch.ensemble_defect = false,
+                _ => apply_sequencer_option(option, value, &mut settings, &mut import_settings)?,
+            }
+            Ok(())
+        })?;
+
+    let structure: Structure = read_structure(args, &import_settings)?;
+    structure.apply_edge_options(&mut settings)?;
+    let candidates: Vec<Candidate> = generate_candidates(
+        &structure.path,
+        &structure.tree,
+        &structure.node_coordinates,
+        &settings,
+        &batch,
+    )
+    .map_err(|error| error.to_string())?;
+
+    candidates.iter().enumerate().for_each(|(rank, candidate)| {
+        println!("#{} {candidate}\n{}", rank + 1, candidate.sequence)
+    });
+    Ok(())
+}
+
+// Generate a sequence for the structure in the given files, and print its transcription template
+fn run_template(args: &[String]) -> Result<(), String> {
+    let mut settings: SequencerSettings = SequencerSettings::default();
+    let mut template_settings: TemplateSettings = TemplateSettings::default();
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    let mut genbank: bool = false;
+    parse_options(args, &TEMPLATE_FLAGS)?
+        .into_iter()
+        .try_for_each(|(option, value)| {
+            match (option, value) {
+                ("--format", "fasta") => genbank = false,
+                ("--format", "genbank") => genbank = true,
+                ("--format", _) => return Err(invalid_value(option, value)),
+                _ => apply_template_option(
+                    option,
+                    value,
+                    &mut settings,
+                    &mut template_settings,
+                    &mut import_settings,
+                )?,
+            }
+            Ok(())
+        })?;
+
+    let structure: Structure = read_structure(args, &import_settings)?;
+    structure.apply_edge_options(&mut settings)?;
+    let sequence: GeneratedSequence = generate_sequence(
+        &structure.path,
+        &structure.tree,
+        &structure.node_coordinates,
+        &settings,
+    )
+    .map_err(|error| error.to_string())?;
+    let template: DnaTemplate = build_template(&sequence.sequence(), &template_settings);
+
+    if genbank {
+        print!("{}", format_genbank("rrna_design", &template));
+    } else {
+        print!(
+            "{}",
+            format_fasta(&[
+                ("rrna_design template", &template.sequence),
+                (
+                    "rrna_design template reverse complement",
+                    &template.reverse_complement()
+                ),
+            ])
+        );
+    }
+    Ok(())
+}
+
+// Generate a sequence for the structure in the given files, and print PCR primers amplifying its template
+fn run_primers(args: &[String]) -> Result<(), String> {
+    let mut settings: SequencerSettings = SequencerSettings::default();
+    let mut template_settings: TemplateSettings = TemplateSettings::default();
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    let mut primer_settings: PrimerSettings = PrimerSettings::default();
+    parse_options(args, &TEMPLATE_FLAGS)?
+        .into_iter()
+        .try_for_each(|(option, value)| match option {
+            "--annealing-temperature" => {
+                primer_settings.conditions.temperature =
+                    value.parse().map_err(|_| invalid_value(option, value))?;
+                Ok(())
+            }
+            _ => apply_template_option(
+                option,
+                value,
+                &mut settings,
+                &mut template_settings,
+                &mut import_settings,
+            ),
+        })?;
+
+    let structure: Structure = read_structure(args, &import_settings)?;
+    structure.apply_edge_options(&mut settings)?;
+    let sequence: GeneratedSequence = generate_sequence(
+        &structure.path,
+        &structure.tree,
+        &structure.node_coordinates,
+        &settings,
+    )
+    .map_err(|error| error.to_string())?;
+    let primers: PrimerPair = design_primers(&sequence, &template_settings, &primer_settings);
+    println!("{primers}");
+    Ok(())
+}
+
+// Generate a sequence for the structure in the given files, and print its secondary structure file
+fn run_structure(args: &[String]) -> Result<(), String> {
+    let mut settings: SequencerSettings = SequencerSettings::default();
+    let mut format: StructureFormat = StructureFormat::Ct;
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    parse_options(args, &[])?
+        .into_iter()
+        .try_for_each(|(option, value)| {
+            match (option, value) {
+                ("--seed", _) => {
+                    settings.seed = Some(value.parse().map_err(|_| invalid_value(option, value))?)
+                }
+                ("--format", "ct") => format = StructureFormat::Ct,
+                ("--format", "bpseq") => format = StructureFormat::Bpseq,
+                ("--format", "dbn") => format = StructureFormat::Dbn,
+                ("--format", _) => return Err(invalid_value(option, value)),
+                _ => apply_sequencer_option(option, value, &mut settings, &mut import_settings)?,
+            }
+            Ok(())
+        })?;
+
+    let structure: Structure = read_structure(args, &import_settings)?;
+    structure.apply_edge_options(&mut settings)?;
+    let sequence: GeneratedSequence = generate_sequence(
+        &structure.path,
+        &structure.tree,
+        &structure.node_coordinates,
+        &settings,
+    )
+    .map_err(|error| error.to_string())?;
+    print!(
+        "{}",
+        format_structure(
+            "rrna_design",
+            &TargetStructure::from_generated(&sequence),
+            format
+        )
+    );
+    Ok(())
+}
+
+// Coarse-grained PDB model of a generated sequence
+fn run_model(args: &[String]) -> Result<(), String> {
+    let mut settings: SequencerSettings = SequencerSettings::default();
+    let mut atoms: PdbAtoms = PdbAtoms::Coarse;
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    parse_options(args, &[])?
+        .into_iter()
+        .try_for_each(|(option, value)| {
+            match (option, value) {
+                ("--seed", _) => {
+                    settings.seed = Some(value.parse().map_err(|_| invalid_value(option, value))?)
+                }
+                ("--atoms", "single") => atoms = PdbAtoms::Single,
+                ("--atoms", "coarse") => atoms = PdbAtoms::Coarse,
+                ("--atoms", _) => return Err(invalid_value(option, value)),
+                _ => apply_sequencer_option(option, value, &mut settings, &mut import_settings)?,
+            }
+            Ok(())
+        })?;
+
+    let structure: Structure = read_structure(args, &import_settings)?;
+    structure.apply_edge_options(&mut settings)?;
+    let sequence: GeneratedSequence = generate_sequence(
+        &structure.path,
+        &structure.tree,
+        &structure.node_coordinates,
+        &settings,
+    )
+    .map_err(|error| error.to_string())?;
+    let trace: Vec<NucleotideFrame> =
+        build_trace(&sequence, &structure.tree, &structure.node_coordinates);
+    print!(
+        "{}",
+        format_pdb("rrna_design", &[(&sequence.sequence(), &trace)], atoms)
+    );
+    Ok(())
+}
+
+// oxRNA topology, configuration and mutual traps, written to <prefix>.top, <prefix>.dat and <prefix>_traps.txt
+fn run_oxrna(args: &[String]) -> Result<(), String> {
+    let mut settings: SequencerSettings = SequencerSettings::default();
+    let mut prefix: Option<&str> = None;
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    parse_options(args, &[])?.into_iter().try_for_each(
+        |(option, value)| -> Result<(), String> {
+            match option {
+                "--seed" => {
+                    settings.seed = Some(value.parse().map_err(|_| invalid_value(option, value))?)
+                }
+                "--output" => prefix = Some(value),
+                _ => apply_sequencer_option(option, value, &mut settings, &mut import_settings)?,
+            }
+            Ok(())
+        },
+    )?;
+    let Some(prefix) = prefix else {
+        return Err("Missing output prefix".to_string());
+    };
+
+    let structure: Structure = read_structure(args, &import_settings)?;
+    structure.apply_edge_options(&mut settings)?;
+    let sequence: GeneratedSequence = generate_sequence(
+        &structure.path,
+        &structure.tree,
+        &structure.node_coordinates,
+        &settings,
+    )
+    .map_err(|error| error.to_string())?;
+    let trace: Vec<NucleotideFrame> =
+        build_trace(&sequence, &structure.tree, &structure.node_coordinates);
+    let pairs: Vec<(usize, usize)> = TargetStructure::from_generated(&sequence)
+        .pairs
+        .iter()
+        .map(|pair| (pair.first, pair.second))
+        .collect();
+    let files: OxrnaFiles = format_oxrna(&[(&sequence.sequence(), &trace)], &pairs);
+
+    let write = |path: String, contents: &str| {
+        std::fs::write(&path, contents).map_err(|error| format!("Cannot write {path}: {error}"))
+    };
+    write(format!("{prefix}.top"), &files.topology)?;
+    write(format!("{prefix}.dat"), &files.configuration)?;
+    write(format!("{prefix}_traps.txt"), &files.traps)
+}
+
+// Compare a predicted structure file, such as from an external folding tool, with a target one
+// Both are CT, BPSEQ or dot-bracket files, the format being detected from their contents
+fn run_compare(args: &[String]) -> Result<(), String> {
+    let [target_path, predicted_path] = args else {
+        return Err("Expected a target and a predicted structure file".to_string());
+    };
+    let read = |path: &String| {
+        let input: String = std::fs::read_to_string(path)
+            .map_err(|error| format!("Cannot read {path}: {error}"))?;
+        parse_structure(&input).map_err(|error| format!("{path}: {error}"))
+    };
+
+    let target: TargetStructure = read(target_path)?;
+    let predicted: TargetStructure = read(predicted_path)?;
+    let comparison: StructureComparison = target.compare(&predicted).ok_or(format!(
+        "The target has {} nucleotides, the prediction {}",
+        target.len(),
+        predicted.len()
+    ))?;
+    println!("{comparison}");
+    Ok(())
+}
+
+// Write the structure as vertex and edge tables, tab separated for .tsv files and comma separated otherwise
+fn run_convert(args: &[String]) -> Result<(), String> {
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    let mut vertices_path: Option<&str> = None;
+    let mut edges_path: Option<&str> = None;
+    parse_options(args, &[])?
+        .into_iter()
+        .try_for_each(|(option, value)| match option {
+            "--vertices" => {
+                vertices_path = Some(value);
+                Ok(())
+            }
+            "--edges" => {
+                edges_path = Some(value);
+                Ok(())
+            }
+            _ => apply_import_option(option, value, &mut import_settings),
+        })?;
+    let (Some(vertices_path), Some(edges_path)) = (vertices_path, edges_path) else {
+        return Err("Missing output files".to_string());
+    };
+
+    let (wireframe, edge_rows) = read_wireframe(args, &import_settings)?;
+    let separator = |path: &str| {
+        if path.to_ascii_lowercase().ends_with(".tsv") {
+            '\t'
+        } else {
+            ','
+        }
+    };
+    let write = |path: &str, contents: String| {
+        std::fs::write(path, contents).map_err(|error| format!("Cannot write {path}: {error}"))
+    };
+    write(
+        vertices_path,
+        format_vertex_table(&wireframe.vertices, separator(vertices_path)),
+    )?;
+    write(
+        edges_path,
+        format_edge_table(&edge_rows, separator(edges_path)),
+    )
+}
@@ -0,0 +1,364 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use super::nucleotide::{IupacCode, Nucleotide, RnaSequence, gc_content};
+
+#[derive(Debug, Clone)]
+pub struct SequenceConstraints {
+    pub gc_range: (f32, f32),        // Allowed GC content of the whole sequence
+    pub window: Option<GcWindow>,    // Allowed GC content of every window of the sequence
+    pub max_homopolymer: usize,      // Longest allowed run of a single nucleotide
+    pub forbidden: Vec<RnaSequence>, // Subsequences that must not appear (terminators, restriction sites...)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GcWindow {
+    pub size: usize,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for SequenceConstraints {
+    fn default() -> Self {
+        SequenceConstraints {
+            gc_range: (0.35, 0.65),
+            window: Some(GcWindow {
+                size: 20,
+                min: 0.2,
+                max: 0.8,
+            }),
+            max_homopolymer: 4,
+            // UUUU acts as a T7 terminator
+            forbidden: vec![RnaSequence::from(vec![Nucleotide::U; 4])],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PinTarget {
+    Edge((usize, usize)), // Strand travelling from origin to destination, either sense or antisense
+    Node(usize),          // Motif of a node, applied every time the path passes through it
+}
+
+impl Display for PinTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PinTarget::Edge((origin, destination)) => write!(f, "edge {origin} {destination}"),
+            PinTarget::Node(node) => write!(f, "node {node}"),
+        }
+    }
+}
+
+// User-locked (partial) sequence, starting at an offset within the target strand or motif
+#[derive(Debug, Clone)]
+pub struct SequencePin {
+    pub target: PinTarget,
+    pub offset: usize,
+    pub pattern: Vec<IupacCode>,
+}
+
+impl Display for SequencePin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ", self.target, self.offset)?;
+        self.pattern.iter().try_for_each(|code| write!(f, "{code}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPinError;
+
+impl FromStr for SequencePin {
+    type Err = InvalidPinError;
+
+    // Pins are written as `edge <origin> <destination> <offset> <pattern>` or `node <id> <offset> <pattern>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let parse_index = |word: &str| word.parse::<usize>().map_err(|_| InvalidPinError);
+        let (target, offset, pattern): (PinTarget, usize, &str) = match words.as_slice() {
+            ["edge", origin, destination, offset, pattern] => (
+                PinTarget::Edge((parse_index(origin)?, parse_index(destination)?)),
+                parse_index(offset)?,
+                pattern,
+            ),
+            ["node", node, offset, pattern] => (
+                PinTarget::Node(parse_index(node)?),
+                parse_index(offset)?,
+                pattern,
+            ),
+            _ => return Err(InvalidPinError),
+        };
+
+        Ok(SequencePin {
+            target,
+            offset,
+            pattern: pattern
+                .chars()
+                .map(IupacCode::try_from)
+                .collect::<Result<Vec<IupacCode>, _>>()
+                .map_err(|_| InvalidPinError)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstraintViolation {
+    GcContent {
+        gc_content: f32,
+    },
+    WindowGcContent {
+        start: usize, // First window out of range, following windows out of range are merged into it
+        end: usize,
+        gc_content: f32,
+    },
+    Homopolymer {
+        start: usize,
+        length: usize,
+        nucleotide: Nucleotide,
+    },
+    Forbidden {
+        start: usize,
+        motif: RnaSequence,
+    },
+}
+
+impl Display for ConstraintViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintViolation::GcContent { gc_content } => {
+                write!(f, "GC content of {:.0}%", gc_content * 100.0)
+            }
+            ConstraintViolation::WindowGcContent {
+                start,
+                end,
+                gc_content,
+            } => write!(
+                f,
+                "GC content of {:.0}% in window {start}-{end}",
+                gc_content * 100.0
+            ),
+            ConstraintViolation::Homopolymer {
+                start,
+                length,
+                nucleotide,
+            } => write!(f, "{length} consecutive {nucleotide} at {start}"),
+            ConstraintViolation::Forbidden { start, motif } => {
+                write!(f, "Forbidden motif {motif} at {start}")
+            }
+        }
+    }
+}
+
+impl SequenceConstraints {
+    // Check whether the next nucleotide can be appended to a sequence without breaking the constraints
+    // Sliding windows are only rejected if they can no longer be completed within range
+    pub fn allows(&self, preceding: &[Nucleotide], next: Nucleotide) -> bool {
+        // Homopolymer ending with the new nucleotide
+        let run: usize = preceding
+            .iter()
+            .rev()
+            .take_while(|nucleotide| **nucleotide == next)
+            .count()
+            + 1;
+        if run > self.max_homopolymer {
+            return false;
+        }
+
+        // Forbidden motif ending with the new nucleotide
+        if self.forbidden.iter().any(|motif| {
+            !motif.is_empty()
+                && motif.last() == Some(&next)
+                && preceding.ends_with(&motif[..motif.len() - 1])
+        }) {
+            return false;
+        }
+
+        // Window ending with the new nucleotide
+        if let Some(window) = self.window
+            && window.size > 0
+        {
+            let filled: usize = window.size.min(preceding.len() + 1);
+            let gc_count: usize = preceding
+                .iter()
+                .rev()
+                .take(filled - 1)
+                .filter(|nucleotide| nucleotide.is_gc())
+                .count()
+                + usize::from(next.is_gc());
+            let remaining: usize = window.size - filled;
+            let size: f32 = window.size as f32;
+            if gc_count as f32 / size > window.max
+                || (gc_count + remaining) as f32 / size < window.min
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Check whether the next nucleotide can be appended to a sequence, followed by fixed nucleotides
+    // Violations within the fixed nucleotides alone are not held against the next nucleotide
+    pub fn allows_before(
+        &self,
+        preceding: &[Nucleotide],
+        next: Nucleotide,
+        following: &[Nucleotide],
+    ) -> bool {
+        if !self.allows(preceding, next) {
+            return false;
+        }
+
+        // Only the nucleotides within reach of a violation ending with or after the new one matter
+        let reach: usize = self.reach();
+        let mut context: Vec<Nucleotide> =
+            preceding[preceding.len().saturating_sub(reach)..].to_vec();
+        context.push(next);
+        following
+            .iter()
+            .take(reach)
+            .enumerate()
+            .all(|(index, &nucleotide)| {
+                let allowed: bool = self.allows(&context, nucleotide)
+                    || !self.allows(&following[..index], nucleotide);
+                context.push(nucleotide);
+                allowed
+            })
+    }
+
+    // Longest stretch of nucleotides a single violation can span, checks look no further back
+    pub fn reach(&self) -> usize {
+        self.max_homopolymer
+            + self
+                .forbidden
+                .iter()
+                .map(|motif| motif.len())
+                .max()
+                .unwrap_or(0)
+            + self.window.map_or(0, |window| window.size)
+    }
+
+    // Constraints that should also hold on the reverse complement of a strand
+    // Helix strands appear a second time as their complement, so motifs are forbidden in both directions
+    pub fn with_reverse_complements(&self) -> SequenceConstraints {
+        let mut constraints: SequenceConstraints = self.clone();
+        constraints.forbidden.extend(
+            self.forbidden
+                .iter()
+                .map(|motif| motif.reverse_complement())
+                .filter(|motif| !self.forbidden.contains(motif))
+                .collect::<Vec<RnaSequence>>(),
+        );
+        constraints
+    }
+}
+
+// List every constraint the sequence does not satisfy
+pub fn check_constraints(
+    sequence: &[Nucleotide],
+    constraints: &SequenceConstraints,
+) -> Vec<ConstraintViolation> {
+    let mut violations: Vec<ConstraintViolation> = Vec::new();
+
+    // Global GC content
+    let global_gc: f32 = gc_content(sequence);
+    if !sequence.is_empty()
+        && (global_gc < constraints.gc_range.0 || global_gc > constraints.gc_range.1)
+    {
+        violations.push(ConstraintViolation::GcContent {
+            gc_content: global_gc,
+        });
+    }
+
+    // Sliding windows, consecutive windows out of range are reported once
+    if let Some(window) = constraints.window
+        && window.size > 0
+    {
+        let mut current: Option<ConstraintViolation> = None;
+        sequence
+            .windows(window.size)
+            .enumerate()
+            .for_each(|(start, nucleotides)| {
+                let window_gc: f32 = gc_content(nucleotides);
+                if window_gc < window.min || window_gc > window.max {
+                    match current.as_mut() {
+                        Some(ConstraintViolation::WindowGcContent { end, .. }) => {
+                            *end = start + window.size
+                        }
+                        _ => {
+                            current = Some(ConstraintViolation::WindowGcContent {
+                                start,
+                                end: start + window.size,
+                                gc_content: window_gc,
+                            })
+                        }
+                    }
+                } else if let Some(violation) = current.take() {
+                    violations.push(violation);
+                }
+            });
+        violations.extend(current);
+    }
+
+    // Homopolymers
+    let mut start: usize = 0;
+    (1..=sequence.len()).for_each(|index| {
+        if index == sequence.len() || sequence[index] != sequence[start] {
+            let length: usize = index - start;
+            if length > constraints.max_homopolymer {
+                violations.push(ConstraintViolation::Homopolymer {
+                    start,
+                    length,
+                    nucleotide: sequence[start],
+                });
+            }
+            start = index;
+        }
+    });
+
+    // Forbidden motifs
+    constraints
+        .forbidden
+        .iter()
+        .filter(|motif| !motif.is_empty())
+        .for_each(|motif| {
+            sequence
+                .windows(motif.len())
+                .enumerate()
+                .filter(|(_, nucleotides)| *nucleotides == &motif[..])
+                .for_each(|(start, _)| {
+                    violations.push(ConstraintViolation::Forbidden {
+                        start,
+                        motif: motif.clone(),
+                    })
+                });
+        });
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_with_following_nucleotides_are_checked() {
+        let constraints: SequenceConstraints = SequenceConstraints::default();
+        let kink: [Nucleotide; 4] = [Nucleotide::A; 4];
+        let preceding: [Nucleotide; 2] = [Nucleotide::G, Nucleotide::C];
+        assert!(constraints.allows(&preceding, Nucleotide::A));
+        assert!(!constraints.allows_before(&preceding, Nucleotide::A, &kink));
+        assert!(constraints.allows_before(&preceding, Nucleotide::G, &kink));
+        // UUU followed by U gives the forbidden UUUU
+        assert!(!constraints.allows_before(&preceding, Nucleotide::U, &[Nucleotide::U; 3]));
+    }
+
+    #[test]
+    fn fixed_violations_are_not_held_against_the_next_nucleotide() {
+        let constraints: SequenceConstraints = SequenceConstraints::default();
+        let following: [Nucleotide; 5] = [Nucleotide::A; 5];
+        assert!(constraints.allows_before(&[Nucleotide::G], Nucleotide::C, &following));
+        assert!(!constraints.allows_before(&[Nucleotide::G], Nucleotide::A, &following));
+    }
+}
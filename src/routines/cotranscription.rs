@@ -0,0 +1,277 @@
+use std::fmt::{Display, Formatter};
+
+use super::{
+    analysis::{OffTargetMatch, find_off_targets},
+    nucleotide::RnaSequence,
+    sequencer::{GeneratedSequence, NucleotideRecord, Role, Source},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct FoldingSettings {
+    pub max_delay: usize, // Longest wait between the two halves of an element before it is flagged
+    pub trap_length: usize, // Shortest unintended stretch counted as a trap, G·U pairs included
+}
+
+impl Default for FoldingSettings {
+    fn default() -> Self {
+        FoldingSettings {
+            max_delay: 200,
+            trap_length: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FoldingElement {
+    Helix((usize, usize)),     // Tree edge
+    KissingLoop(usize, usize), // Nodes of the two loops, in transcription order
+}
+
+impl Display for FoldingElement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FoldingElement::Helix((parent, child)) => write!(f, "helix {parent}-{child}"),
+            FoldingElement::KissingLoop(first, second) => {
+                write!(f, "kissing loop {first}-{second}")
+            }
+        }
+    }
+}
+
+// When an element can form as the transcript emerges
+// Halves are inclusive index ranges, the first half is transcribed first
+#[derive(Debug, Clone)]
+pub struct FoldingStep {
+    pub element: FoldingElement,
+    pub first_half: (usize, usize),
+    pub second_half: (usize, usize),
+    pub formed_at: usize, // Transcript length at which both halves exist
+    pub delay: usize,     // Nucleotides transcribed between the two halves
+    pub long_range: bool, // Delay above the settings maximum
+    pub traps: Vec<OffTargetMatch>, // Unintended pairings of the first half able to form before the second half exists
+}
+
+impl Display for FoldingStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} forms at {} nt, {} nt after its first half",
+            self.element, self.formed_at, self.delay
+        )?;
+        if self.long_range {
+            write!(f, " (long range)")?;
+        }
+        if !self.traps.is_empty() {
+            write!(f, ", {} possible traps", self.traps.len())?;
+        }
+        Ok(())
+    }
+}
+
+// Structural elements of a generated sequence in the order they can form during transcription
+pub fn folding_order(
+    generated: &GeneratedSequence,
+    settings: &FoldingSettings,
+) -> Vec<FoldingStep> {
+    // Designed pairs grouped by element, each pair written from its earlier nucleotide
+    let mut elements: Vec<(FoldingElement, Vec<(usize, usize)>)> = Vec::new();
+    generated
+        .nucleotides
+        .iter()
+        .enumerate()
+        .for_each(|(index, nucleotide)| {
+            let Some(partner) = nucleotide.partner.filter(|partner| *partner > index) else {
+                return;
+            };
+            let other: &NucleotideRecord = generated.nucleotides.get(partner).unwrap();
+            let element: FoldingElement = match (nucleotide.role, nucleotide.source, other.source) {
+                (Role::Sense | Role::Antisense, Source::Edge(edge), _) => {
+                    FoldingElement::Helix(edge)
+                }
+                (Role::KissingLoop, Source::Node(first), Source::Node(second))
+                    if first != second =>
+                {
+                    FoldingElement::KissingLoop(first, second)
+                }
+                _ => return,
+            };
+            match elements.iter_mut().find(|(known, _)| *known == element) {
+                Some((_, pairs)) => pairs.push((index, partner)),
+                None => elements.push((element, vec![(index, partner)])),
+            }
+        });
+
+    let sequence: RnaSequence = generated.sequence();
+    let off_targets: Vec<OffTargetMatch> =
+        find_off_targets(&sequence, &generated.partners(), settings.trap_length, true);
+
+    let mut steps: Vec<FoldingStep> = elements
+        .into_iter()
+        .map(|(element, pairs)| {
+            let first_half: (usize, usize) = (
+                pairs.iter().map(|(first, _)| *first).min().unwrap(),
+                pairs.iter().map(|(first, _)| *first).max().unwrap(),
+            );
+            let second_half: (usize, usize) = (
+                pairs.iter().map(|(_, second)| *second).min().unwrap(),
+                pairs.iter().map(|(_, second)| *second).max().unwrap(),
+            );
+            let delay: usize = second_half.0.saturating_sub(first_half.1 + 1);
+
+            // Traps involve the first half, and are complete before the second half starts
+            let overlaps = |start: usize, length: usize| {
+                start <= first_half.1 && first_half.0 < start + length
+            };
+            let traps: Vec<OffTargetMatch> = off_targets
+                .iter()
+                .filter(|off_target| {
+                    (overlaps(off_target.first, off_target.length)
+                        || overlaps(off_target.second, off_target.length))
+                        && off_target.second + off_target.length <= second_half.0
+                })
+                .copied()
+                .collect();
+
+            FoldingStep {
+                element,
+                first_half,
+                second_half,
+                formed_at: second_half.1 + 1,
+                delay,
+                long_range: delay > settings.max_delay,
+                traps,
+            }
+        })
+        .collect();
+
+    steps.sort_by_key(|step| step.formed_at);
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two hairpins on node 0 whose loops kiss, the first loop also holding a copy of its antisense strand
+    fn transcript() -> GeneratedSequence {
+        let segments: [(&str, Source, Role); 6] = [
+            ("CUGA", Source::Edge((0, 1)), Role::Sense),
+            ("AAGGCAAUCAGA", Source::Node(1), Role::KissingLoop),
+            ("UCAG", Source::Edge((0, 1)), Role::Antisense),
+            ("GAGC", Source::Edge((0, 2)), Role::Sense),
+            ("AAUGCCAAA", Source::Node(2), Role::KissingLoop),
+            ("GCUC", Source::Edge((0, 2)), Role::Antisense),
+        ];
+        let mut generated: GeneratedSequence = GeneratedSequence::default();
+        segments
+            .iter()
+            .enumerate()
+            .for_each(|(path_index, (bases, source, role))| {
+                let bases: RnaSequence = bases.parse().unwrap();
+                generated
+                    .nucleotides
+                    .extend(bases.iter().map(|&base| NucleotideRecord {
+                        base,
+                        path_index,
+                        source: *source,
+                        role: *role,
+                        partner: None,
+                    }));
+            });
+        // Helix 0-1, kissing loop and helix 0-2, each as its outermost pair
+        [(0, 19), (6, 29), (20, 36)]
+            .into_iter()
+            .for_each(|(first, last)| {
+                (0..4).for_each(|offset| {
+                    generated
+                        .nucleotides
+                        .get_mut(first + offset)
+                        .unwrap()
+                        .partner = Some(last - offset);
+                    generated
+                        .nucleotides
+                        .get_mut(last - offset)
+                        .unwrap()
+                        .partner = Some(first + offset);
+                });
+            });
+        generated
+    }
+
+    #[test]
+    fn elements_form_once_their_second_half_is_transcribed() {
+        let settings: FoldingSettings = FoldingSettings {
+            max_delay: 10,
+            trap_length: 4,
+        };
+        let steps: Vec<FoldingStep> = folding_order(&transcript(), &settings);
+        // Element, halves, transcript length when formed, delay and long range
+        type Summary = (
+            FoldingElement,
+            (usize, usize),
+            (usize, usize),
+            usize,
+            usize,
+            bool,
+        );
+        let summary: Vec<Summary> = steps
+            .iter()
+            .map(|step| {
+                (
+                    step.element,
+                    step.first_half,
+                    step.second_half,
+                    step.formed_at,
+                    step.delay,
+                    step.long_range,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    FoldingElement::Helix((0, 1)),
+                    (0, 3),
+                    (16, 19),
+                    20,
+                    12,
+                    true
+                ),
+                (
+                    FoldingElement::KissingLoop(1, 2),
+                    (6, 9),
+                    (26, 29),
+                    30,
+                    16,
+                    true
+                ),
+                (
+                    FoldingElement::Helix((0, 2)),
+                    (20, 23),
+                    (33, 36),
+                    37,
+                    9,
+                    false
+                ),
+            ]
+        );
+
+        // The copy of the antisense strand in the first loop can take the sense strand first
+        let helix: &FoldingStep = steps.first().unwrap();
+        assert_eq!(
+            helix.traps,
+            vec![OffTargetMatch {
+                first: 0,
+                second: 11,
+                length: 4
+            }]
+        );
+        assert!(steps.iter().skip(1).all(|step| step.traps.is_empty()));
+        assert!(
+            helix
+                .to_string()
+                .ends_with("(long range), 1 possible traps")
+        );
+    }
+}
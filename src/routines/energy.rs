@@ -0,0 +1,234 @@
+use super::{analysis::MIN_LOOP_LENGTH, nucleotide::Nucleotide};
+
+// Simplified Turner 2004 nearest-neighbour model at 37 °C, in kcal/mol
+// Loops are scored from their size and closing pairs only: no terminal mismatches, dangles or special loops
+
+// Pair types, in the order of the stacking table
+const PAIRS: [(Nucleotide, Nucleotide); 6] = [
+    (Nucleotide::C, Nucleotide::G),
+    (Nucleotide::G, Nucleotide::C),
+    (Nucleotide::G, Nucleotide::U),
+    (Nucleotide::U, Nucleotide::G),
+    (Nucleotide::A, Nucleotide::U),
+    (Nucleotide::U, Nucleotide::A),
+];
+// Stack of the pair (i, j) on (i + 1, j - 1), indexed by the types of (i, j) and (j - 1, i + 1)
+const STACKS: [[f32; 6]; 6] = [
+    [-2.4, -3.3, -2.1, -1.4, -2.1, -2.1],
+    [-3.3, -3.4, -2.5, -1.5, -2.2, -2.4],
+    [-2.1, -2.5, 1.3, -0.5, -1.4, -1.3],
+    [-1.4, -1.5, -0.5, 0.3, -0.6, -1.0],
+    [-2.1, -2.2, -1.4, -0.6, -1.1, -0.9],
+    [-2.1, -2.4, -1.3, -1.0, -0.9, -1.3],
+];
+// Initiation by loop size, longer loops are extrapolated logarithmically
+const HAIRPIN: [f32; 10] = [
+    f32::INFINITY,
+    f32::INFINITY,
+    f32::INFINITY,
+    5.4,
+    5.6,
+    5.7,
+    5.4,
+    6.0,
+    5.5,
+    6.4,
+];
+const BULGE: [f32; 11] = [
+    f32::INFINITY,
+    3.8,
+    2.8,
+    3.2,
+    3.6,
+    4.0,
+    4.4,
+    4.6,
+    4.7,
+    4.8,
+    4.9,
+];
+const INTERIOR: [f32; 11] = [
+    f32::INFINITY,
+    f32::INFINITY,
+    0.5,
+    1.6,
+    1.1,
+    2.0,
+    2.0,
+    2.2,
+    2.3,
+    2.4,
+    2.5,
+];
+const EXTRAPOLATION: f32 = 1.07856;
+const HAIRPIN_MISMATCH: f32 = -0.8; // Average terminal mismatch bonus of hairpins longer than the minimum
+const NINIO: f32 = 0.6; // Per nucleotide of interior loop asymmetry
+const MAX_NINIO: f32 = 3.0;
+const INTERIOR_AU: f32 = 0.7; // Per interior loop closing pair other than G·C
+pub const TERMINAL_AU: f32 = 0.5; // Per A·U or G·U pair closing a helix in a multiloop or the exterior loop
+pub const MULTILOOP_CLOSING: f32 = 9.3;
+pub const MULTILOOP_BRANCH: f32 = -0.9; // Per helix in a multiloop, the closing one included
+pub const MULTILOOP_UNPAIRED: f32 = 0.0;
+// Largest interior loop or bulge considered, in unpaired nucleotides
+pub const MAX_INTERIOR: usize = 30;
+
+// Thermal energy at 37 °C, in kcal/mol
+pub const KT: f32 = 0.61632;
+
+fn pair_type(first: Nucleotide, second: Nucleotide) -> usize {
+    PAIRS
+        .iter()
+        .position(|pair| *pair == (first, second))
+        .unwrap()
+}
+
+fn base(sequence: &[Nucleotide], index: usize) -> Nucleotide {
+    *sequence.get(index).unwrap()
+}
+
+fn loop_initiation(table: &[f32], size: usize) -> f32 {
+    match table.get(size) {
+        Some(energy) => *energy,
+        None => {
+            let last: usize = table.len() - 1;
+            table.get(last).unwrap() + EXTRAPOLATION * (size as f32 / last as f32).ln()
+        }
+    }
+}
+
+// Whether i and j can close a loop, with at least MIN_LOOP_LENGTH nucleotides between them
+pub fn can_pair(sequence: &[Nucleotide], i: usize, j: usize) -> bool {
+    j > i + MIN_LOOP_LENGTH && base(sequence, i).pairs_with(base(sequence, j))
+}
+
+fn is_gc_pair(sequence: &[Nucleotide], i: usize, j: usize) -> bool {
+    base(sequence, i).is_gc() && base(sequence, j).is_gc()
+}
+
+// Penalty of a helix ending on the pair (i, j)
+pub fn terminal_penalty(sequence: &[Nucleotide], i: usize, j: usize) -> f32 {
+    if is_gc_pair(sequence, i, j) {
+        0.0
+    } else {
+        TERMINAL_AU
+    }
+}
+
+// Stack of the pair (i, j) on the pair (k, l) it closes
+fn stack_energy(sequence: &[Nucleotide], i: usize, j: usize, k: usize, l: usize) -> f32 {
+    *STACKS
+        .get(pair_type(base(sequence, i), base(sequence, j)))
+        .unwrap()
+        .get(pair_type(base(sequence, l), base(sequence, k)))
+        .unwrap()
+}
+
+pub fn hairpin_energy(sequence: &[Nucleotide], i: usize, j: usize) -> f32 {
+    let size: usize = j - i - 1;
+    let mismatch: f32 = if size > MIN_LOOP_LENGTH {
+        HAIRPIN_MISMATCH
+    } else {
+        terminal_penalty(sequence, i, j)
+    };
+    loop_initiation(&HAIRPIN, size) + mismatch
+}
+
+// Loop closed by (i, j) around the single inner pair (k, l): stack, bulge or interior loop
+pub fn interior_energy(sequence: &[Nucleotide], i: usize, j: usize, k: usize, l: usize) -> f32 {
+    let (left, right): (usize, usize) = (k - i - 1, j - l - 1);
+    match (left, right) {
+        (0, 0) => stack_energy(sequence, i, j, k, l),
+        (0, size) | (size, 0) => {
+            let bulge: f32 = loop_initiation(&BULGE, size);
+            if size == 1 {
+                // Single bulges leave the two helices stacked
+                bulge + stack_energy(sequence, i, j, k, l)
+            } else {
+                bulge + terminal_penalty(sequence, i, j) + terminal_penalty(sequence, k, l)
+            }
+        }
+        _ => {
+            let closing_au: f32 = [(i, j), (k, l)]
+                .iter()
+                .filter(|(first, second)| !is_gc_pair(sequence, *first, *second))
+                .count() as f32
+                * INTERIOR_AU;
+            loop_initiation(&INTERIOR, left + right)
+                + (NINIO * left.abs_diff(right) as f32).min(MAX_NINIO)
+                + closing_au
+        }
+    }
+}
+
+// Pairs directly inside the loop closed by (i, j), or in the exterior loop if None, with the number of unpaired nucleotides
+pub fn loop_branches(
+    partners: &[Option<usize>],
+    closing: Option<(usize, usize)>,
+) -> (Vec<(usize, usize)>, usize) {
+    let (start, end): (usize, usize) = match closing {
+        Some((i, j)) => (i + 1, j),
+        None => (0, partners.len()),
+    };
+    let mut branches: Vec<(usize, usize)> = Vec::new();
+    let mut unpaired: usize = 0;
+    let mut k: usize = start;
+    while k < end {
+        match *partners.get(k).unwrap() {
+            Some(l) if l > k => {
+                branches.push((k, l));
+                k = l + 1;
+            }
+            _ => {
+                unpaired += 1;
+                k += 1;
+            }
+        }
+    }
+    (branches, unpaired)
+}
+
+// Free energy of the loop closed by (i, j), or of the exterior loop if None
+pub fn loop_energy(
+    sequence: &[Nucleotide],
+    partners: &[Option<usize>],
+    closing: Option<(usize, usize)>,
+) -> f32 {
+    let (branches, unpaired) = loop_branches(partners, closing);
+    branched_loop_energy(sequence, closing, &branches, unpaired)
+}
+
+// Free energy of the loop closed by (i, j), or of the exterior loop if None, from the pairs directly inside it
+pub fn branched_loop_energy(
+    sequence: &[Nucleotide],
+    closing: Option<(usize, usize)>,
+    branches: &[(usize, usize)],
+    unpaired: usize,
+) -> f32 {
+    let branch_penalties: f32 = branches
+        .iter()
+        .map(|(k, l)| terminal_penalty(sequence, *k, *l))
+        .sum();
+    match (closing, branches) {
+        (None, _) => branch_penalties,
+        (Some((i, j)), []) => hairpin_energy(sequence, i, j),
+        (Some((i, j)), [(k, l)]) => interior_energy(sequence, i, j, *k, *l),
+        (Some((i, j)), _) => {
+            MULTILOOP_CLOSING
+                + MULTILOOP_BRANCH * (branches.len() + 1) as f32
+                + MULTILOOP_UNPAIRED * unpaired as f32
+                + branch_penalties
+                + terminal_penalty(sequence, i, j)
+        }
+    }
+}
+
+// Free energy of a pseudoknot-free secondary structure, as the sum of its loops
+pub fn structure_energy(sequence: &[Nucleotide], partners: &[Option<usize>]) -> f32 {
+    loop_energy(sequence, partners, None)
+        + partners
+            .iter()
+            .enumerate()
+            .filter_map(|(i, partner)| partner.filter(|j| *j > i).map(|j| (i, j)))
+            .map(|closing| loop_energy(sequence, partners, Some(closing)))
+            .sum::<f32>()
+}
@@ -0,0 +1,363 @@
+use std::fmt::{Display, Formatter};
+
+use super::{
+    energy::{
+        KT, MAX_INTERIOR, MULTILOOP_BRANCH, MULTILOOP_CLOSING, MULTILOOP_UNPAIRED, can_pair,
+        hairpin_energy, interior_energy, structure_energy, terminal_penalty,
+    },
+    nucleotide::{Nucleotide, RnaSequence},
+    sequencer::{GeneratedSequence, Source},
+    structure::TargetStructure,
+};
+
+// Boltzmann-weighted ensemble of the pseudoknot-free structures of a sequence
+#[derive(Debug, Clone)]
+pub struct Ensemble {
+    pub free_energy: f32, // kcal/mol
+    length: usize,
+    probabilities: Vec<f32>, // Pair probabilities (i, j) with i <= j, row by row
+}
+
+// Position of (i, j) with i <= j in a row by row upper triangle of a length by length matrix
+fn triangle_index(length: usize, i: usize, j: usize) -> usize {
+    i * (2 * length - i + 1) / 2 + j - i
+}
+
+impl Ensemble {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn pair_probability(&self, i: usize, j: usize) -> f32 {
+        *self
+            .probabilities
+            .get(triangle_index(self.length, i.min(j), i.max(j)))
+            .unwrap()
+    }
+
+    pub fn unpaired_probability(&self, i: usize) -> f32 {
+        1.0 - (0..self.length)
+            .map(|j| self.pair_probability(i, j))
+            .sum::<f32>()
+    }
+
+    // Probability that each nucleotide is in its target state, paired to its partner or unpaired
+    pub fn target_probabilities(&self, target: &[Option<usize>]) -> Vec<f32> {
+        target
+            .iter()
+            .enumerate()
+            .map(|(i, partner)| match partner {
+                Some(j) => self.pair_probability(i, *j),
+                None => self.unpaired_probability(i),
+            })
+            .collect()
+    }
+
+    // Expected number of nucleotides in a different state than in the target (Dirks et al., 2004)
+    pub fn ensemble_defect(&self, target: &[Option<usize>]) -> f32 {
+        self.target_probabilities(target)
+            .iter()
+            .map(|probability| 1.0 - probability)
+            .sum()
+    }
+}
+
+fn boltzmann(energy: f32) -> f64 {
+    (-(energy as f64) / KT as f64).exp()
+}
+
+// McCaskill partition function and base pair probabilities, in O(n³) with bounded interior loops
+// Weights are scaled per nucleotide from the expected free energy, so long sequences do not overflow
+pub fn partition_function(sequence: &[Nucleotide], expected_energy: f32) -> Ensemble {
+    let length: usize = sequence.len();
+    let index = |i: usize, j: usize| triangle_index(length, i, j);
+    let size: usize = length * (length + 1) / 2;
+    let scale: f64 = boltzmann(-expected_energy.min(0.0) / length.max(1) as f32);
+    let scales: Vec<f64> = (0..=length + 1)
+        .map(|size| scale.powi(size as i32))
+        .collect();
+    let scales: &[f64] = &scales;
+    let unpaired =
+        |size: usize| boltzmann(MULTILOOP_UNPAIRED * size as f32) * scales.get(size).unwrap();
+    let closing = |i: usize, j: usize| {
+        boltzmann(MULTILOOP_CLOSING + MULTILOOP_BRANCH + terminal_penalty(sequence, i, j))
+            * scales.get(2).unwrap()
+    };
+    let branch =
+        |i: usize, j: usize| boltzmann(MULTILOOP_BRANCH + terminal_penalty(sequence, i, j));
+    let exterior = |i: usize, j: usize| boltzmann(terminal_penalty(sequence, i, j));
+    // Inner pairs (k, l) of the interior loops closed by (i, j), with the loop weight
+    let interior_loops = |i: usize, j: usize| {
+        (i + 1..j.saturating_sub(4).min(i + 2 + MAX_INTERIOR))
+            .flat_map(move |k| {
+                let left: usize = k - i - 1;
+                ((k + 4).max(j - 1 - (MAX_INTERIOR - left).min(j - 1))..j).map(move |l| (k, l))
+            })
+            .filter(move |&(k, l)| can_pair(sequence, k, l))
+            .map(move |(k, l)| {
+                let weight: f64 = boltzmann(interior_energy(sequence, i, j, k, l))
+                    * scales.get(k - i + j - l).unwrap();
+                (k, l, weight)
+            })
+    };
+
+    // Inside: structures closed by (i, j), multiloop segments with at least one branch, and with exactly one starting at i
+    let mut paired: Vec<f64> = vec![0.0; size];
+    let mut multi: Vec<f64> = vec![0.0; size];
+    let mut multi_first: Vec<f64> = vec![0.0; size];
+    let mut exterior_prefix: Vec<f64> = vec![1.0; length + 1]; // Segments 0..j
+    (4..length).for_each(|span| {
+        (0..length - span).for_each(|i| {
+            let j: usize = i + span;
+            if can_pair(sequence, i, j) {
+                let mut weight: f64 =
+                    boltzmann(hairpin_energy(sequence, i, j)) * scales.get(span + 1).unwrap();
+                interior_loops(i, j).for_each(|(k, l, loop_weight)| {
+                    weight += loop_weight * paired.get(index(k, l)).unwrap();
+                });
+                (i + 6..j).for_each(|u| {
+                    weight += closing(i, j)
+                        * multi.get(index(i + 1, u - 1)).unwrap()
+                        * multi_first.get(index(u, j - 1)).unwrap();
+                });
+                *paired.get_mut(index(i, j)).unwrap() = weight;
+            }
+            *multi_first.get_mut(index(i, j)).unwrap() = (i + 4..=j)
+                .map(|l| paired.get(index(i, l)).unwrap() * branch(i, l) * unpaired(j - l))
+                .sum();
+            *multi.get_mut(index(i, j)).unwrap() = (i..=j)
+                .map(|u| {
+                    let before: f64 = if u > i {
+                        *multi.get(index(i, u - 1)).unwrap()
+                    } else {
+                        0.0
+                    };
+                    (unpaired(u - i) + before) * multi_first.get(index(u, j)).unwrap()
+                })
+                .sum();
+        })
+    });
+    (0..length).for_each(|j| {
+        let weight: f64 = exterior_prefix.get(j).unwrap() * scale
+            + (0..j.saturating_sub(3))
+                .map(|k| {
+                    exterior_prefix.get(k).unwrap()
+                        * paired.get(index(k, j)).unwrap()
+                        * exterior(k, j)
+                })
+                .sum::<f64>();
+        *exterior_prefix.get_mut(j + 1).unwrap() = weight;
+    });
+    let partition: f64 = *exterior_prefix.get(length).unwrap();
+
+    // Outside: derivative of the partition function with respect to each inside weight
+    let mut paired_outside: Vec<f64> = vec![0.0; size];
+    let mut multi_outside: Vec<f64> = vec![0.0; size];
+    let mut multi_first_outside: Vec<f64> = vec![0.0; size];
+    let mut exterior_outside: Vec<f64> = vec![0.0; length + 1];
+    *exterior_outside.get_mut(length).unwrap() = 1.0;
+    (0..length).rev().for_each(|j| {
+        let outside: f64 = *exterior_outside.get(j + 1).unwrap();
+        *exterior_outside.get_mut(j).unwrap() += outside * scale;
+        (0..j.saturating_sub(3)).for_each(|k| {
+            *exterior_outside.get_mut(k).unwrap() +=
+                outside * paired.get(index(k, j)).unwrap() * exterior(k, j);
+            *paired_outside.get_mut(index(k, j)).unwrap() +=
+                outside * exterior_prefix.get(k).unwrap() * exterior(k, j);
+        });
+    });
+    // Longer segments first, and within a segment the order in which inside weights depend on each other
+    (4..length).rev().for_each(|span| {
+        (0..length - span).for_each(|i| {
+            let j: usize = i + span;
+            let outside: f64 = *multi_outside.get(index(i, j)).unwrap();
+            if outside != 0.0 {
+                (i..=j).for_each(|u| {
+                    let before: f64 = if u > i {
+                        *multi.get(index(i, u - 1)).unwrap()
+                    } else {
+                        0.0
+                    };
+                    *multi_first_outside.get_mut(index(u, j)).unwrap() +=
+                        outside * (unpaired(u - i) + before);
+                    if u > i {
+                        *multi_outside.get_mut(index(i, u - 1)).unwrap() +=
+                            outside * multi_first.get(index(u, j)).unwrap();
+                    }
+                });
+            }
+            let outside: f64 = *multi_first_outside.get(index(i, j)).unwrap();
+            if outside != 0.0 {
+                (i + 4..=j).for_each(|l| {
+                    *paired_outside.get_mut(index(i, l)).unwrap() +=
+                        outside * branch(i, l) * unpaired(j - l);
+                });
+            }
+            let outside: f64 = *paired_outside.get(index(i, j)).unwrap();
+            if outside != 0.0 && can_pair(sequence, i, j) {
+                interior_loops(i, j).for_each(|(k, l, loop_weight)| {
+                    *paired_outside.get_mut(index(k, l)).unwrap() += outside * loop_weight;
+                });
+                (i + 6..j).for_each(|u| {
+                    *multi_outside.get_mut(index(i + 1, u - 1)).unwrap() +=
+                        outside * closing(i, j) * multi_first.get(index(u, j - 1)).unwrap();
+                    *multi_first_outside.get_mut(index(u, j - 1)).unwrap() +=
+                        outside * closing(i, j) * multi.get(index(i + 1, u - 1)).unwrap();
+                });
+            }
+        })
+    });
+
+    // Only the pair weights are left to use
+    drop(multi);
+    drop(multi_first);
+    drop(multi_outside);
+    drop(multi_first_outside);
+    let probabilities: Vec<f32> = paired
+        .iter()
+        .zip(&paired_outside)
+        .map(|(inside, outside)| (inside * outside / partition) as f32)
+        .collect();
+
+    Ensemble {
+        free_energy: (-(KT as f64) * (partition.ln() - length as f64 * scale.ln())) as f32,
+        length,
+        probabilities,
+    }
+}
+
+// Ensemble defect of a generated sequence against its pseudoknot-free target
+#[derive(Debug, Clone)]
+pub struct DefectReport {
+    pub free_energy: f32,                  // Ensemble free energy, kcal/mol
+    pub target_energy: f32,                // Free energy of the target structure, kcal/mol
+    pub defect: f32,                       // Expected number of incorrectly paired nucleotides
+    pub normalized_defect: f32,            // Defect per nucleotide
+    pub contributions: Vec<(Source, f32)>, // Defect of each helix and motif, largest first
+}
+
+impl Display for DefectReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Ensemble defect {:.1} nt ({:.1}%), ensemble ΔG {:.1} kcal/mol, target ΔG {:.1} kcal/mol",
+            self.defect,
+            100.0 * self.normalized_defect,
+            self.free_energy,
+            self.target_energy
+        )?;
+        self.contributions
+            .iter()
+            .try_for_each(|(source, defect)| write!(f, "\n- {source}: {defect:.2} nt"))
+    }
+}
+
+pub fn design_defect(generated: &GeneratedSequence) -> DefectReport {
+    let sequence: RnaSequence = generated.sequence();
+    let target: Vec<Option<usize>> = TargetStructure::from_generated(generated).nested_partners();
+    let target_energy: f32 = structure_energy(&sequence, &target);
+    let ensemble: Ensemble = partition_function(&sequence, target_energy);
+
+    let mut contributions: Vec<(Source, f32)> = Vec::new();
+    ensemble
+        .target_probabilities(&target)
+        .iter()
+        .zip(&generated.nucleotides)
+        .for_each(|(probability, nucleotide)| {
+            match contributions
+                .iter_mut()
+                .find(|(source, _)| *source == nucleotide.source)
+            {
+                Some((_, defect)) => *defect += 1.0 - probability,
+                None => contributions.push((nucleotide.source, 1.0 - probability)),
+            }
+        });
+    contributions.sort_by(|first, second| second.1.total_cmp(&first.1));
+    let defect: f32 = contributions.iter().map(|(_, defect)| defect).sum();
+
+    DefectReport {
+        free_energy: ensemble.free_energy,
+        target_energy,
+        defect,
+        normalized_defect: defect / sequence.len().max(1) as f32,
+        contributions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routines::energy::loop_branches;
+
+    // Every pseudoknot-free structure of sequence[start..end], as lists of pairs
+    fn structures(sequence: &[Nucleotide], start: usize, end: usize) -> Vec<Vec<(usize, usize)>> {
+        if start >= end {
+            return vec![Vec::new()];
+        }
+        let mut found: Vec<Vec<(usize, usize)>> = structures(sequence, start + 1, end);
+        (start + 1..end)
+            .filter(|&k| can_pair(sequence, start, k))
+            .for_each(|k| {
+                let outside: Vec<Vec<(usize, usize)>> = structures(sequence, k + 1, end);
+                structures(sequence, start + 1, k)
+                    .iter()
+                    .for_each(|inside| {
+                        outside.iter().for_each(|after| {
+                            found.push([&[(start, k)], &inside[..], &after[..]].concat());
+                        })
+                    });
+            });
+        found
+    }
+
+    #[test]
+    fn probabilities_match_the_enumerated_ensemble() {
+        let sequence: RnaSequence = "GGGAGCAAAGCACGAAAGUCCC".parse().unwrap();
+        let length: usize = sequence.len();
+        let weights: Vec<(Vec<Option<usize>>, f64)> = structures(&sequence, 0, length)
+            .iter()
+            .map(|pairs| {
+                let mut partners: Vec<Option<usize>> = vec![None; length];
+                pairs.iter().for_each(|&(i, j)| {
+                    *partners.get_mut(i).unwrap() = Some(j);
+                    *partners.get_mut(j).unwrap() = Some(i);
+                });
+                let weight: f64 = boltzmann(structure_energy(&sequence, &partners));
+                (partners, weight)
+            })
+            .collect();
+        // Multiloops are part of the enumerated ensemble
+        assert!(weights.iter().any(|(partners, _)| {
+            (0..length).any(|i| {
+                partners
+                    .get(i)
+                    .unwrap()
+                    .is_some_and(|j| j > i && loop_branches(partners, Some((i, j))).0.len() > 1)
+            })
+        }));
+        let partition: f64 = weights.iter().map(|(_, weight)| weight).sum();
+
+        let ensemble: Ensemble = partition_function(&sequence, -8.0);
+        let free_energy: f32 = (-(KT as f64) * partition.ln()) as f32;
+        assert!((ensemble.free_energy - free_energy).abs() < 1e-3);
+        (0..length).for_each(|i| {
+            (i + 1..length).for_each(|j| {
+                let probability: f64 = weights
+                    .iter()
+                    .filter(|(partners, _)| *partners.get(i).unwrap() == Some(j))
+                    .map(|(_, weight)| weight)
+                    .sum::<f64>()
+                    / partition;
+                assert!((ensemble.pair_probability(i, j) as f64 - probability).abs() < 1e-4);
+                assert_eq!(
+                    ensemble.pair_probability(i, j),
+                    ensemble.pair_probability(j, i)
+                );
+            })
+        });
+    }
+}
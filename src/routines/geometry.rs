@@ -0,0 +1,578 @@
+use std::fmt::{Display, Formatter};
+
+use glam::Vec3A;
+use rustc_hash::FxHashSet;
+
+use super::{
+    graph::{Tree, construct_tree},
+    sequencer::estimate_sequence_length,
+};
+
+// Physical dimensions of an A-form RNA helix
+// One coordinate unit corresponds to one base pair, as edge lengths are rounded to nucleotide counts
+pub const HELIX_RISE_NM: f32 = 0.28;
+pub const HELIX_DIAMETER_NM: f32 = 2.3;
+// Junctions are treated as spheres with a radius of one helix diameter
+// Adjacent helices leaving a junction at less than ~60° therefore clash
+pub const JUNCTION_RADIUS_NM: f32 = 2.3;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Clash {
+    // Two helices whose cylinders overlap, with the distance between their axes in coordinate units
+    Helices {
+        first: (usize, usize),
+        second: (usize, usize),
+        distance: f32,
+    },
+    // A helix passing through the volume of a junction it is not part of, with the distance from its axis
+    Junction {
+        edge: (usize, usize),
+        node: usize,
+        distance: f32,
+    },
+}
+
+impl Clash {
+    // Edges involved in the clash, used to highlight them
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        match *self {
+            Clash::Helices { first, second, .. } => vec![first, second],
+            Clash::Junction { edge, .. } => vec![edge],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ScaleTarget {
+    BoundingBox(f32),      // Largest dimension of the bounding box
+    MeanEdgeLength(f32),   // Mean length of the user-defined edges
+    SequenceLength(usize), // Total number of nucleotides in the generated sequence
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleError {
+    InvalidTarget,                     // Sizes and lengths must be positive
+    NoExtent,                          // Every vertex or edge end is at the same place
+    LengthTooShort { minimum: usize }, // Below the fixed motifs with single base pair helices
+}
+
+impl Display for ScaleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScaleError::InvalidTarget => write!(f, "The target size must be positive"),
+            ScaleError::NoExtent => write!(f, "The structure has no size to scale"),
+            ScaleError::LengthTooShort { minimum } => write!(
+                f,
+                "The sequence length must be at least {minimum} nt for this structure"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScalePreview {
+    pub factor: f32,
+    pub sequence_length: usize,
+}
+
+// Convert a physical length to coordinate units
+pub fn nm_to_units(length: f32) -> f32 {
+    length / HELIX_RISE_NM
+}
+
+// Convert a length in coordinate units to nm
+pub fn units_to_nm(length: f32) -> f32 {
+    length * HELIX_RISE_NM
+}
+
+// Find all pairs of helices (and helix/junction pairs) that cannot physically coexist
+pub fn find_clashes(edges: &[(usize, usize)], node_coordinates: &[Vec3A]) -> Vec<Clash> {
+    let helix_diameter: f32 = nm_to_units(HELIX_DIAMETER_NM);
+    let helix_radius: f32 = 0.5 * helix_diameter;
+    let junction_radius: f32 = nm_to_units(JUNCTION_RADIUS_NM);
+
+    // Ignore edges referencing missing vertices, as well as duplicates in either direction
+    let mut seen: FxHashSet<(usize, usize)> = FxHashSet::default();
+    let valid_edges: Vec<(usize, usize)> = edges
+        .iter()
+        .copied()
+        .filter(|&(origin, destination)| {
+            origin != destination
+                && origin < node_coordinates.len()
+                && destination < node_coordinates.len()
+                && seen.insert((origin.min(destination), origin.max(destination)))
+        })
+        .collect();
+
+    let mut clashes: Vec<Clash> = Vec::new();
+
+    // Helix against helix
+    valid_edges.iter().enumerate().for_each(|(index, &first)| {
+        valid_edges.iter().skip(index + 1).for_each(|&second| {
+            let shared_node: Option<usize> = [first.0, first.1]
+                .into_iter()
+                .find(|node| *node == second.0 || *node == second.1);
+
+            let (mut a0, mut a1) = edge_endpoints(first, node_coordinates);
+            let (mut b0, mut b1) = edge_endpoints(second, node_coordinates);
+
+            // Adjacent helices always meet at their junction
+            // Only the parts outside of the junction volume can clash (acute angles)
+            if let Some(node) = shared_node {
+                let (trimmed_a, trimmed_b) = (
+                    trim_at_node(first, node, (a0, a1), junction_radius),
+                    trim_at_node(second, node, (b0, b1), junction_radius),
+                );
+                match (trimmed_a, trimmed_b) {
+                    (Some(a), Some(b)) => {
+                        (a0, a1) = a;
+                        (b0, b1) = b;
+                    }
+                    // Helix entirely inside the junction, nothing to compare
+                    _ => return,
+                }
+            }
+
+            let distance: f32 = segment_distance(a0, a1, b0, b1);
+            if distance < helix_diameter {
+                clashes.push(Clash::Helices {
+                    first,
+                    second,
+                    distance,
+                });
+            }
+        });
+    });
+
+    // Helix against junction
+    let junctions: FxHashSet<usize> = valid_edges
+        .iter()
+        .flat_map(|&(origin, destination)| [origin, destination])
+        .collect();
+    valid_edges.iter().for_each(|&edge| {
+        let (start, end) = edge_endpoints(edge, node_coordinates);
+        junctions
+            .iter()
+            .filter(|&&node| node != edge.0 && node != edge.1)
+            .for_each(|&node| {
+                let distance: f32 =
+                    point_segment_distance(*node_coordinates.get(node).unwrap(), start, end);
+                if distance < helix_radius + junction_radius {
+                    clashes.push(Clash::Junction {
+                        edge,
+                        node,
+                        distance,
+                    });
+                }
+            });
+    });
+
+    clashes
+}
+
+fn edge_endpoints(edge: (usize, usize), node_coordinates: &[Vec3A]) -> (Vec3A, Vec3A) {
+    (
+        *node_coordinates.get(edge.0).unwrap(),
+        *node_coordinates.get(edge.1).unwrap(),
+    )
+}
+
+// Remove the part of a segment lying within the junction sphere of one of its ends
+fn trim_at_node(
+    edge: (usize, usize),
+    node: usize,
+    (start, end): (Vec3A, Vec3A),
+    radius: f32,
+) -> Option<(Vec3A, Vec3A)> {
+    let length: f32 = start.distance(end);
+    if length <= radius {
+        return None;
+    }
+    let direction: Vec3A = (end - start) / length;
+    if edge.0 == node {
+        Some((start + direction * radius, end))
+    } else {
+        Some((start, end - direction * radius))
+    }
+}
+
+fn point_segment_distance(point: Vec3A, start: Vec3A, end: Vec3A) -> f32 {
+    let segment: Vec3A = end - start;
+    let length_squared: f32 = segment.length_squared();
+    if length_squared == 0.0 {
+        return point.distance(start);
+    }
+    let t: f32 = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    point.distance(start + segment * t)
+}
+
+// Minimum distance between segments [a0, a1] and [b0, b1]
+fn segment_distance(a0: Vec3A, a1: Vec3A, b0: Vec3A, b1: Vec3A) -> f32 {
+    let da: Vec3A = a1 - a0;
+    let db: Vec3A = b1 - b0;
+    let r: Vec3A = a0 - b0;
+    let len_a: f32 = da.length_squared();
+    let len_b: f32 = db.length_squared();
+    let f: f32 = db.dot(r);
+
+    // Degenerate segments collapse to points
+    if len_a <= f32::EPSILON && len_b <= f32::EPSILON {
+        return a0.distance(b0);
+    }
+    if len_a <= f32::EPSILON {
+        return point_segment_distance(a0, b0, b1);
+    }
+    if len_b <= f32::EPSILON {
+        return point_segment_distance(b0, a0, a1);
+    }
+
+    let c: f32 = da.dot(r);
+    let b: f32 = da.dot(db);
+    let denominator: f32 = len_a * len_b - b * b;
+
+    // Closest point parameters on each segment, clamped to the segments
+    // Parallel segments have no unique closest pair, so any start point works
+    let mut s: f32 = if denominator > f32::EPSILON {
+        ((b * f - c * len_b) / denominator).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let mut t: f32 = (b * s + f) / len_b;
+    if t < 0.0 {
+        t = 0.0;
+        s = (-c / len_a).clamp(0.0, 1.0);
+    } else if t > 1.0 {
+        t = 1.0;
+        s = ((b - c) / len_a).clamp(0.0, 1.0);
+    }
+
+    (a0 + da * s).distance(b0 + db * t)
+}
+
+// Find the uniform scaling factor that makes the structure hit the target
+// Sequence lengths are reached with helices of at least one base pair, so the structure never collapses
+pub fn find_scale_factor(
+    edges: &[(usize, usize)],
+    node_coordinates: &[Vec3A],
+    target: ScaleTarget,
+) -> Result<f32, ScaleError> {
+    let valid: bool = match target {
+        ScaleTarget::BoundingBox(size) | ScaleTarget::MeanEdgeLength(size) => {
+            size.is_finite() && size > 0.0
+        }
+        ScaleTarget::SequenceLength(length) => length > 0,
+    };
+    if !valid {
+        return Err(ScaleError::InvalidTarget);
+    }
+    if edges.is_empty() || node_coordinates.is_empty() {
+        return Ok(1.0);
+    }
+
+    let lengths: Vec<f32> = edges
+        .iter()
+        .filter(|&&(origin, destination)| {
+            origin < node_coordinates.len() && destination < node_coordinates.len()
+        })
+        .map(|&edge| {
+            let (start, end) = edge_endpoints(edge, node_coordinates);
+            start.distance(end)
+        })
+        .collect();
+    match target {
+        ScaleTarget::BoundingBox(size) => {
+            let (min, max) = node_coordinates.iter().fold(
+                (Vec3A::splat(f32::INFINITY), Vec3A::splat(f32::NEG_INFINITY)),
+                |(min, max), pos| (min.min(*pos), max.max(*pos)),
+            );
+            let current_size: f32 = (max - min).max_element();
+            if current_size > 0.0 {
+                Ok(size / current_size)
+            } else {
+                Err(ScaleError::NoExtent)
+            }
+        }
+        ScaleTarget::MeanEdgeLength(length) => {
+            let mean: f32 = lengths.iter().sum::<f32>() / lengths.len().max(1) as f32;
+            if mean > 0.0 {
+                Ok(length / mean)
+            } else {
+                Err(ScaleError::NoExtent)
+            }
+        }
+        ScaleTarget::SequenceLength(length) => {
+            // Helix lengths are rounded, so the sequence length is a step function of the factor
+            // Bisect for the smallest factor reaching the target, then keep whichever neighbour is closest
+            let tree: Tree = construct_tree(edges);
+            let length_at = |factor: f32| -> usize {
+                let scaled: Vec<Vec3A> = node_coordinates.iter().map(|pos| pos * factor).collect();
+                estimate_sequence_length(&tree, &scaled).total()
+            };
+
+            // The shortest edge keeps at least one base pair
+            let shortest: f32 = lengths
+                .iter()
+                .copied()
+                .filter(|length| *length > 0.0)
+                .fold(f32::INFINITY, f32::min);
+            if !shortest.is_finite() {
+                return Err(ScaleError::NoExtent);
+            }
+            let mut low: f32 = 1.0 / shortest;
+            let minimum: usize = length_at(low);
+            if minimum > length {
+                return Err(ScaleError::LengthTooShort { minimum });
+            }
+
+            let mut high: f32 = low.max(1.0);
+            while length_at(high) < length && high < 1e6 {
+                high *= 2.0;
+            }
+            (0..48).for_each(|_| {
+                let mid: f32 = 0.5 * (low + high);
+                if length_at(mid) < length {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            });
+
+            if length_at(high).abs_diff(length) <= length_at(low).abs_diff(length) {
+                Ok(high)
+            } else {
+                Ok(low)
+            }
+        }
+    }
+}
+
+// Translate the structure so its centroid is at the origin
+pub fn recentre(node_coordinates: &mut [Vec3A]) {
+    let centroid: Vec3A =
+        node_coordinates.iter().sum::<Vec3A>() / node_coordinates.len().max(1) as f32;
+    node_coordinates.iter_mut().for_each(|pos| *pos -= centroid);
+}
+
+// Uniformly scale the structure about its centroid
+pub fn rescale(node_coordinates: &mut [Vec3A], factor: f32) {
+    let centroid: Vec3A =
+        node_coordinates.iter().sum::<Vec3A>() / node_coordinates.len().max(1) as f32;
+    node_coordinates
+        .iter_mut()
+        .for_each(|pos| *pos = centroid + (*pos - centroid) * factor);
+}
+
+// Scaling factor and resulting sequence length, without modifying the structure
+pub fn preview_rescale(
+    edges: &[(usize, usize)],
+    node_coordinates: &[Vec3A],
+    target: ScaleTarget,
+) -> Result<ScalePreview, ScaleError> {
+    let factor: f32 = find_scale_factor(edges, node_coordinates, target)?;
+    let mut scaled: Vec<Vec3A> = node_coordinates.to_vec();
+    rescale(&mut scaled, factor);
+    Ok(ScalePreview {
+        factor,
+        sequence_length: if edges.is_empty() {
+            0
+        } else {
+            estimate_sequence_length(&construct_tree(edges), &scaled).total()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3a;
+
+    use super::*;
+
+    fn pyramid() -> (Vec<(usize, usize)>, Vec<Vec3A>) {
+        (
+            vec![
+                (4, 0),
+                (4, 1),
+                (4, 2),
+                (4, 3),
+                (0, 1),
+                (1, 2),
+                (2, 3),
+                (3, 0),
+            ],
+            vec![
+                vec3a(-10.0, 10.0, 0.0),
+                vec3a(10.0, 10.0, 0.0),
+                vec3a(10.0, -10.0, 0.0),
+                vec3a(-10.0, -10.0, 0.0),
+                vec3a(0.0, 0.0, 20.0),
+            ],
+        )
+    }
+
+    #[test]
+    fn sizes_are_scaled_to() {
+        let (edges, coordinates) = pyramid();
+        assert_eq!(
+            find_scale_factor(&edges, &coordinates, ScaleTarget::BoundingBox(40.0)),
+            Ok(2.0)
+        );
+        assert_eq!(
+            find_scale_factor(&edges, &coordinates, ScaleTarget::MeanEdgeLength(20.0)),
+            Ok(20.0 / (0.5 * (20.0 + 600.0f32.sqrt())))
+        );
+    }
+
+    #[test]
+    fn sequence_lengths_are_scaled_to() {
+        let (edges, coordinates) = pyramid();
+        let preview: ScalePreview =
+            preview_rescale(&edges, &coordinates, ScaleTarget::SequenceLength(600)).unwrap();
+        assert!(preview.sequence_length.abs_diff(600) <= 8, "{preview:?}");
+
+        // Below what the motifs alone take, the structure is not collapsed to a point
+        match find_scale_factor(&edges, &coordinates, ScaleTarget::SequenceLength(10)) {
+            Err(ScaleError::LengthTooShort { minimum }) => assert!(minimum > 10),
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn invalid_targets_are_rejected() {
+        let (edges, coordinates) = pyramid();
+        [
+            ScaleTarget::BoundingBox(-40.0),
+            ScaleTarget::BoundingBox(0.0),
+            ScaleTarget::MeanEdgeLength(f32::NAN),
+            ScaleTarget::SequenceLength(0),
+        ]
+        .into_iter()
+        .for_each(|target| {
+            assert_eq!(
+                find_scale_factor(&edges, &coordinates, target),
+                Err(ScaleError::InvalidTarget)
+            );
+        });
+        assert_eq!(
+            find_scale_factor(&[(0, 1)], &[Vec3A::ONE; 2], ScaleTarget::BoundingBox(10.0)),
+            Err(ScaleError::NoExtent)
+        );
+    }
+
+    // Two edges leaving the origin at the given angle, in degrees
+    fn corner(angle: f32) -> Vec<Vec3A> {
+        let radians: f32 = angle.to_radians();
+        vec![
+            Vec3A::ZERO,
+            vec3a(30.0, 0.0, 0.0),
+            vec3a(30.0 * radians.cos(), 30.0 * radians.sin(), 0.0),
+        ]
+    }
+
+    #[test]
+    fn adjacent_helices_clash_below_sixty_degrees() {
+        let clashes: Vec<Clash> = find_clashes(&[(0, 1), (0, 2)], &corner(50.0));
+        assert!(matches!(
+            clashes.as_slice(),
+            [Clash::Helices {
+                first: (0, 1),
+                second: (0, 2),
+                ..
+            }]
+        ));
+        assert!(find_clashes(&[(0, 1), (0, 2)], &corner(70.0)).is_empty());
+        // Shorter than the junction radius, the helix is entirely inside the junction
+        let mut coordinates: Vec<Vec3A> = corner(10.0);
+        *coordinates.get_mut(2).unwrap() = vec3a(1.0, 1.0, 0.0);
+        assert!(
+            !find_clashes(&[(0, 1), (0, 2)], &coordinates)
+                .iter()
+                .any(|clash| matches!(clash, Clash::Helices { .. }))
+        );
+    }
+
+    #[test]
+    fn non_adjacent_helices_clash_when_close() {
+        // Crossing at their middles
+        let crossing: Vec<Vec3A> = vec![
+            vec3a(-20.0, 0.0, 0.0),
+            vec3a(20.0, 0.0, 0.0),
+            vec3a(0.0, -20.0, 2.0),
+            vec3a(0.0, 20.0, 2.0),
+        ];
+        match find_clashes(&[(0, 1), (2, 3)], &crossing).as_slice() {
+            [Clash::Helices { distance, .. }] => assert!((distance - 2.0).abs() < 1e-5),
+            other => panic!("{other:?}"),
+        }
+
+        // Parallel, closer or further than a helix diameter
+        let parallel = |gap: f32| {
+            vec![
+                vec3a(0.0, 0.0, 0.0),
+                vec3a(40.0, 0.0, 0.0),
+                vec3a(10.0, gap, 0.0),
+                vec3a(50.0, gap, 0.0),
+            ]
+        };
+        match find_clashes(&[(0, 1), (2, 3)], &parallel(5.0)).first() {
+            Some(Clash::Helices { distance, .. }) => assert!((distance - 5.0).abs() < 1e-5),
+            other => panic!("{other:?}"),
+        }
+        assert!(find_clashes(&[(0, 1), (2, 3)], &parallel(20.0)).is_empty());
+    }
+
+    #[test]
+    fn helices_through_junctions_clash() {
+        let coordinates: Vec<Vec3A> = vec![
+            vec3a(-30.0, 0.0, 0.0),
+            vec3a(30.0, 0.0, 0.0),
+            vec3a(0.0, 3.0, 0.0),
+            vec3a(0.0, 40.0, 0.0),
+        ];
+        let clashes: Vec<Clash> = find_clashes(&[(0, 1), (2, 3)], &coordinates);
+        assert!(clashes.iter().any(|clash| matches!(
+            clash,
+            Clash::Junction {
+                edge: (0, 1),
+                node: 2,
+                distance,
+            } if (distance - 3.0).abs() < 1e-5
+        )));
+        // Beyond the junction and helix radii, nothing clashes
+        let mut far: Vec<Vec3A> = coordinates.clone();
+        *far.get_mut(2).unwrap() = vec3a(0.0, 20.0, 0.0);
+        assert!(find_clashes(&[(0, 1), (2, 3)], &far).is_empty());
+    }
+
+    #[test]
+    fn zero_length_segments_are_points() {
+        let point: Vec3A = vec3a(1.0, 2.0, 0.0);
+        assert_eq!(
+            point_segment_distance(point, Vec3A::ZERO, Vec3A::ZERO),
+            point.length()
+        );
+        assert_eq!(
+            segment_distance(point, point, vec3a(-5.0, 0.0, 0.0), vec3a(5.0, 0.0, 0.0)),
+            2.0
+        );
+        assert_eq!(
+            segment_distance(vec3a(-5.0, 0.0, 0.0), vec3a(5.0, 0.0, 0.0), point, point),
+            2.0
+        );
+        assert_eq!(
+            segment_distance(point, point, Vec3A::ZERO, Vec3A::ZERO),
+            point.length()
+        );
+        assert_eq!(
+            trim_at_node((0, 1), 0, (Vec3A::ZERO, vec3a(10.0, 0.0, 0.0)), 4.0),
+            Some((vec3a(4.0, 0.0, 0.0), vec3a(10.0, 0.0, 0.0)))
+        );
+        assert_eq!(
+            trim_at_node((0, 1), 1, (Vec3A::ZERO, vec3a(10.0, 0.0, 0.0)), 4.0),
+            Some((Vec3A::ZERO, vec3a(6.0, 0.0, 0.0)))
+        );
+        assert_eq!(
+            trim_at_node((0, 1), 0, (Vec3A::ZERO, Vec3A::ZERO), 4.0),
+            None
+        );
+    }
+}
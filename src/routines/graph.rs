@@ -277,3 +277,30 @@ pub fn find_rna_path(tree: &mut Tree, node_coordinates: &[Vec3A]) -> Vec<usize>
 
     path
 }
+
+// Find the cycle-breaker node paired with the given node, if it is a cycle-breaker
+pub fn cycle_breaker_partner(tree: &Tree, node: usize) -> Option<usize> {
+    match tree.cycle_breakers.get(&node) {
+        // If current node is the key, other is the value
+        Some(&cycle_breaker) => Some(cycle_breaker),
+        // If the current node is the value, find the pair with this node as the value and extract the key
+        None => tree
+            .cycle_breakers
+            .iter()
+            .find(|(_key, val)| **val == node)
+            .map(|(&key, _val)| key),
+    }
+}
+
+// Position of a node in space
+// Cycle-breaker nodes have no coordinates of their own, they sit halfway along the edge they replace
+pub fn node_position(tree: &Tree, node_coordinates: &[Vec3A], node: usize) -> Vec3A {
+    match cycle_breaker_partner(tree, node) {
+        Some(partner) => {
+            let parent: usize = tree.nodes.get(&node).unwrap().0;
+            let target: usize = tree.nodes.get(&partner).unwrap().0;
+            0.5 * (node_coordinates.get(parent).unwrap() + node_coordinates.get(target).unwrap())
+        }
+        None => *node_coordinates.get(node).unwrap(),
+    }
+}
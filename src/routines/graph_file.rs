@@ -0,0 +1,1081 @@
+use std::fmt::{Display, Formatter};
+
+use glam::Vec3A;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::{
+    geometry::ScaleError,
+    io::{EdgeOption, EdgeRow, ImportSettings, Wireframe, parse_edge_option},
+};
+
+// Arrays and objects nested deeper than this are rejected, as they are read recursively
+const MAX_JSON_DEPTH: usize = 64;
+
+// Node or edge of an imported graph, with its attributes as text
+#[derive(Debug, Clone, Default)]
+struct GraphElement {
+    ids: Vec<String>, // Id of a node, source and target of an edge
+    attributes: FxHashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    InvalidSyntax(usize), // Line numbers start at 1
+    TooDeep(usize),       // Nesting deeper than MAX_JSON_DEPTH, on this line
+    Empty,
+    MissingAttribute {
+        element: &'static str,
+        attribute: &'static str,
+    },
+    DuplicateNode(String),
+    UnknownNode(String),
+    MissingCoordinates(String),
+    InvalidCoordinate {
+        node: String,
+        value: String,
+    },
+    InvalidOption {
+        edge: (String, String),
+        option: String,
+    },
+    Disconnected,
+    Scale(ScaleError),
+}
+
+impl Display for GraphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::InvalidSyntax(line) => write!(f, "Invalid syntax on line {line}"),
+            GraphError::TooDeep(line) => write!(
+                f,
+                "Values nested more than {MAX_JSON_DEPTH} levels deep on line {line}"
+            ),
+            GraphError::Empty => write!(f, "No edges found"),
+            GraphError::MissingAttribute { element, attribute } => {
+                write!(f, "Missing '{attribute}' attribute of {element}")
+            }
+            GraphError::DuplicateNode(id) => write!(f, "Node '{id}' defined twice"),
+            GraphError::UnknownNode(id) => write!(f, "Unknown node '{id}'"),
+            GraphError::MissingCoordinates(id) => write!(
+                f,
+                "Node '{id}' has no coordinates, give x, y and z or pos attributes"
+            ),
+            GraphError::InvalidCoordinate { node, value } => {
+                write!(f, "Invalid coordinate '{value}' for node '{node}'")
+            }
+            GraphError::InvalidOption { edge, option } => {
+                write!(f, "Invalid option '{option}' on edge {} {}", edge.0, edge.1)
+            }
+            GraphError::Disconnected => write!(f, "The graph has several disconnected parts"),
+            GraphError::Scale(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+// Line of a byte offset, only worked out once an error is found
+fn line_at(input: &str, position: usize) -> usize {
+    input[..position].matches('\n').count() + 1
+}
+
+enum XmlEvent {
+    Start {
+        name: String,
+        attributes: FxHashMap<String, String>,
+        empty: bool, // Self-closing, no end event follows
+    },
+    End(String),
+    Text(String),
+}
+
+// Element names without their namespace prefix
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap()
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded: String = String::with_capacity(text.len());
+    let mut rest: &str = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity: Option<char> = rest.find(';').and_then(|end| match &rest[1..end] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            code => code
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .unwrap_or_else(|| code.strip_prefix('#').unwrap_or("").parse())
+                .ok()
+                .and_then(char::from_u32),
+        });
+        match entity {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[rest.find(';').unwrap() + 1..];
+            }
+            None => {
+                decoded.push('&'); // Left as written
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Attributes of a start tag, after its name
+fn xml_attributes(text: &str, line: usize) -> Result<FxHashMap<String, String>, GraphError> {
+    let mut attributes: FxHashMap<String, String> = FxHashMap::default();
+    let mut rest: &str = text.trim_start();
+    while !rest.is_empty() {
+        let (name, value) = rest
+            .split_once('=')
+            .ok_or(GraphError::InvalidSyntax(line))?;
+        let value: &str = value.trim_start();
+        let quote: char = value
+            .chars()
+            .next()
+            .filter(|quote| *quote == '"' || *quote == '\'')
+            .ok_or(GraphError::InvalidSyntax(line))?;
+        let end: usize = value[1..]
+            .find(quote)
+            .ok_or(GraphError::InvalidSyntax(line))?
+            + 1;
+        attributes.insert(name.trim().to_string(), decode_entities(&value[1..end]));
+        rest = value[end + 1..].trim_start();
+    }
+    Ok(attributes)
+}
+
+// Start and end tags and text, leaving out comments, declarations and processing instructions
+// The line is counted along the way, for the errors
+fn xml_events(input: &str) -> Result<Vec<XmlEvent>, GraphError> {
+    let mut events: Vec<XmlEvent> = Vec::new();
+    let mut position: usize = 0;
+    let mut line: usize = 1;
+    while position < input.len() {
+        let rest: &str = &input[position..];
+        let skip_to = |terminator: &str| {
+            rest.find(terminator)
+                .map(|end| end + terminator.len())
+                .ok_or(GraphError::InvalidSyntax(line))
+        };
+
+        let length: usize = if !rest.starts_with('<') {
+            let end: usize = rest.find('<').unwrap_or(rest.len());
+            events.push(XmlEvent::Text(decode_entities(&rest[..end])));
+            end
+        } else if rest.starts_with("<!--") {
+            skip_to("-->")?
+        } else if let Some(data) = rest.strip_prefix("<![CDATA[") {
+            let end: usize = skip_to("]]>")?;
+            events.push(XmlEvent::Text(data[..end - 12].to_string()));
+            end
+        } else if rest.starts_with("<?") {
+            skip_to("?>")?
+        } else if rest.starts_with("<!") {
+            skip_to(">")?
+        } else {
+            // Tag, ending at the first '>' outside quotes
+            let mut quote: Option<char> = None;
+            let end: usize = rest
+                .char_indices()
+                .find(|&(_, character)| match quote {
+                    Some(open) if character == open => {
+                        quote = None;
+                        false
+                    }
+                    Some(_) => false,
+                    None if character == '"' || character == '\'' => {
+                        quote = Some(character);
+                        false
+                    }
+                    None => character == '>',
+                })
+                .map(|(end, _)| end)
+                .ok_or(GraphError::InvalidSyntax(line))?;
+            if let Some(name) = rest[..end].strip_prefix("</") {
+                events.push(XmlEvent::End(local_name(name.trim()).to_string()));
+            } else {
+                let (tag, empty): (&str, bool) = match rest[1..end].strip_suffix('/') {
+                    Some(tag) => (tag, true),
+                    None => (&rest[1..end], false),
+                };
+                let (name, attributes): (&str, &str) =
+                    tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                if name.is_empty() {
+                    return Err(GraphError::InvalidSyntax(line));
+                }
+                events.push(XmlEvent::Start {
+                    name: local_name(name).to_string(),
+                    attributes: xml_attributes(attributes, line)?,
+                    empty,
+                });
+            }
+            end + 1
+        };
+        line += rest[..length].matches('\n').count();
+        position += length;
+    }
+    Ok(events)
+}
+
+// GraphML nodes and edges, attributes being named after their keys
+// Key defaults are applied to the nodes and edges that leave them out
+fn parse_graphml(input: &str) -> Result<(Vec<GraphElement>, Vec<GraphElement>), GraphError> {
+    let mut key_names: FxHashMap<String, String> = FxHashMap::default();
+    let mut defaults: Vec<(String, String, String)> = Vec::new(); // (domain, name, value)
+    let mut current_key: Option<(String, String)> = None; // (domain, name)
+    let mut open: Vec<(bool, GraphElement)> = Vec::new(); // Nested nodes of subgraphs, flagged if edges
+    let mut data: Option<(String, String)> = None; // (name, text)
+    let mut nodes: Vec<GraphElement> = Vec::new();
+    let mut edges: Vec<GraphElement> = Vec::new();
+    let mut in_graphml: bool = false;
+
+    let new_element = |is_edge: bool, attributes: &FxHashMap<String, String>| {
+        let (kind, names): (&'static str, &[&'static str]) = if is_edge {
+            ("edge", &["source", "target"])
+        } else {
+            ("node", &["id"])
+        };
+        Ok::<GraphElement, GraphError>(GraphElement {
+            ids: names
+                .iter()
+                .map(|name| {
+                    attributes
+                        .get(*name)
+                        .cloned()
+                        .ok_or(GraphError::MissingAttribute {
+                            element: kind,
+                            attribute: name,
+                        })
+                })
+                .collect::<Result<Vec<String>, GraphError>>()?,
+            attributes: FxHashMap::default(),
+        })
+    };
+
+    xml_events(input)?.into_iter().try_for_each(|event| {
+        match event {
+            XmlEvent::Start {
+                name,
+                attributes,
+                empty,
+            } => match name.as_str() {
+                "graphml" => in_graphml = true,
+                "key" => {
+                    let id: String =
+                        attributes
+                            .get("id")
+                            .cloned()
+                            .ok_or(GraphError::MissingAttribute {
+                                element: "key",
+                                attribute: "id",
+                            })?;
+                    let name: String = attributes.get("attr.name").cloned().unwrap_or(id.clone());
+                    key_names.insert(id, name.clone());
+                    let domain: String =
+                        attributes.get("for").cloned().unwrap_or("all".to_string());
+                    current_key = (!empty).then_some((domain, name));
+                }
+                "default" if current_key.is_some() => {
+                    data = Some((String::new(), String::new()));
+                }
+                "node" | "edge" => {
+                    let is_edge: bool = name == "edge";
+                    let element: GraphElement = new_element(is_edge, &attributes)?;
+                    if empty {
+                        if is_edge { &mut edges } else { &mut nodes }.push(element);
+                    } else {
+                        open.push((is_edge, element));
+                    }
+                }
+                "data" if !open.is_empty() && !empty => {
+                    let key: &String =
+                        attributes.get("key").ok_or(GraphError::MissingAttribute {
+                            element: "data",
+                            attribute: "key",
+                        })?;
+                    let name: String = key_names.get(key).unwrap_or(key).clone();
+                    data = Some((name, String::new()));
+                }
+                _ => (),
+            },
+            XmlEvent::Text(text) => {
+                if let Some((_, value)) = data.as_mut() {
+                    value.push_str(&text);
+                }
+            }
+            XmlEvent::End(name) => match name.as_str() {
+                "key" => current_key = None,
+                "default" => {
+                    if let (Some((domain, name)), Some((_, value))) =
+                        (current_key.as_ref(), data.take())
+                    {
+                        defaults.push((domain.clone(), name.clone(), value.trim().to_string()));
+                    }
+                }
+                "data" => {
+                    if let (Some((_, element)), Some((name, value))) =
+                        (open.last_mut(), data.take())
+                        && !value.trim().is_empty()
+                    {
+                        element.attributes.insert(name, value.trim().to_string());
+                    }
+                }
+                "node" | "edge" => {
+                    if let Some((is_edge, element)) = open.pop() {
+                        if is_edge { &mut edges } else { &mut nodes }.push(element);
+                    }
+                }
+                _ => (),
+            },
+        }
+        Ok::<(), GraphError>(())
+    })?;
+    if !in_graphml {
+        return Err(GraphError::InvalidSyntax(1));
+    }
+
+    defaults.iter().for_each(|(domain, name, value)| {
+        let apply = |elements: &mut Vec<GraphElement>| {
+            elements.iter_mut().for_each(|element| {
+                element
+                    .attributes
+                    .entry(name.clone())
+                    .or_insert(value.clone());
+            })
+        };
+        if domain == "node" || domain == "all" {
+            apply(&mut nodes);
+        }
+        if domain == "edge" || domain == "all" {
+            apply(&mut edges);
+        }
+    });
+    Ok((nodes, edges))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // Text of a scalar, or of the items of an array separated by spaces
+    fn text(&self) -> Option<String> {
+        match self {
+            JsonValue::Null | JsonValue::Object(_) => None,
+            JsonValue::Bool(value) => Some(value.to_string()),
+            JsonValue::Number(value) => Some(value.to_string()),
+            JsonValue::Text(text) => Some(text.clone()),
+            JsonValue::Array(items) => Some(
+                items
+                    .iter()
+                    .filter_map(JsonValue::text)
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            ),
+        }
+    }
+}
+
+fn hex_unit(characters: &mut std::str::CharIndices) -> Option<u16> {
+    let digits: String = characters
+        .by_ref()
+        .take(4)
+        .map(|(_, digit)| digit)
+        .collect();
+    u16::from_str_radix(&digits, 16)
+        .ok()
+        .filter(|_| digits.len() == 4)
+}
+
+struct JsonReader<'a> {
+    input: &'a str,
+    position: usize, // Byte offset
+    depth: usize,    // Arrays and objects open around the current value
+}
+
+impl<'a> JsonReader<'a> {
+    fn error(&self) -> GraphError {
+        GraphError::InvalidSyntax(line_at(self.input, self.position))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest: &str = &self.input[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), GraphError> {
+        if self.input[self.position..].starts_with(token) {
+            self.position += token.len();
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, GraphError> {
+        self.skip_whitespace();
+        match self.peek().ok_or_else(|| self.error())? {
+            '{' | '[' => {
+                if self.depth == MAX_JSON_DEPTH {
+                    return Err(GraphError::TooDeep(line_at(self.input, self.position)));
+                }
+                self.depth += 1;
+                let value: Result<JsonValue, GraphError> = if self.peek() == Some('{') {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                value
+            }
+            '"' => Ok(JsonValue::Text(self.string()?)),
+            't' => self.expect("true").map(|_| JsonValue::Bool(true)),
+            'f' => self.expect("false").map(|_| JsonValue::Bool(false)),
+            'n' => self.expect("null").map(|_| JsonValue::Null),
+            _ => {
+                let rest: &str = &self.input[self.position..];
+                let length: usize = rest
+                    .find(|character: char| {
+                        !(character.is_ascii_digit() || "+-.eE".contains(character))
+                    })
+                    .unwrap_or(rest.len());
+                let number: f64 = rest[..length].parse().map_err(|_| self.error())?;
+                self.position += length;
+                Ok(JsonValue::Number(number))
+            }
+        }
+    }
+
+    // Object members, from the opening brace
+    fn object(&mut self) -> Result<JsonValue, GraphError> {
+        self.position += 1;
+        let mut members: Vec<(String, JsonValue)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key: String = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    // Array items, from the opening bracket
+    fn array(&mut self) -> Result<JsonValue, GraphError> {
+        self.position += 1;
+        let mut items: Vec<JsonValue> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, GraphError> {
+        self.expect("\"")?;
+        let input: &'a str = self.input;
+        let mut text: String = String::new();
+        let mut characters = input[self.position..].char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => {
+                    self.position += index + 1;
+                    return Ok(text);
+                }
+                '\\' => match characters.next().map(|(_, escaped)| escaped) {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('b') => text.push('\u{8}'),
+                    Some('f') => text.push('\u{c}'),
+                    Some('u') => {
+                        let mut units: Vec<u16> =
+                            vec![hex_unit(&mut characters).ok_or_else(|| self.error())?];
+                        // Characters outside the basic plane are written as surrogate pairs
+                        let mut lookahead = characters.clone();
+                        if (0xD800..0xDC00).contains(units.first().unwrap())
+                            && lookahead.next().map(|(_, escape)| escape) == Some('\\')
+                            && lookahead.next().map(|(_, escape)| escape) == Some('u')
+                            && let Some(low) = hex_unit(&mut lookahead)
+                        {
+                            units.push(low);
+                            characters = lookahead;
+                        }
+                        text.extend(
+                            char::decode_utf16(units)
+                                .map(|decoded| decoded.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                        );
+                    }
+                    Some(escaped @ ('"' | '\\' | '/')) => text.push(escaped),
+                    _ => return Err(self.error()),
+                },
+                _ => text.push(character),
+            }
+        }
+        Err(self.error())
+    }
+}
+
+fn json_elements(
+    items: &[JsonValue],
+    kind: &'static str,
+    names: &[&'static str],
+) -> Result<Vec<GraphElement>, GraphError> {
+    items
+        .iter()
+        .map(|item| {
+            let JsonValue::Object(members) = item else {
+                return Err(GraphError::MissingAttribute {
+                    element: kind,
+                    attribute: names.first().unwrap(),
+                });
+            };
+            Ok(GraphElement {
+                ids: names
+                    .iter()
+                    .map(|name| {
+                        item.get(name).and_then(JsonValue::text).ok_or(
+                            GraphError::MissingAttribute {
+                                element: kind,
+                                attribute: name,
+                            },
+                        )
+                    })
+                    .collect::<Result<Vec<String>, GraphError>>()?,
+                attributes: members
+                    .iter()
+                    .filter(|(name, _)| !names.contains(&name.as_str()))
+                    .filter_map(|(name, value)| Some((name.clone(), value.text()?)))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+// Node-link JSON, with edges listed under links or edges depending on the NetworkX version
+fn parse_node_link(input: &str) -> Result<(Vec<GraphElement>, Vec<GraphElement>), GraphError> {
+    let mut reader: JsonReader = JsonReader {
+        input,
+        position: 0,
+        depth: 0,
+    };
+    let graph: JsonValue = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position < input.len() {
+        return Err(reader.error());
+    }
+
+    let Some(JsonValue::Array(nodes)) = graph.get("nodes") else {
+        return Err(GraphError::MissingAttribute {
+            element: "graph",
+            attribute: "nodes",
+        });
+    };
+    let Some(JsonValue::Array(links)) = graph.get("links").or(graph.get("edges")) else {
+        return Err(GraphError::MissingAttribute {
+            element: "graph",
+            attribute: "links",
+        });
+    };
+    Ok((
+        json_elements(nodes, "node", &["id"])?,
+        json_elements(links, "link", &["source", "target"])?,
+    ))
+}
+
+// Coordinates from x, y and optional z attributes, or from a pos or position list
+fn node_position(node: &GraphElement) -> Result<Vec3A, GraphError> {
+    let id: &String = node.ids.first().unwrap();
+    let invalid = |value: &str| GraphError::InvalidCoordinate {
+        node: id.clone(),
+        value: value.to_string(),
+    };
+    let coordinate = |value: &str| value.trim().parse::<f32>().map_err(|_| invalid(value));
+
+    let values: Vec<f32> =
+        if let (Some(x), Some(y)) = (node.attributes.get("x"), node.attributes.get("y")) {
+            [Some(x), Some(y), node.attributes.get("z")]
+                .into_iter()
+                .flatten()
+                .map(|value| coordinate(value))
+                .collect::<Result<Vec<f32>, GraphError>>()?
+        } else if let Some(position) = ["pos", "position"]
+            .iter()
+            .find_map(|name| node.attributes.get(*name))
+        {
+            let values: Vec<f32> = position
+                .split(|character: char| {
+                    character == ',' || character.is_whitespace() || "[]()".contains(character)
+                })
+                .filter(|value| !value.is_empty())
+                .map(coordinate)
+                .collect::<Result<Vec<f32>, GraphError>>()?;
+            if !(2..=3).contains(&values.len()) {
+                return Err(invalid(position));
+            }
+            values
+        } else {
+            return Err(GraphError::MissingCoordinates(id.clone()));
+        };
+    Ok(Vec3A::new(
+        *values.first().unwrap(),
+        *values.get(1).unwrap(),
+        values.get(2).copied().unwrap_or(0.0),
+    ))
+}
+
+// Edge options from pin and module attributes, written <offset>:<value>, or from an options attribute
+fn link_options(link: &GraphElement) -> Result<Vec<EdgeOption>, GraphError> {
+    ["pin", "module", "options"]
+        .iter()
+        .filter_map(|name| Some((*name, link.attributes.get(*name)?)))
+        .flat_map(|(name, value)| {
+            value.split_whitespace().map(move |item| match name {
+                "options" => item.to_string(),
+                _ => format!("{name}={item}"),
+            })
+        })
+        .map(|option| {
+            parse_edge_option(&option, 0).map_err(|_| GraphError::InvalidOption {
+                edge: (
+                    link.ids.first().unwrap().clone(),
+                    link.ids.get(1).unwrap().clone(),
+                ),
+                option,
+            })
+        })
+        .collect()
+}
+
+// GraphML or node-link JSON, as written by NetworkX or igraph, told apart by the first character
+// Nodes become vertices in the order they are listed, leaving out those without edges
+// Self-loops are skipped, and of parallel edges only the first is kept with its options
+// Rows are returned in the order of the wireframe edges
+pub fn import_graph(
+    input: &str,
+    settings: &ImportSettings,
+) -> Result<(Wireframe, Vec<EdgeRow>), GraphError> {
+    let (nodes, links) = if input.trim_start().starts_with('<') {
+        parse_graphml(input)?
+    } else {
+        parse_node_link(input)?
+    };
+
+    let mut indices: FxHashMap<&str, usize> = FxHashMap::default();
+    nodes.iter().enumerate().try_for_each(|(index, node)| {
+        let id: &str = node.ids.first().unwrap();
+        match indices.insert(id, index) {
+            Some(_) => Err(GraphError::DuplicateNode(id.to_string())),
+            None => Ok(()),
+        }
+    })?;
+    let node_index = |id: &String| {
+        indices
+            .get(id.as_str())
+            .copied()
+            .ok_or_else(|| GraphError::UnknownNode(id.clone()))
+    };
+
+    let mut known: FxHashSet<(usize, usize)> = FxHashSet::default();
+    let mut kept: Vec<((usize, usize), &GraphElement)> = Vec::new();
+    links.iter().try_for_each(|link| {
+        let (origin, destination): (usize, usize) = (
+            node_index(link.ids.first().unwrap())?,
+            node_index(link.ids.get(1).unwrap())?,
+        );
+        if origin != destination && known.insert((origin.min(destination), origin.max(destination)))
+        {
+            kept.push(((origin, destination), link));
+        }
+        Ok::<(), GraphError>(())
+    })?;
+    if kept.is_empty() {
+        return Err(GraphError::Empty);
+    }
+
+    let mut used: Vec<bool> = vec![false; nodes.len()];
+    kept.iter().for_each(|&((origin, destination), _)| {
+        *used.get_mut(origin).unwrap() = true;
+        *used.get_mut(destination).unwrap() = true;
+    });
+    let mut vertices: Vec<Vec3A> = Vec::new();
+    let mut new_indices: Vec<usize> = vec![0; nodes.len()];
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *used.get(*index).unwrap())
+        .try_for_each(|(index, node)| {
+            *new_indices.get_mut(index).unwrap() = vertices.len();
+            vertices.push(node_position(node)?);
+            Ok::<(), GraphError>(())
+        })?;
+    let mut rows: Vec<EdgeRow> = kept
+        .iter()
+        .map(|&((origin, destination), link)| {
+            Ok(EdgeRow {
+                edge: (
+                    *new_indices.get(origin).unwrap(),
+                    *new_indices.get(destination).unwrap(),
+                ),
+                options: link_options(link)?,
+            })
+        })
+        .collect::<Result<Vec<EdgeRow>, GraphError>>()?;
+
+    let mut wireframe: Wireframe =
+        Wireframe::from_rows(vertices, &mut rows).ok_or(GraphError::Disconnected)?;
+    wireframe.adjust(settings).map_err(GraphError::Scale)?;
+    Ok((wireframe, rows))
+}
+
+pub fn is_graph_file(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("graphml") || extension.eq_ignore_ascii_case("json")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3a;
+
+    use super::*;
+    use crate::routines::io::{
+        format_edge_table, format_vertex_table, parse_edges, parse_node_coordinates,
+    };
+
+    // Square pyramid written by NetworkX 3.6 with write_graphml, a pin and a module on its first edge
+    const NETWORKX_GRAPHML: &str = r#"<?xml version='1.0' encoding='utf-8'?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">
+  <key id="d4" for="edge" attr.name="module" attr.type="string" />
+  <key id="d3" for="edge" attr.name="pin" attr.type="string" />
+  <key id="d2" for="node" attr.name="z" attr.type="double" />
+  <key id="d1" for="node" attr.name="y" attr.type="double" />
+  <key id="d0" for="node" attr.name="x" attr.type="double" />
+  <graph edgedefault="undirected">
+    <node id="0">
+      <data key="d0">-10.0</data>
+      <data key="d1">10.0</data>
+      <data key="d2">0.0</data>
+    </node>
+    <node id="1">
+      <data key="d0">10.0</data>
+      <data key="d1">10.0</data>
+      <data key="d2">0.0</data>
+    </node>
+    <node id="2">
+      <data key="d0">10.0</data>
+      <data key="d1">-10.0</data>
+      <data key="d2">0.0</data>
+    </node>
+    <node id="3">
+      <data key="d0">-10.0</data>
+      <data key="d1">-10.0</data>
+      <data key="d2">0.0</data>
+    </node>
+    <node id="4">
+      <data key="d0">0.0</data>
+      <data key="d1">0.0</data>
+      <data key="d2">20.0</data>
+    </node>
+    <edge source="0" target="4">
+      <data key="d3">0:GGA</data>
+      <data key="d4">2:MS2</data>
+    </edge>
+    <edge source="0" target="1" />
+    <edge source="0" target="3" />
+    <edge source="1" target="4" />
+    <edge source="1" target="2" />
+    <edge source="2" target="4" />
+    <edge source="2" target="3" />
+    <edge source="3" target="4" />
+  </graph>
+</graphml>
+"#;
+
+    // The same pyramid written by NetworkX 3.6 with node_link_data, positions as pos lists
+    const NETWORKX_NODE_LINK: &str = r#"{"directed": false, "multigraph": false, "graph": {},
+"nodes": [{"pos": [-10, 10, 0], "id": 0}, {"pos": [10, 10, 0], "id": 1}, {"pos": [10, -10, 0], "id": 2},
+  {"pos": [-10, -10, 0], "id": 3}, {"pos": [0, 0, 20], "id": 4}],
+"links": [{"options": "pin=0:GGA module=2:MS2", "source": 0, "target": 4}, {"source": 0, "target": 1},
+  {"source": 0, "target": 3}, {"source": 1, "target": 4}, {"source": 1, "target": 2},
+  {"source": 2, "target": 4}, {"source": 2, "target": 3}, {"source": 3, "target": 4}]}"#;
+
+    // Triangle in the layout igraph writes: named nodes, tags wrapped over lines, a comment and a key default
+    const IGRAPH_GRAPHML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns"
+         xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+         xsi:schemaLocation="http://graphml.graphdrawing.org/xmlns
+         http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd">
+<!-- Created by igraph -->
+  <key id="v_x" for="node" attr.name="x" attr.type="double"/>
+  <key id="v_y" for="node" attr.name="y" attr.type="double"/>
+  <key id="v_z" for="node" attr.name="z" attr.type="double">
+    <default>0</default>
+  </key>
+  <graph id="G" edgedefault="undirected">
+    <node id="n0">
+      <data key="v_x">0</data>
+      <data key="v_y">0</data>
+    </node>
+    <node id="n1">
+      <data key="v_x">20</data>
+      <data key="v_y">0</data>
+    </node>
+    <node id="n2">
+      <data key="v_x">10</data>
+      <data key="v_y">17.32</data>
+      <data key="v_z">5</data>
+    </node>
+    <edge source="n0" target="n1">
+    </edge>
+    <edge source="n1" target="n2">
+    </edge>
+    <edge source="n2" target="n0">
+    </edge>
+  </graph>
+</graphml>
+"#;
+
+    fn pyramid() -> Vec<Vec3A> {
+        vec![
+            vec3a(-10.0, 10.0, 0.0),
+            vec3a(10.0, 10.0, 0.0),
+            vec3a(10.0, -10.0, 0.0),
+            vec3a(-10.0, -10.0, 0.0),
+            vec3a(0.0, 0.0, 20.0),
+        ]
+    }
+
+    fn undirected(edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let mut edges: Vec<(usize, usize)> = edges
+            .iter()
+            .map(|&(origin, destination)| (origin.min(destination), origin.max(destination)))
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    fn check_pyramid(input: &str) {
+        let (wireframe, rows) = import_graph(input, &ImportSettings::default()).unwrap();
+        assert_eq!(wireframe.vertices, pyramid());
+        assert_eq!(
+            undirected(&wireframe.edges),
+            vec![
+                (0, 1),
+                (0, 3),
+                (0, 4),
+                (1, 2),
+                (1, 4),
+                (2, 3),
+                (2, 4),
+                (3, 4)
+            ]
+        );
+        // Rows follow the edges, each in the direction of the file
+        assert_eq!(
+            undirected(&rows.iter().map(|row| row.edge).collect::<Vec<_>>()),
+            undirected(&wireframe.edges)
+        );
+        rows.iter()
+            .zip(&wireframe.edges)
+            .for_each(|(row, &(origin, destination))| {
+                assert!(row.edge == (origin, destination) || row.edge == (destination, origin));
+            });
+        let pinned: &EdgeRow = rows.iter().find(|row| !row.options.is_empty()).unwrap();
+        assert_eq!(pinned.edge, (0, 4));
+        assert_eq!(
+            pinned.options,
+            vec![
+                parse_edge_option("pin=0:GGA", 0).unwrap(),
+                parse_edge_option("module=2:MS2", 0).unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn networkx_graphml() {
+        check_pyramid(NETWORKX_GRAPHML);
+    }
+
+    #[test]
+    fn networkx_node_link() {
+        check_pyramid(NETWORKX_NODE_LINK);
+        // Older versions list the edges under links, newer ones may use edges
+        check_pyramid(&NETWORKX_NODE_LINK.replace("\"links\"", "\"edges\""));
+    }
+
+    #[test]
+    fn igraph_graphml() {
+        let (wireframe, rows) = import_graph(IGRAPH_GRAPHML, &ImportSettings::default()).unwrap();
+        assert_eq!(
+            wireframe.vertices,
+            vec![
+                vec3a(0.0, 0.0, 0.0),
+                vec3a(20.0, 0.0, 0.0),
+                vec3a(10.0, 17.32, 5.0)
+            ]
+        );
+        assert_eq!(undirected(&wireframe.edges), vec![(0, 1), (0, 2), (1, 2)]);
+        assert!(rows.iter().all(|row| row.options.is_empty()));
+    }
+
+    #[test]
+    fn graphs_round_trip_through_tables() {
+        [NETWORKX_GRAPHML, NETWORKX_NODE_LINK, IGRAPH_GRAPHML]
+            .into_iter()
+            .for_each(|input| {
+                let (wireframe, rows) = import_graph(input, &ImportSettings::default()).unwrap();
+                ['\t', ','].into_iter().for_each(|separator| {
+                    let vertices: Vec<Vec3A> = parse_node_coordinates(&format_vertex_table(
+                        &wireframe.vertices,
+                        separator,
+                    ))
+                    .unwrap();
+                    let mut read_rows: Vec<EdgeRow> =
+                        parse_edges(&format_edge_table(&rows, separator), vertices.len()).unwrap();
+                    assert_eq!(read_rows, rows);
+                    let read: Wireframe = Wireframe::from_rows(vertices, &mut read_rows).unwrap();
+                    assert_eq!(read.vertices, wireframe.vertices);
+                    assert_eq!(read.edges, wireframe.edges);
+                });
+            });
+    }
+
+    #[test]
+    fn entities_and_character_data_are_decoded() {
+        let input: &str = r#"<graphml><key id="p" for="edge" attr.name="pin"/><graph>
+<node id="a&amp;b" x="0"><data key="x">0</data><data key="y">0</data></node>
+<node id="c"><data key="x">&#49;0</data><data key="y"><![CDATA[0]]></data></node>
+<edge source="a&amp;b" target="c"><data key="p">0:G&#x47;A</data></edge>
+</graph></graphml>"#;
+        let (wireframe, rows) = import_graph(input, &ImportSettings::default()).unwrap();
+        assert_eq!(wireframe.vertices, vec![Vec3A::ZERO, vec3a(10.0, 0.0, 0.0)]);
+        assert_eq!(
+            rows.first().unwrap().options,
+            vec![parse_edge_option("pin=0:GGA", 0).unwrap()]
+        );
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        // Comment left open
+        let unclosed: String = NETWORKX_GRAPHML.replace("<node id=\"3\">", "<!-- <node id=\"3\">");
+        assert_eq!(
+            import_graph(&unclosed, &ImportSettings::default()).unwrap_err(),
+            GraphError::InvalidSyntax(24)
+        );
+        let trailing_comma: String = NETWORKX_NODE_LINK.replace("\"id\": 4}", "\"id\": 4,}");
+        assert_eq!(
+            import_graph(&trailing_comma, &ImportSettings::default()).unwrap_err(),
+            GraphError::InvalidSyntax(3)
+        );
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected() {
+        let settings: ImportSettings = ImportSettings::default();
+        let error = |input: &str| import_graph(input, &settings).unwrap_err();
+        assert_eq!(
+            error(r#"{"nodes": []}"#),
+            GraphError::MissingAttribute {
+                element: "graph",
+                attribute: "links"
+            }
+        );
+        assert_eq!(
+            error(
+                r#"{"nodes": [{"id": 0, "x": 0, "y": 0}, {"id": 0, "x": 1, "y": 0}], "links": []}"#
+            ),
+            GraphError::DuplicateNode("0".to_string())
+        );
+        assert_eq!(
+            error(
+                r#"{"nodes": [{"id": 0, "x": 0, "y": 0}], "links": [{"source": 0, "target": 1}]}"#
+            ),
+            GraphError::UnknownNode("1".to_string())
+        );
+        assert_eq!(
+            error(r#"{"nodes": [{"id": 0}, {"id": 1}], "links": [{"source": 0, "target": 1}]}"#),
+            GraphError::MissingCoordinates("0".to_string())
+        );
+        assert_eq!(
+            error(
+                r#"{"nodes": [{"id": 0, "x": 0, "y": 0}, {"id": 1, "x": 1, "y": 0}, {"id": 2, "x": 0, "y": 1}, {"id": 3, "x": 1, "y": 1}],
+                "links": [{"source": 0, "target": 1}, {"source": 2, "target": 3}]}"#
+            ),
+            GraphError::Disconnected
+        );
+        assert_eq!(error("<graph></graph>"), GraphError::InvalidSyntax(1));
+        assert_eq!(
+            error("<graphml><graph><node/></graph></graphml>"),
+            GraphError::MissingAttribute {
+                element: "node",
+                attribute: "id"
+            }
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let depth: usize = 100_000;
+        let input: String = format!("{{\"nodes\": {}{}}}", "[".repeat(depth), "]".repeat(depth));
+        assert_eq!(
+            import_graph(&input, &ImportSettings::default()).unwrap_err(),
+            GraphError::TooDeep(1)
+        );
+    }
+}
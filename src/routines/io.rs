@@ -1 +1,1855 @@
-// TODO
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Formatter},
+};
+
+use glam::Vec3A;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::{
+    analysis::{DotBracketError, parse_dot_bracket},
+    constraints::{PinTarget, SequencePin},
+    geometry::{ScaleError, ScaleTarget, find_scale_factor, recentre, rescale},
+    model::{NucleotideFrame, phosphate},
+    motifs::{FunctionalModule, InvalidModuleError, ModuleInsertion},
+    nucleotide::{IupacCode, Nucleotide, RnaSequence},
+    structure::TargetStructure,
+    template::{DnaTemplate, FeatureKind},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidNodeDataError {
+    InvalidLine(usize), // Line numbers start at 1
+    InvalidValue { line: usize, value: String },
+    DuplicateVertex { line: usize, id: usize },
+    VertexOutOfRange { line: usize, id: usize }, // Vertex ids must run from 0 without gaps
+    UnknownVertex { line: usize, id: usize },
+    InvalidOption { line: usize, option: String },
+}
+
+impl Display for InvalidNodeDataError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidNodeDataError::InvalidLine(line) => write!(f, "Invalid line {line}"),
+            InvalidNodeDataError::InvalidValue { line, value } => {
+                write!(f, "Invalid value '{value}' on line {line}")
+            }
+            InvalidNodeDataError::DuplicateVertex { line, id } => {
+                write!(f, "Vertex {id} defined again on line {line}")
+            }
+            InvalidNodeDataError::VertexOutOfRange { line, id } => write!(
+                f,
+                "Vertex {id} on line {line} is out of range, ids must run from 0 without gaps"
+            ),
+            InvalidNodeDataError::UnknownVertex { line, id } => {
+                write!(f, "Unknown vertex {id} on line {line}")
+            }
+            InvalidNodeDataError::InvalidOption { line, option } => {
+                write!(f, "Invalid edge option '{option}' on line {line}")
+            }
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str, line: usize) -> Result<T, InvalidNodeDataError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| InvalidNodeDataError::InvalidValue {
+            line,
+            value: value.trim().to_string(),
+        })
+}
+
+// Lines of a vertex or edge list, without comments
+fn data_lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    numbered_lines(input).filter(|(_, text)| !text.starts_with('#'))
+}
+
+// Fields of a table row, separated by tabs, or else by commas
+fn row_fields(text: &str) -> Vec<&str> {
+    let separator: char = if text.contains('\t') { '\t' } else { ',' };
+    text.split(separator).map(str::trim).collect()
+}
+
+// Table header, recognised by a first field that is not a number
+fn is_header(fields: &[&str]) -> bool {
+    fields
+        .first()
+        .is_some_and(|field| field.parse::<f32>().is_err())
+}
+
+// Groups of comma separated values written between the given brackets, as in [x, y, z] or (origin, destination)
+fn bracket_groups(text: &str, opening: char, closing: char) -> Vec<&str> {
+    text.split(closing)
+        .map(|group| group.trim().trim_start_matches(opening))
+        .filter(|group| !group.is_empty())
+        .collect()
+}
+
+// Vertices are written as [x, y, z], or as rows of a table of id, x, y and z, separated by commas or tabs
+// Table ids must run from 0 without gaps, in any order, and bracketed vertices are numbered in order
+pub fn parse_node_coordinates(input: &str) -> Result<Vec<Vec3A>, InvalidNodeDataError> {
+    let mut vertices: Vec<(usize, usize, Vec3A)> = Vec::new(); // Line, id and coordinates
+    data_lines(input)
+        .enumerate()
+        .try_for_each(|(row, (line, text))| {
+            if text.starts_with('[') {
+                return bracket_groups(text, '[', ']')
+                    .into_iter()
+                    .try_for_each(|group| {
+                        let values: Vec<f32> = group
+                            .split(',')
+                            .map(|value| parse_value(value, line))
+                            .collect::<Result<Vec<f32>, InvalidNodeDataError>>()?;
+                        if values.len() != 3 {
+                            return Err(InvalidNodeDataError::InvalidLine(line));
+                        }
+                        vertices.push((line, vertices.len(), Vec3A::from_slice(&values)));
+                        Ok(())
+                    });
+            }
+
+            let fields: Vec<&str> = row_fields(text);
+            if row == 0 && is_header(&fields) {
+                return Ok(());
+            }
+            match fields.as_slice() {
+                [id, x, y, z] => {
+                    let position: Vec3A = Vec3A::new(
+                        parse_value(x, line)?,
+                        parse_value(y, line)?,
+                        parse_value(z, line)?,
+                    );
+                    vertices.push((line, parse_value(id, line)?, position));
+                    Ok(())
+                }
+                _ => Err(InvalidNodeDataError::InvalidLine(line)),
+            }
+        })?;
+
+    let mut coordinates: Vec<Option<Vec3A>> = vec![None; vertices.len()];
+    vertices
+        .into_iter()
+        .try_for_each(|(line, id, position)| match coordinates.get_mut(id) {
+            Some(Some(_)) => Err(InvalidNodeDataError::DuplicateVertex { line, id }),
+            Some(slot) => {
+                *slot = Some(position);
+                Ok(())
+            }
+            None => Err(InvalidNodeDataError::VertexOutOfRange { line, id }),
+        })?;
+    // Every id is in range and used once, so every vertex is defined
+    Ok(coordinates.into_iter().flatten().collect())
+}
+
+pub fn format_node_coordinates(coordinates: &[Vec3A]) -> String {
+    coordinates
+        .iter()
+        .map(|pos| format!("[{}, {}, {}]\n", pos.x, pos.y, pos.z))
+        .collect()
+}
+
+// Design options given with an edge, applied to the strand travelling from its origin to its destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdgeOption {
+    Pin {
+        offset: usize,
+        pattern: Vec<IupacCode>,
+    }, // Written pin=<offset>:<IUPAC sequence>
+    Module {
+        offset: usize,
+        name: String,
+    }, // Written module=<offset>:<name>
+}
+
+impl Display for EdgeOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EdgeOption::Pin { offset, pattern } => {
+                write!(f, "pin={offset}:")?;
+                pattern.iter().try_for_each(|code| write!(f, "{code}"))
+            }
+            EdgeOption::Module { offset, name } => write!(f, "module={offset}:{name}"),
+        }
+    }
+}
+
+pub fn parse_edge_option(option: &str, line: usize) -> Result<EdgeOption, InvalidNodeDataError> {
+    let invalid = || InvalidNodeDataError::InvalidOption {
+        line,
+        option: option.to_string(),
+    };
+    let (key, value): (&str, &str) = option.split_once('=').ok_or_else(invalid)?;
+    let (offset, value): (&str, &str) = value.split_once(':').ok_or_else(invalid)?;
+    let offset: usize = offset.parse().map_err(|_| invalid())?;
+    match key {
+        "pin" => Ok(EdgeOption::Pin {
+            offset,
+            pattern: value
+                .chars()
+                .map(IupacCode::try_from)
+                .collect::<Result<Vec<IupacCode>, _>>()
+                .map_err(|_| invalid())?,
+        }),
+        "module" if !value.is_empty() => Ok(EdgeOption::Module {
+            offset,
+            name: value.to_string(),
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdgeRow {
+    pub edge: (usize, usize), // (origin, destination)
+    pub options: Vec<EdgeOption>,
+}
+
+// Edges are written as (origin, destination), or as rows of a table of origin, destination and options
+// Options are separated by spaces or further fields, as in `0,1,pin=0:GGA module=4:MS2`
+pub fn parse_edges(input: &str, vertex_count: usize) -> Result<Vec<EdgeRow>, InvalidNodeDataError> {
+    let vertex = |value: &str, line: usize| {
+        let id: usize = parse_value(value, line)?;
+        if id < vertex_count {
+            Ok(id)
+        } else {
+            Err(InvalidNodeDataError::UnknownVertex { line, id })
+        }
+    };
+
+    let mut rows: Vec<EdgeRow> = Vec::new();
+    data_lines(input)
+        .enumerate()
+        .try_for_each(|(row, (line, text))| {
+            if text.starts_with('(') {
+                return bracket_groups(text, '(', ')').into_iter().try_for_each(
+                    |group| match group.split(',').collect::<Vec<&str>>()[..] {
+                        [origin, destination] => {
+                            rows.push(EdgeRow {
+                                edge: (vertex(origin, line)?, vertex(destination, line)?),
+                                options: Vec::new(),
+                            });
+                            Ok(())
+                        }
+                        _ => Err(InvalidNodeDataError::InvalidLine(line)),
+                    },
+                );
+            }
+
+            let fields: Vec<&str> = row_fields(text);
+            if row == 0 && is_header(&fields) {
+                return Ok(());
+            }
+            match fields.as_slice() {
+                [origin, destination, options @ ..] => {
+                    rows.push(EdgeRow {
+                        edge: (vertex(origin, line)?, vertex(destination, line)?),
+                        options: options
+                            .iter()
+                            .flat_map(|field| field.split_whitespace())
+                            .map(|option| parse_edge_option(option, line))
+                            .collect::<Result<Vec<EdgeOption>, InvalidNodeDataError>>()?,
+                    });
+                    Ok(())
+                }
+                _ => Err(InvalidNodeDataError::InvalidLine(line)),
+            }
+        })?;
+    Ok(rows)
+}
+
+// Pins given as edge options
+pub fn edge_pins(rows: &[EdgeRow]) -> Vec<SequencePin> {
+    rows.iter()
+        .flat_map(|row| {
+            row.options.iter().filter_map(|option| match option {
+                EdgeOption::Pin { offset, pattern } => Some(SequencePin {
+                    target: PinTarget::Edge(row.edge),
+                    offset: *offset,
+                    pattern: pattern.clone(),
+                }),
+                EdgeOption::Module { .. } => None,
+            })
+        })
+        .collect()
+}
+
+// Module insertions given as edge options, modules being looked up by name in the library
+pub fn edge_modules(
+    rows: &[EdgeRow],
+    library: &[FunctionalModule],
+) -> Result<Vec<ModuleInsertion>, InvalidModuleError> {
+    rows.iter()
+        .flat_map(|row| {
+            row.options.iter().filter_map(|option| match option {
+                EdgeOption::Module { offset, name } => Some(ModuleInsertion::parse(
+                    &format!("edge {} {} {offset} {name}", row.edge.0, row.edge.1),
+                    library,
+                )),
+                EdgeOption::Pin { .. } => None,
+            })
+        })
+        .collect()
+}
+
+// Vertex table of id, x, y and z, with a header, separated by commas or tabs
+pub fn format_vertex_table(coordinates: &[Vec3A], separator: char) -> String {
+    let mut table: String = ["id", "x", "y", "z"].join(&separator.to_string()) + "\n";
+    coordinates.iter().enumerate().for_each(|(id, pos)| {
+        table.push_str(&format!(
+            "{id}{separator}{}{separator}{}{separator}{}\n",
+            pos.x, pos.y, pos.z
+        ))
+    });
+    table
+}
+
+// Edge table of origin, destination and options, with a header, separated by commas or tabs
+pub fn format_edge_table(rows: &[EdgeRow], separator: char) -> String {
+    let mut table: String =
+        ["origin", "destination", "options"].join(&separator.to_string()) + "\n";
+    rows.iter().for_each(|row| {
+        let options: Vec<String> = row.options.iter().map(EdgeOption::to_string).collect();
+        table.push_str(&format!(
+            "{}{separator}{}{separator}{}\n",
+            row.edge.0,
+            row.edge.1,
+            options.join(" ")
+        ))
+    });
+    table
+}
+
+pub fn format_edges(edges: &[(usize, usize)]) -> String {
+    edges
+        .iter()
+        .map(|(origin, destination)| format!("({origin}, {destination})\n"))
+        .collect()
+}
+
+// Vertices and edges of a structure, with the faces of the mesh it was imported from if any
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wireframe {
+    pub vertices: Vec<Vec3A>,
+    pub edges: Vec<(usize, usize)>, // Edges shared by several faces are listed once
+    pub faces: Vec<Vec<usize>>,
+}
+
+// Adjustments applied to an imported structure
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportSettings {
+    pub recentre: bool,             // Move the centroid to the origin
+    pub scale: Option<ScaleTarget>, // Rescale about the centroid after import
+}
+
+impl Wireframe {
+    // Edges of faces and polylines, from vertex indices in the order they are listed
+    fn from_elements(vertices: Vec<Vec3A>, faces: Vec<Vec<usize>>, lines: &[Vec<usize>]) -> Self {
+        let mut known: FxHashSet<(usize, usize)> = FxHashSet::default();
+        let mut edges: Vec<(usize, usize)> = Vec::new();
+        let face_edges = faces.iter().flat_map(|face| {
+            face.iter()
+                .zip(face.iter().cycle().skip(1))
+                .take(if face.len() > 2 { face.len() } else { 1 })
+        });
+        let line_edges = lines
+            .iter()
+            .flat_map(|line| line.iter().zip(line.iter().skip(1)));
+        face_edges
+            .chain(line_edges)
+            .filter(|(origin, destination)| origin != destination)
+            .for_each(|(&origin, &destination)| {
+                if known.insert((origin.min(destination), origin.max(destination))) {
+                    edges.push((origin, destination));
+                }
+            });
+
+        Wireframe {
+            vertices,
+            edges,
+            faces,
+        }
+    }
+
+    // Vertices used by no edge, such as reference points left in the modelling tool, are removed
+    fn remove_loose_vertices(&mut self) {
+        let mut used: Vec<bool> = vec![false; self.vertices.len()];
+        self.edges.iter().for_each(|&(origin, destination)| {
+            *used.get_mut(origin).unwrap() = true;
+            *used.get_mut(destination).unwrap() = true;
+        });
+        let mut new_indices: Vec<usize> = Vec::with_capacity(used.len());
+        let mut count: usize = 0;
+        used.iter().for_each(|used| {
+            new_indices.push(count);
+            count += usize::from(*used);
+        });
+
+        let mut kept = used.iter();
+        self.vertices.retain(|_| *kept.next().unwrap());
+        let reindex = |index: &mut usize| *index = *new_indices.get(*index).unwrap();
+        self.edges.iter_mut().for_each(|(origin, destination)| {
+            reindex(origin);
+            reindex(destination);
+        });
+        self.faces.iter_mut().flatten().for_each(reindex);
+    }
+
+    // Edges put in the order the tree is grown, returns false if the wireframe is in several pieces
+    fn order_edges(&mut self) -> bool {
+        match order_edges(&self.edges, self.vertices.len()) {
+            Some(ordered) => {
+                self.edges = ordered;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Wireframe of typed or tabulated vertices and edges, the rows being sorted in the order of its edges
+    // Each row keeps the direction it was given in, as its pins are read along it
+    // Returns None if the edges are in several pieces
+    pub fn from_rows(vertices: Vec<Vec3A>, rows: &mut [EdgeRow]) -> Option<Self> {
+        let edges: Vec<(usize, usize)> = rows.iter().map(|row| row.edge).collect();
+        let mut wireframe: Wireframe = Wireframe {
+            vertices,
+            edges,
+            faces: Vec::new(),
+        };
+        if !wireframe.order_edges() {
+            return None;
+        }
+        sort_edge_rows(rows, &wireframe.edges);
+        Some(wireframe)
+    }
+
+    pub fn adjust(&mut self, settings: &ImportSettings) -> Result<(), ScaleError> {
+        if settings.recentre {
+            recentre(&mut self.vertices);
+        }
+        if let Some(target) = settings.scale {
+            let factor: f32 = find_scale_factor(&self.edges, &self.vertices, target)?;
+            rescale(&mut self.vertices, factor);
+        }
+        Ok(())
+    }
+}
+
+// The tree is grown from its first edge, so every edge must start from a vertex already reached
+// Edges are listed breadth-first from the vertex with the most edges, and oriented away from it
+// Edges listed twice, in either direction, are kept once
+// Returns None if some edges cannot be reached, the edges being in several pieces
+pub fn order_edges(edges: &[(usize, usize)], vertex_count: usize) -> Option<Vec<(usize, usize)>> {
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    let mut known: FxHashSet<(usize, usize)> = FxHashSet::default();
+    edges.iter().for_each(|&(origin, destination)| {
+        if known.insert((origin.min(destination), origin.max(destination))) {
+            neighbours.get_mut(origin).unwrap().push(destination);
+            if origin != destination {
+                neighbours.get_mut(destination).unwrap().push(origin);
+            }
+        }
+    });
+    let Some(root) =
+        (0..neighbours.len()).max_by_key(|vertex| neighbours.get(*vertex).unwrap().len())
+    else {
+        return edges.is_empty().then(Vec::new);
+    };
+
+    let mut reached: Vec<bool> = vec![false; neighbours.len()];
+    *reached.get_mut(root).unwrap() = true;
+    let mut queue: VecDeque<usize> = VecDeque::from([root]);
+    let mut placed: FxHashSet<(usize, usize)> = FxHashSet::default();
+    let mut ordered: Vec<(usize, usize)> = Vec::with_capacity(known.len());
+    while let Some(vertex) = queue.pop_front() {
+        neighbours
+            .get(vertex)
+            .unwrap()
+            .iter()
+            .for_each(|&neighbour| {
+                if placed.insert((vertex.min(neighbour), vertex.max(neighbour))) {
+                    ordered.push((vertex, neighbour));
+                    if !*reached.get(neighbour).unwrap() {
+                        *reached.get_mut(neighbour).unwrap() = true;
+                        queue.push_back(neighbour);
+                    }
+                }
+            });
+    }
+
+    (ordered.len() == known.len()).then_some(ordered)
+}
+
+// Rows sorted in the order of the edges, in either direction, rows of the same edge keeping their order
+pub fn sort_edge_rows(rows: &mut [EdgeRow], edges: &[(usize, usize)]) {
+    let positions: FxHashMap<(usize, usize), usize> = edges
+        .iter()
+        .enumerate()
+        .map(|(position, &(origin, destination))| {
+            ((origin.min(destination), origin.max(destination)), position)
+        })
+        .collect();
+    rows.sort_by_key(|row| {
+        let (origin, destination): (usize, usize) = row.edge;
+        positions
+            .get(&(origin.min(destination), origin.max(destination)))
+            .copied()
+            .unwrap_or(usize::MAX)
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshError {
+    Empty,
+    Disconnected,
+    BinaryPly,
+    InvalidLine(usize), // Line numbers start at 1
+    InvalidIndex { line: usize, index: i64 },
+    Scale(ScaleError),
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshError::Empty => write!(f, "No edges found"),
+            MeshError::Disconnected => write!(f, "The mesh has several disconnected parts"),
+            MeshError::BinaryPly => {
+                write!(f, "Binary PLY files are not supported, export as ASCII")
+            }
+            MeshError::InvalidLine(line) => write!(f, "Invalid line {line}"),
+            MeshError::InvalidIndex { line, index } => {
+                write!(f, "Invalid vertex index {index} on line {line}")
+            }
+            MeshError::Scale(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+fn parse_fields<T: std::str::FromStr>(fields: &[&str], line: usize) -> Result<Vec<T>, MeshError> {
+    fields
+        .iter()
+        .map(|field| field.parse().map_err(|_| MeshError::InvalidLine(line)))
+        .collect()
+}
+
+// Wavefront OBJ: vertices (v), faces (f) and polylines (l), other statements are ignored
+// Indices start at 1, negative ones count back from the last vertex read
+pub fn parse_obj(input: &str) -> Result<Wireframe, MeshError> {
+    let mut vertices: Vec<Vec3A> = Vec::new();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+    let mut lines: Vec<Vec<usize>> = Vec::new();
+    numbered_lines(input).try_for_each(|(line, text)| {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let (keyword, values): (&str, &[&str]) =
+            (fields.first().unwrap(), fields.get(1..).unwrap());
+        match keyword {
+            "v" => {
+                let coordinates: Vec<f32> =
+                    parse_fields(values.get(..3).ok_or(MeshError::InvalidLine(line))?, line)?;
+                vertices.push(Vec3A::from_slice(&coordinates));
+            }
+            "f" | "l" => {
+                // Face vertices may carry texture and normal indices, as vertex/texture/normal
+                let indices: Vec<i64> = parse_fields(
+                    &values
+                        .iter()
+                        .map(|value| value.split('/').next().unwrap())
+                        .collect::<Vec<&str>>(),
+                    line,
+                )?;
+                let indices: Vec<usize> = indices
+                    .into_iter()
+                    .map(|index| {
+                        let resolved: i64 = if index < 0 {
+                            vertices.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        if (0..vertices.len() as i64).contains(&resolved) {
+                            Ok(resolved as usize)
+                        } else {
+                            Err(MeshError::InvalidIndex { line, index })
+                        }
+                    })
+                    .collect::<Result<Vec<usize>, MeshError>>()?;
+                if indices.len() < 2 {
+                    return Err(MeshError::InvalidLine(line));
+                }
+                if keyword == "f" {
+                    faces.push(indices);
+                } else {
+                    lines.push(indices);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    finish_wireframe(Wireframe::from_elements(vertices, faces, &lines))
+}
+
+// ASCII PLY: vertex coordinates from the x, y and z properties, faces from their index list, and edges from vertex1 and vertex2
+// Elements are read in the order of the header, unknown ones are skipped
+pub fn parse_ply(input: &str) -> Result<Wireframe, MeshError> {
+    let mut lines = numbered_lines(input);
+    if lines.next().is_none_or(|(_, text)| text != "ply") {
+        return Err(MeshError::InvalidLine(1));
+    }
+
+    // Header: element names and counts, with their property names
+    let mut elements: Vec<(&str, usize, Vec<&str>)> = Vec::new();
+    loop {
+        let (line, text) = lines.next().ok_or(MeshError::Empty)?;
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        match fields.as_slice() {
+            ["end_header"] => break,
+            ["format", "ascii", ..] => {}
+            ["format", ..] => return Err(MeshError::BinaryPly),
+            ["element", name, count] => elements.push((
+                name,
+                count.parse().map_err(|_| MeshError::InvalidLine(line))?,
+                Vec::new(),
+            )),
+            ["property", .., name] => elements
+                .last_mut()
+                .ok_or(MeshError::InvalidLine(line))?
+                .2
+                .push(name),
+            _ => {} // Comments and object information
+        }
+    }
+
+    let mut vertices: Vec<Vec3A> = Vec::new();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+    let mut edges: Vec<Vec<usize>> = Vec::new();
+    elements.iter().try_for_each(|(name, count, properties)| {
+        let position = |property: &str| properties.iter().position(|name| *name == property);
+        (0..*count).try_for_each(|_| {
+            let (line, text) = lines.next().ok_or(MeshError::Empty)?;
+            let values: Vec<&str> = text.split_whitespace().collect();
+            let index = |value: &str| {
+                let index: i64 = value.parse().map_err(|_| MeshError::InvalidLine(line))?;
+                usize::try_from(index)
+                    .ok()
+                    .filter(|index| *index < vertices.len())
+                    .ok_or(MeshError::InvalidIndex { line, index })
+            };
+            match *name {
+                "vertex" => {
+                    let coordinates: Vec<f32> = ["x", "y", "z"]
+                        .iter()
+                        .map(|axis| {
+                            position(axis)
+                                .and_then(|position| values.get(position))
+                                .and_then(|value| value.parse().ok())
+                                .ok_or(MeshError::InvalidLine(line))
+                        })
+                        .collect::<Result<Vec<f32>, MeshError>>()?;
+                    vertices.push(Vec3A::from_slice(&coordinates));
+                }
+                // The vertex list comes first in the usual face layout, as a count then the indices
+                "face" => {
+                    let length: usize = values
+                        .first()
+                        .and_then(|length| length.parse().ok())
+                        .ok_or(MeshError::InvalidLine(line))?;
+                    let face: Vec<usize> = values
+                        .get(1..=length)
+                        .ok_or(MeshError::InvalidLine(line))?
+                        .iter()
+                        .map(|value| index(value))
+                        .collect::<Result<Vec<usize>, MeshError>>()?;
+                    faces.push(face);
+                }
+                "edge" => {
+                    let edge: Vec<usize> = ["vertex1", "vertex2"]
+                        .iter()
+                        .map(|property| {
+                            let value: &str = position(property)
+                                .and_then(|position| values.get(position))
+                                .ok_or(MeshError::InvalidLine(line))?;
+                            index(value)
+                        })
+                        .collect::<Result<Vec<usize>, MeshError>>()?;
+                    edges.push(edge);
+                }
+                _ => {}
+            }
+            Ok(())
+        })
+    })?;
+
+    finish_wireframe(Wireframe::from_elements(vertices, faces, &edges))
+}
+
+fn finish_wireframe(mut wireframe: Wireframe) -> Result<Wireframe, MeshError> {
+    if wireframe.edges.is_empty() {
+        return Err(MeshError::Empty);
+    }
+    wireframe.remove_loose_vertices();
+    if !wireframe.order_edges() {
+        return Err(MeshError::Disconnected);
+    }
+    Ok(wireframe)
+}
+
+// OBJ or PLY mesh, told apart by the PLY magic line
+pub fn import_mesh(input: &str, settings: &ImportSettings) -> Result<Wireframe, MeshError> {
+    let mut wireframe: Wireframe = if input.trim_start().starts_with("ply") {
+        parse_ply(input)?
+    } else {
+        parse_obj(input)?
+    };
+    wireframe.adjust(settings).map_err(MeshError::Scale)?;
+    Ok(wireframe)
+}
+
+pub fn is_mesh_file(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("obj") || extension.eq_ignore_ascii_case("ply")
+        })
+}
+
+// FASTA records, with sequences wrapped at 60 characters
+pub fn format_fasta(records: &[(&str, &str)]) -> String {
+    records
+        .iter()
+        .map(|(name, sequence)| {
+            let lines: Vec<String> = sequence
+                .chars()
+                .collect::<Vec<char>>()
+                .chunks(60)
+                .map(|line| line.iter().collect())
+                .collect();
+            format!(">{name}\n{}\n", lines.join("\n"))
+        })
+        .collect()
+}
+
+// GenBank flat file of a transcription template, with its features annotated
+pub fn format_genbank(name: &str, template: &DnaTemplate) -> String {
+    let mut genbank: String = format!(
+        "LOCUS       {:<16} {:>11} bp    DNA     linear   SYN {}\n",
+        name.replace(char::is_whitespace, "_"),
+        template.sequence.len(),
+        genbank_date()
+    );
+    genbank.push_str(&format!(
+        "DEFINITION  Transcription template of {name}.\nFEATURES             Location/Qualifiers\n"
+    ));
+    template.features.iter().for_each(|feature| {
+        let key: &str = match feature.kind {
+            FeatureKind::Promoter => "promoter",
+            FeatureKind::StartGuanines => "misc_feature",
+            FeatureKind::Ribozyme | FeatureKind::Rna => "misc_RNA",
+            FeatureKind::Terminator => "terminator",
+        };
+        genbank.push_str(&format!(
+            "     {key:<16}{}..{}\n                     /label=\"{}\"\n",
+            feature.start + 1,
+            feature.end,
+            feature.name
+        ));
+    });
+
+    // Sequence in lines of 60, in blocks of 10, numbered from 1
+    genbank.push_str("ORIGIN\n");
+    template
+        .sequence
+        .to_ascii_lowercase()
+        .chars()
+        .collect::<Vec<char>>()
+        .chunks(60)
+        .enumerate()
+        .for_each(|(line, bases)| {
+            let blocks: Vec<String> = bases
+                .chunks(10)
+                .map(|block| block.iter().collect())
+                .collect();
+            genbank.push_str(&format!("{:>9} {}\n", line * 60 + 1, blocks.join(" ")));
+        });
+    genbank.push_str("//\n");
+    genbank
+}
+
+// Current date as DD-MMM-YYYY
+fn genbank_date() -> String {
+    const MONTHS: [&str; 12] = [
+        "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+    ];
+    let days: i64 = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| (duration.as_secs() / 86400) as i64);
+
+    // Civil date from days since 1970-01-01 (Hinnant's algorithm)
+    let shifted: i64 = days + 719468;
+    let era: i64 = shifted.div_euclid(146097);
+    let day_of_era: i64 = shifted - era * 146097;
+    let year_of_era: i64 =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{day:02}-{}-{year}",
+        MONTHS.get(month as usize - 1).unwrap()
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructureFormat {
+    Ct,
+    Bpseq,
+    Dbn,
+}
+
+impl StructureFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            StructureFormat::Ct => "ct",
+            StructureFormat::Bpseq => "bpseq",
+            StructureFormat::Dbn => "dbn",
+        }
+    }
+}
+
+// Secondary structure file, pseudoknots being kept in CT and BPSEQ and written with [], {} and <> in dot-bracket
+pub fn format_structure(
+    name: &str,
+    structure: &TargetStructure,
+    format: StructureFormat,
+) -> String {
+    match format {
+        StructureFormat::Ct => structure.ct(name),
+        StructureFormat::Bpseq => format!("# {name}\n{}", structure.bpseq()),
+        StructureFormat::Dbn => format!(
+            ">{name}\n{}\n{}\n",
+            structure.sequence,
+            structure.dot_bracket()
+        ),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructureFileError {
+    Empty,
+    InvalidLine(usize), // Line numbers start at 1
+    InvalidNucleotide { line: usize, character: char },
+    InvalidPartner { line: usize, partner: usize }, // Out of range, or not pairing back
+    LengthMismatch { expected: usize, found: usize },
+    DotBracket(DotBracketError),
+}
+
+impl Display for StructureFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructureFileError::Empty => write!(f, "No structure found"),
+            StructureFileError::InvalidLine(line) => write!(f, "Invalid line {line}"),
+            StructureFileError::InvalidNucleotide { line, character } => {
+                write!(f, "Invalid nucleotide '{character}' on line {line}")
+            }
+            StructureFileError::InvalidPartner { line, partner } => {
+                write!(f, "Invalid partner {partner} on line {line}")
+            }
+            StructureFileError::LengthMismatch { expected, found } => {
+                write!(f, "Expected {expected} nucleotides, found {found}")
+            }
+            StructureFileError::DotBracket(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+// Nonempty lines with their 1-based line numbers
+fn numbered_lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+// Structure from one (line number, base, 1-based partner or 0) entry per nucleotide, checking that pairs are mutual
+fn structure_from_entries(
+    entries: &[(usize, &str, usize)],
+) -> Result<TargetStructure, StructureFileError> {
+    if entries.is_empty() {
+        return Err(StructureFileError::Empty);
+    }
+    let mut sequence: RnaSequence = RnaSequence::new();
+    let mut partners: Vec<Option<usize>> = Vec::with_capacity(entries.len());
+    entries
+        .iter()
+        .enumerate()
+        .try_for_each(|(index, &(line, base, partner))| {
+            let character: char = base.chars().next().unwrap();
+            if base.chars().count() != 1 {
+                return Err(StructureFileError::InvalidNucleotide { line, character });
+            }
+            sequence.push(
+                Nucleotide::try_from(character)
+                    .map_err(|_| StructureFileError::InvalidNucleotide { line, character })?,
+            );
+            let paired_back: bool = partner == 0
+                || entries
+                    .get(partner - 1)
+                    .is_some_and(|&(_, _, other)| other == index + 1 && partner != index + 1);
+            if !paired_back {
+                return Err(StructureFileError::InvalidPartner { line, partner });
+            }
+            partners.push(partner.checked_sub(1));
+            Ok(())
+        })?;
+    Ok(TargetStructure::with_kissing_loops(sequence, &partners))
+}
+
+// Connectivity table: a header starting with the length, then index, base, previous, next, partner and natural numbering
+// Only the first structure of files holding several is read
+pub fn parse_ct(input: &str) -> Result<TargetStructure, StructureFileError> {
+    let mut lines = numbered_lines(input);
+    let (header_line, header): (usize, &str) = lines.next().ok_or(StructureFileError::Empty)?;
+    let length: usize = header
+        .split_whitespace()
+        .next()
+        .and_then(|length| length.parse().ok())
+        .ok_or(StructureFileError::InvalidLine(header_line))?;
+
+    let entries: Vec<(usize, &str, usize)> = lines
+        .take(length)
+        .enumerate()
+        .map(|(index, (line, text))| {
+            let fields: Vec<&str> = text.split_whitespace().collect();
+            match (
+                fields.first().map(|field| field.parse::<usize>()),
+                fields.get(4),
+            ) {
+                (Some(Ok(number)), Some(partner)) if number == index + 1 && fields.len() >= 6 => {
+                    let partner: usize = partner
+                        .parse()
+                        .map_err(|_| StructureFileError::InvalidLine(line))?;
+                    Ok((line, *fields.get(1).unwrap(), partner))
+                }
+                _ => Err(StructureFileError::InvalidLine(line)),
+            }
+        })
+        .collect::<Result<Vec<(usize, &str, usize)>, StructureFileError>>()?;
+    if entries.len() != length {
+        return Err(StructureFileError::LengthMismatch {
+            expected: length,
+            found: entries.len(),
+        });
+    }
+    structure_from_entries(&entries)
+}
+
+// BPSEQ: index, base and partner per line
+// Comments and header lines before the first nucleotide, as written by databases, are skipped
+pub fn parse_bpseq(input: &str) -> Result<TargetStructure, StructureFileError> {
+    let entries: Vec<(usize, &str, usize)> = numbered_lines(input)
+        .filter(|(_, text)| !text.starts_with('#'))
+        .skip_while(|(_, text)| {
+            text.split_whitespace()
+                .next()
+                .is_none_or(|field| field.parse::<usize>().is_err())
+        })
+        .enumerate()
+        .map(|(index, (line, text))| {
+            let fields: Vec<&str> = text.split_whitespace().collect();
+            match fields.as_slice() {
+                [number, base, partner] if number.parse::<usize>() == Ok(index + 1) => {
+                    let partner: usize = partner
+                        .parse()
+                        .map_err(|_| StructureFileError::InvalidLine(line))?;
+                    Ok((line, *base, partner))
+                }
+                _ => Err(StructureFileError::InvalidLine(line)),
+            }
+        })
+        .collect::<Result<Vec<(usize, &str, usize)>, StructureFileError>>()?;
+    structure_from_entries(&entries)
+}
+
+// Dot-bracket file: optional '>' or '#' header lines, the sequence, then the structure
+// Anything after the structure on its line, such as a free energy, is ignored
+pub fn parse_dbn(input: &str) -> Result<TargetStructure, StructureFileError> {
+    let mut lines =
+        numbered_lines(input).filter(|(_, text)| !(text.starts_with('>') || text.starts_with('#')));
+    let (sequence_line, sequence_text): (usize, &str) =
+        lines.next().ok_or(StructureFileError::Empty)?;
+    let (_, structure_text): (usize, &str) = lines.next().ok_or(StructureFileError::Empty)?;
+
+    let sequence: RnaSequence = sequence_text
+        .chars()
+        .map(|character| {
+            Nucleotide::try_from(character).map_err(|_| StructureFileError::InvalidNucleotide {
+                line: sequence_line,
+                character,
+            })
+        })
+        .collect::<Result<RnaSequence, StructureFileError>>()?;
+    let partners: Vec<Option<usize>> =
+        parse_dot_bracket(structure_text.split_whitespace().next().unwrap())
+            .map_err(StructureFileError::DotBracket)?;
+    if partners.len() != sequence.len() {
+        return Err(StructureFileError::LengthMismatch {
+            expected: sequence.len(),
+            found: partners.len(),
+        });
+    }
+    Ok(TargetStructure::with_kissing_loops(sequence, &partners))
+}
+
+// Format guessed from the contents: BPSEQ has three fields per nucleotide, CT starts with its length
+pub fn detect_structure_format(input: &str) -> StructureFormat {
+    let content: Vec<&str> = numbered_lines(input)
+        .map(|(_, text)| text)
+        .filter(|text| !text.starts_with('#'))
+        .collect();
+    let is_bpseq_line = |text: &&str| {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        matches!(fields.as_slice(), [number, _, partner]
+            if number.parse::<usize>().is_ok() && partner.parse::<usize>().is_ok())
+    };
+    if content.iter().any(is_bpseq_line) {
+        StructureFormat::Bpseq
+    } else if content.first().is_some_and(|text| {
+        text.split_whitespace()
+            .next()
+            .is_some_and(|field| field.parse::<usize>().is_ok())
+    }) {
+        StructureFormat::Ct
+    } else {
+        StructureFormat::Dbn
+    }
+}
+
+pub fn parse_structure(input: &str) -> Result<TargetStructure, StructureFileError> {
+    match detect_structure_format(input) {
+        StructureFormat::Ct => parse_ct(input),
+        StructureFormat::Bpseq => parse_bpseq(input),
+        StructureFormat::Dbn => parse_dbn(input),
+    }
+}
+
+// Atoms written for each nucleotide of a PDB model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdbAtoms {
+    Single, // One C4' pseudo-atom
+    Coarse, // P, C4' and the glycosidic nitrogen, N9 for purines and N1 for pyrimidines
+}
+
+// Chain ids in the order they are given to strands
+const PDB_CHAIN_IDS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+// Coarse-grained PDB model with one chain per strand, residues named after the sequence
+// Atom serials and residue numbers wrap around when they outgrow their columns
+pub fn format_pdb(
+    name: &str,
+    strands: &[(&[Nucleotide], &[NucleotideFrame])],
+    atoms: PdbAtoms,
+) -> String {
+    let mut pdb: String = format!("TITLE     {name}\n");
+    let mut serial: usize = 0;
+    strands
+        .iter()
+        .enumerate()
+        .for_each(|(strand, (sequence, trace))| {
+            let chain: char = PDB_CHAIN_IDS
+                .chars()
+                .nth(strand % PDB_CHAIN_IDS.len())
+                .unwrap();
+            sequence
+                .iter()
+                .zip(trace.iter())
+                .enumerate()
+                .for_each(|(index, (nucleotide, frame))| {
+                    let nitrogen: &str = match nucleotide {
+                        Nucleotide::A | Nucleotide::G => "N9",
+                        Nucleotide::C | Nucleotide::U => "N1",
+                    };
+                    let residue_atoms: Vec<(&str, Vec3A)> = match atoms {
+                        PdbAtoms::Single => vec![("C4'", frame.backbone)],
+                        PdbAtoms::Coarse => vec![
+                            ("P", phosphate(trace, index)),
+                            ("C4'", frame.backbone),
+                            (nitrogen, frame.base_atom()),
+                        ],
+                    };
+                    residue_atoms.iter().for_each(|(atom, position)| {
+                        serial += 1;
+                        pdb.push_str(&format!(
+                            "ATOM  {:>5} {:<4} {:>3} {chain}{:>4}    {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}          {:>2}\n",
+                            serial % 100_000,
+                            format!(" {atom}"),
+                            nucleotide.to_string(),
+                            (index + 1) % 10_000,
+                            position.x,
+                            position.y,
+                            position.z,
+                            1.0,
+                            0.0,
+                            atom.get(..1).unwrap()
+                        ));
+                    });
+                });
+            serial += 1;
+            pdb.push_str(&format!(
+                "TER   {:>5}      {:>3} {chain}{:>4}\n",
+                serial % 100_000,
+                sequence.last().map(Nucleotide::to_string).unwrap_or_default(),
+                sequence.len() % 10_000
+            ));
+        });
+    pdb.push_str("END\n");
+    pdb
+}
+
+// oxDNA length unit, shared by oxRNA, in ångströms
+const OXDNA_LENGTH_UNIT: f32 = 8.518;
+// Distance from the backbone to the centre of an oxRNA nucleotide, in oxDNA units
+const OXRNA_CENTRE_DISTANCE: f32 = 0.4;
+// Free space around the structure in the periodic box, in oxDNA units
+const OXRNA_BOX_MARGIN: f32 = 20.0;
+// Mutual traps hold the designed pairs together while the initial configuration relaxes
+const TRAP_STIFFNESS: f32 = 0.09;
+const TRAP_DISTANCE: f32 = 1.2;
+
+// Files of an oxRNA simulation
+#[derive(Debug, Clone, Default)]
+pub struct OxrnaFiles {
+    pub topology: String,
+    pub configuration: String,
+    pub traps: String, // External forces, a mutual trap each way for every designed pair
+}
+
+// Topology, initial configuration and mutual traps of strands with their 3D trace
+// Pairs are indices of the strands joined one after the other, 5' to 3'
+// The classic topology format lists each strand 3' to 5', so the files number nucleotides in that order
+pub fn format_oxrna(
+    strands: &[(&[Nucleotide], &[NucleotideFrame])],
+    pairs: &[(usize, usize)],
+) -> OxrnaFiles {
+    let mut file_indices: Vec<usize> = Vec::new();
+    let mut topology: String = String::new();
+    let mut listed: Vec<NucleotideFrame> = Vec::new();
+    strands
+        .iter()
+        .enumerate()
+        .for_each(|(strand, (sequence, trace))| {
+            let start: usize = listed.len();
+            file_indices.extend((0..sequence.len()).rev().map(|index| start + index));
+            sequence
+                .iter()
+                .zip(trace.iter())
+                .rev()
+                .enumerate()
+                .for_each(|(position, (nucleotide, frame))| {
+                    let index: i64 = (start + position) as i64;
+                    let n3: i64 = if position == 0 { -1 } else { index - 1 };
+                    let n5: i64 = if position + 1 == sequence.len() {
+                        -1
+                    } else {
+                        index + 1
+                    };
+                    topology.push_str(&format!("{} {nucleotide} {n3} {n5}\n", strand + 1));
+                    listed.push(*frame);
+                });
+        });
+    topology.insert_str(0, &format!("{} {}\n", listed.len(), strands.len()));
+
+    // Nucleotides are centred in a cubic periodic box
+    let centres: Vec<Vec3A> = listed
+        .iter()
+        .map(|frame| frame.backbone / OXDNA_LENGTH_UNIT + frame.base * OXRNA_CENTRE_DISTANCE)
+        .collect();
+    let (low, high): (Vec3A, Vec3A) = centres.iter().fold(
+        (Vec3A::splat(f32::MAX), Vec3A::splat(f32::MIN)),
+        |(low, high), centre| (low.min(*centre), high.max(*centre)),
+    );
+    let side: f32 = if centres.is_empty() {
+        2.0 * OXRNA_BOX_MARGIN
+    } else {
+        (high - low).max_element() + 2.0 * OXRNA_BOX_MARGIN
+    };
+    let shift: Vec3A = if centres.is_empty() {
+        Vec3A::ZERO
+    } else {
+        Vec3A::splat(0.5 * side) - 0.5 * (low + high)
+    };
+    let mut configuration: String =
+        format!("t = 0\nb = {side:.3} {side:.3} {side:.3}\nE = 0.000 0.000 0.000\n");
+    listed
+        .iter()
+        .zip(centres.iter())
+        .for_each(|(frame, centre)| {
+            let position: Vec3A = *centre + shift;
+            let a1: Vec3A = frame.base;
+            let a3: Vec3A = (frame.normal - a1 * a1.dot(frame.normal))
+                .try_normalize()
+                .unwrap_or(a1.any_orthonormal_vector());
+            configuration.push_str(&format!(
+                "{:.4} {:.4} {:.4} {:.4} {:.4} {:.4} {:.4} {:.4} {:.4} 0 0 0 0 0 0\n",
+                position.x, position.y, position.z, a1.x, a1.y, a1.z, a3.x, a3.y, a3.z
+            ));
+        });
+
+    let traps: String = pairs
+        .iter()
+        .flat_map(|&(first, second)| {
+            let (first, second): (usize, usize) = (
+                *file_indices.get(first).unwrap(),
+                *file_indices.get(second).unwrap(),
+            );
+            [(first, second), (second, first)]
+        })
+        .map(|(particle, reference)| {
+            format!(
+                "{{\ntype = mutual_trap\nparticle = {particle}\nref_particle = {reference}\nstiff = {TRAP_STIFFNESS}\nr0 = {TRAP_DISTANCE}\nPBC = 1\n}}\n"
+            )
+        })
+        .collect();
+
+    OxrnaFiles {
+        topology,
+        configuration,
+        traps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3a;
+
+    use super::*;
+    use crate::routines::{
+        graph::{Tree, construct_tree, find_rna_path},
+        model::NucleotideFrame,
+        sequencer::{GeneratedSequence, SequencerSettings, generate_sequence},
+        template::{TemplateSettings, build_template},
+    };
+
+    // Square pyramid, its apex last
+    fn pyramid() -> Vec<Vec3A> {
+        vec![
+            vec3a(-10.0, 10.0, 0.0),
+            vec3a(10.0, 10.0, 0.0),
+            vec3a(10.0, -10.0, 0.0),
+            vec3a(-10.0, -10.0, 0.0),
+            vec3a(0.0, 0.0, 20.0),
+        ]
+    }
+
+    fn rows(edges: &[(usize, usize)]) -> Vec<EdgeRow> {
+        edges
+            .iter()
+            .map(|&edge| EdgeRow {
+                edge,
+                options: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vertex_lists_and_tables() {
+        let bracketed: Vec<Vec3A> =
+            parse_node_coordinates("[-10.0, 10.0, 0.0] [10, 10, 0]\n# Base\n[10.0, -10.0, 0.0]\n")
+                .unwrap();
+        assert_eq!(bracketed, pyramid().get(..3).unwrap());
+        // Table rows in any order, with a header
+        let table: Vec<Vec3A> =
+            parse_node_coordinates("id\tx\ty\tz\n1\t10\t10\t0\n0\t-10\t10\t0\n").unwrap();
+        assert_eq!(table, pyramid().get(..2).unwrap());
+
+        assert_eq!(
+            parse_node_coordinates("0,0,0,0\n0,1,1,1"),
+            Err(InvalidNodeDataError::DuplicateVertex { line: 2, id: 0 })
+        );
+        assert_eq!(
+            parse_node_coordinates("0,0,0,0\n2,1,1,1"),
+            Err(InvalidNodeDataError::VertexOutOfRange { line: 2, id: 2 })
+        );
+        assert_eq!(
+            parse_node_coordinates("[1, 2]"),
+            Err(InvalidNodeDataError::InvalidLine(1))
+        );
+        assert_eq!(
+            parse_node_coordinates("[0, 0, 0]\n[1, x, 0]"),
+            Err(InvalidNodeDataError::InvalidValue {
+                line: 2,
+                value: "x".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn edge_lists_and_tables() {
+        let bracketed: Vec<EdgeRow> = parse_edges("(0, 1) (1, 2)\n(2, 0)", 3).unwrap();
+        assert_eq!(bracketed, rows(&[(0, 1), (1, 2), (2, 0)]));
+
+        let table: Vec<EdgeRow> = parse_edges(
+            "origin,destination,options\n0,1,pin=0:GGNA module=4:MS2\n1,2,pin=2:U\n",
+            3,
+        )
+        .unwrap();
+        assert_eq!(
+            table.first().unwrap().options,
+            vec![
+                EdgeOption::Pin {
+                    offset: 0,
+                    pattern: "GGNA"
+                        .chars()
+                        .map(|code| IupacCode::try_from(code).unwrap())
+                        .collect()
+                },
+                EdgeOption::Module {
+                    offset: 4,
+                    name: "MS2".to_string()
+                }
+            ]
+        );
+        assert_eq!(edge_pins(&table).len(), 2);
+
+        assert_eq!(
+            parse_edges("(0, 1)\n(1, 3)", 3),
+            Err(InvalidNodeDataError::UnknownVertex { line: 2, id: 3 })
+        );
+        assert_eq!(
+            parse_edges("0,1,pin=GGA", 3),
+            Err(InvalidNodeDataError::InvalidOption {
+                line: 1,
+                option: "pin=GGA".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn tables_round_trip() {
+        // Coordinates with more digits than a fixed precision would keep
+        let vertices: Vec<Vec3A> = vec![
+            vec3a(0.1, -1.0 / 3.0, 1.0e-6),
+            vec3a(12345.678, 2.0f32.sqrt(), -0.000_123_45),
+        ];
+        let table_rows: Vec<EdgeRow> =
+            parse_edges("0,1,pin=0:GGA module=2:MS2\n1,0,pin=1:CY", 2).unwrap();
+        ['\t', ','].into_iter().for_each(|separator| {
+            assert_eq!(
+                parse_node_coordinates(&format_vertex_table(&vertices, separator)).unwrap(),
+                vertices
+            );
+            assert_eq!(
+                parse_edges(&format_edge_table(&table_rows, separator), 2).unwrap(),
+                table_rows
+            );
+        });
+    }
+
+    // Square pyramid mesh, its faces sharing every edge
+    const PYRAMID_OBJ: &str = "# Pyramid
+o Pyramid
+v -10 10 0
+v 10 10 0
+v 10 -10 0
+v -10 -10 0
+v 0 0 20
+v 50 50 50
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+f 1//1 2//1 5//1
+f 2 3 5
+f -4 -3 -2
+f 4 1 5
+";
+
+    const PYRAMID_PLY: &str = "ply
+format ascii 1.0
+comment Pyramid
+element vertex 5
+property float x
+property float y
+property float z
+element face 5
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+-10 10 0
+10 10 0
+10 -10 0
+-10 -10 0
+0 0 20
+4 0 1 2 3
+3 0 1 4
+3 1 2 4
+3 2 3 4
+3 3 0 4
+1 0
+";
+
+    fn undirected(edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let mut edges: Vec<(usize, usize)> = edges
+            .iter()
+            .map(|&(origin, destination)| (origin.min(destination), origin.max(destination)))
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    #[test]
+    fn meshes_share_their_edges() {
+        [PYRAMID_OBJ, PYRAMID_PLY].into_iter().for_each(|input| {
+            let wireframe: Wireframe = import_mesh(input, &ImportSettings::default()).unwrap();
+            // The unused vertex of the OBJ file is dropped
+            assert_eq!(wireframe.vertices, pyramid());
+            assert_eq!(wireframe.faces.len(), 5);
+            assert_eq!(
+                undirected(&wireframe.edges),
+                vec![
+                    (0, 1),
+                    (0, 3),
+                    (0, 4),
+                    (1, 2),
+                    (1, 4),
+                    (2, 3),
+                    (2, 4),
+                    (3, 4)
+                ]
+            );
+            assert_eq!(wireframe.edges.first().unwrap().0, 4);
+        });
+    }
+
+    #[test]
+    fn invalid_meshes_are_rejected() {
+        assert_eq!(
+            parse_obj("v 0 0 0\nv 1 0 0\nl 1 3"),
+            Err(MeshError::InvalidIndex { line: 3, index: 3 })
+        );
+        assert_eq!(parse_obj("v 0 0\n"), Err(MeshError::InvalidLine(1)));
+        assert_eq!(parse_obj("v 0 0 0\n"), Err(MeshError::Empty));
+        assert_eq!(
+            parse_obj("v 0 0 0\nv 1 0 0\nv 2 0 0\nv 3 0 0\nl 1 2\nl 3 4"),
+            Err(MeshError::Disconnected)
+        );
+        assert_eq!(
+            parse_ply("ply\nformat binary_little_endian 1.0\nend_header\n"),
+            Err(MeshError::BinaryPly)
+        );
+        assert_eq!(
+            parse_ply(&PYRAMID_PLY.replace("3 3 0 4", "3 3 0 5")),
+            Err(MeshError::InvalidIndex { line: 23, index: 5 })
+        );
+    }
+
+    #[test]
+    fn imported_meshes_are_adjusted() {
+        let settings: ImportSettings = ImportSettings {
+            recentre: true,
+            scale: Some(ScaleTarget::BoundingBox(40.0)),
+        };
+        let wireframe: Wireframe = import_mesh(PYRAMID_OBJ, &settings).unwrap();
+        let centroid: Vec3A = wireframe.vertices.iter().sum::<Vec3A>() / 5.0;
+        assert!(centroid.length() < 1.0e-4);
+        let (minimum, maximum): (Vec3A, Vec3A) = wireframe.vertices.iter().fold(
+            (Vec3A::INFINITY, Vec3A::NEG_INFINITY),
+            |(minimum, maximum), vertex| (minimum.min(*vertex), maximum.max(*vertex)),
+        );
+        assert!(((maximum - minimum).max_element() - 40.0).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn ordered_edges_start_from_reached_vertices() {
+        // Listed so that (2, 3) comes before any edge reaching 2 or 3
+        let edges: Vec<(usize, usize)> = vec![(0, 1), (2, 3), (3, 4), (1, 2), (4, 0), (4, 1)];
+        let ordered: Vec<(usize, usize)> = order_edges(&edges, 5).unwrap();
+        assert_eq!(ordered.len(), edges.len());
+
+        let mut reached: FxHashSet<usize> = FxHashSet::from_iter([ordered.first().unwrap().0]);
+        ordered.iter().for_each(|&(origin, destination)| {
+            assert!(reached.contains(&origin), "{ordered:?}");
+            reached.insert(destination);
+        });
+    }
+
+    #[test]
+    fn ordered_edges_are_listed_once() {
+        let ordered: Vec<(usize, usize)> =
+            order_edges(&[(0, 1), (1, 0), (1, 2), (0, 1)], 3).unwrap();
+        assert_eq!(ordered, vec![(1, 0), (1, 2)]);
+    }
+
+    #[test]
+    fn disconnected_edges_are_rejected() {
+        assert_eq!(order_edges(&[(0, 1), (2, 3)], 4), None);
+        assert_eq!(order_edges(&[], 0), Some(Vec::new()));
+    }
+
+    #[test]
+    fn tree_of_reordered_rows_reaches_every_node() {
+        // A path listed out of order would leave 2 and 3 in a separate tree
+        let mut path_rows: Vec<EdgeRow> = rows(&[(2, 3), (0, 1), (1, 2)]);
+        let wireframe: Wireframe = Wireframe::from_rows(pyramid(), &mut path_rows).unwrap();
+        let tree: Tree = construct_tree(&wireframe.edges);
+        assert!(tree.cycle_breakers.is_empty());
+        assert_eq!(
+            tree.nodes
+                .values()
+                .filter(|(parent, _)| *parent == usize::MAX)
+                .count(),
+            1
+        );
+
+        // Each edge of the pyramid is travelled once in each direction
+        let mut pyramid_rows: Vec<EdgeRow> = rows(&[
+            (0, 1),
+            (2, 3),
+            (1, 2),
+            (3, 0),
+            (4, 0),
+            (4, 1),
+            (4, 2),
+            (4, 3),
+        ]);
+        let wireframe: Wireframe = Wireframe::from_rows(pyramid(), &mut pyramid_rows).unwrap();
+        let mut tree: Tree = construct_tree(&wireframe.edges);
+        assert_eq!(tree.cycle_breakers.len(), 4);
+        let path: Vec<usize> = find_rna_path(&mut tree, &wireframe.vertices);
+        assert_eq!(path.len(), 2 * tree.edges.len() + 1);
+        assert_eq!(path.first(), path.last());
+    }
+
+    #[test]
+    fn rows_keep_their_direction() {
+        let mut edge_rows: Vec<EdgeRow> = rows(&[(0, 1), (2, 1), (1, 3)]);
+        let wireframe: Wireframe = Wireframe::from_rows(pyramid(), &mut edge_rows).unwrap();
+        assert_eq!(wireframe.edges, vec![(1, 0), (1, 2), (1, 3)]);
+        assert_eq!(
+            edge_rows.iter().map(|row| row.edge).collect::<Vec<_>>(),
+            vec![(0, 1), (2, 1), (1, 3)]
+        );
+    }
+
+    #[test]
+    fn structure_files_round_trip() {
+        let coordinates: Vec<Vec3A> = pyramid();
+        let mut tree: Tree = construct_tree(&[
+            (4, 0),
+            (4, 1),
+            (4, 2),
+            (4, 3),
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 0),
+        ]);
+        let path: Vec<usize> = find_rna_path(&mut tree, &coordinates);
+        let settings: SequencerSettings = SequencerSettings {
+            seed: Some(0),
+            ..Default::default()
+        };
+        let generated: GeneratedSequence =
+            generate_sequence(&path, &tree, &coordinates, &settings).unwrap();
+        let target: TargetStructure = TargetStructure::from_generated(&generated);
+        assert!(target.pairs.iter().any(|pair| pair.kissing_loop));
+
+        [
+            StructureFormat::Ct,
+            StructureFormat::Bpseq,
+            StructureFormat::Dbn,
+        ]
+        .into_iter()
+        .for_each(|format| {
+            let text: String = format_structure("pyramid", &target, format);
+            assert_eq!(detect_structure_format(&text), format);
+            // Kissing loops are recognised as the pairs crossing the helices
+            assert_eq!(parse_structure(&text).unwrap(), target);
+        });
+    }
+
+    #[test]
+    fn fasta_lines_hold_sixty_bases() {
+        let sequence: String = "ACGU".repeat(31);
+        let fasta: String = format_fasta(&[("first", &sequence), ("second", "GGC")]);
+        let lines: Vec<&str> = fasta.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                ">first",
+                &sequence[..60],
+                &sequence[60..120],
+                &sequence[120..],
+                ">second",
+                "GGC"
+            ]
+        );
+        assert!(fasta.ends_with("GGC\n"));
+    }
+
+    #[test]
+    fn genbank_files_list_features_and_numbered_bases() {
+        let rna: RnaSequence = "GAUC".repeat(20).parse().unwrap();
+        let template: DnaTemplate = build_template(
+            &rna,
+            &TemplateSettings {
+                hdv: true,
+                ..Default::default()
+            },
+        );
+        let genbank: String = format_genbank("my design", &template);
+        let lines: Vec<&str> = genbank.lines().collect();
+
+        let locus: &str = lines.first().unwrap();
+        assert!(locus.starts_with("LOCUS       my_design        "));
+        assert!(locus.contains(&format!("{:>11} bp    DNA     linear   SYN ", 182)));
+        assert_eq!(template.sequence.len(), 182);
+        assert_eq!(
+            lines[1..6],
+            [
+                "DEFINITION  Transcription template of my design.",
+                "FEATURES             Location/Qualifiers",
+                "     promoter        1..17",
+                "                     /label=\"T7 promoter\"",
+                "     misc_feature    18..18",
+            ]
+        );
+        assert_eq!(
+            lines[7..11],
+            [
+                "     misc_RNA        19..98",
+                "                     /label=\"Designed RNA\"",
+                "     misc_RNA        99..182",
+                "                     /label=\"HDV ribozyme\"",
+            ]
+        );
+
+        // Lowercase bases, numbered from 1, in lines of six blocks of ten
+        assert_eq!(*lines.get(11).unwrap(), "ORIGIN");
+        let origin: Vec<&str> = lines[12..lines.len() - 1].to_vec();
+        assert_eq!(origin.len(), 4);
+        let first: String = template.sequence[..60].to_ascii_lowercase();
+        assert_eq!(
+            *origin.first().unwrap(),
+            format!(
+                "        1 {} {} {} {} {} {}",
+                &first[..10],
+                &first[10..20],
+                &first[20..30],
+                &first[30..40],
+                &first[40..50],
+                &first[50..60]
+            )
+        );
+        assert!(origin.get(1).unwrap().starts_with("       61 "));
+        assert_eq!(
+            *origin.last().unwrap(),
+            format!(
+                "      181 {}",
+                template.sequence[180..].to_ascii_lowercase()
+            )
+        );
+        let bases: String = origin
+            .iter()
+            .flat_map(|line| line.split_whitespace().skip(1))
+            .collect();
+        assert_eq!(bases, template.sequence.to_ascii_lowercase());
+        assert_eq!(*lines.last().unwrap(), "//");
+    }
+
+    // Nucleotides one backbone step apart along x, their bases facing y
+    fn straight_trace(length: usize) -> Vec<NucleotideFrame> {
+        (0..length)
+            .map(|index| NucleotideFrame {
+                backbone: Vec3A::X * 6.0 * index as f32,
+                base: Vec3A::Y,
+                normal: -Vec3A::X,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pdb_records_keep_their_columns() {
+        let first: RnaSequence = "GAUC".parse().unwrap();
+        let second: RnaSequence = "CU".parse().unwrap();
+        let (first_trace, second_trace): (Vec<NucleotideFrame>, Vec<NucleotideFrame>) =
+            (straight_trace(4), straight_trace(2));
+        let strands: [(&[Nucleotide], &[NucleotideFrame]); 2] =
+            [(&first, &first_trace), (&second, &second_trace)];
+
+        // Serial, atom name, residue name, chain and residue number
+        let columns = |line: &str| -> (String, String, String, String, String) {
+            (
+                line[6..11].to_string(),
+                line[12..16].to_string(),
+                line[17..20].to_string(),
+                line[21..22].to_string(),
+                line[22..26].to_string(),
+            )
+        };
+        let atoms = |pdb: &str| -> Vec<(String, String, String, String, String)> {
+            pdb.lines()
+                .filter(|line| line.starts_with("ATOM  "))
+                .map(columns)
+                .collect()
+        };
+        let row = |serial: &str, atom: &str, residue: &str, chain: &str, number: &str| {
+            (
+                serial.to_string(),
+                atom.to_string(),
+                residue.to_string(),
+                chain.to_string(),
+                number.to_string(),
+            )
+        };
+
+        let pdb: String = format_pdb("design", &strands, PdbAtoms::Single);
+        assert_eq!(
+            atoms(&pdb),
+            vec![
+                row("    1", " C4'", "  G", "A", "   1"),
+                row("    2", " C4'", "  A", "A", "   2"),
+                row("    3", " C4'", "  U", "A", "   3"),
+                row("    4", " C4'", "  C", "A", "   4"),
+                row("    6", " C4'", "  C", "B", "   1"),
+                row("    7", " C4'", "  U", "B", "   2"),
+            ]
+        );
+        let lines: Vec<&str> = pdb.lines().collect();
+        assert_eq!(*lines.first().unwrap(), "TITLE     design");
+        assert_eq!(*lines.get(5).unwrap(), "TER       5        C A   4");
+        assert_eq!(*lines.get(8).unwrap(), "TER       8        U B   2");
+        assert_eq!(*lines.last().unwrap(), "END");
+        // Coordinates, occupancy, temperature factor and element
+        assert_eq!(
+            &lines.get(2).unwrap()[30..],
+            "   6.000   0.000   0.000  1.00  0.00           C"
+        );
+
+        let pdb: String = format_pdb("design", &strands, PdbAtoms::Coarse);
+        let names: Vec<(String, String)> = atoms(&pdb)
+            .into_iter()
+            .map(|(_, atom, residue, _, _)| (residue, atom))
+            .collect();
+        let expected: Vec<(String, String)> = [
+            ("G", "N9"),
+            ("A", "N9"),
+            ("U", "N1"),
+            ("C", "N1"),
+            ("C", "N1"),
+            ("U", "N1"),
+        ]
+        .iter()
+        .flat_map(|(residue, nitrogen)| {
+            [" P  ", " C4'", &format!(" {nitrogen} ")]
+                .map(|atom| (format!("  {residue}"), atom.to_string()))
+        })
+        .collect();
+        assert_eq!(names, expected);
+        assert!(
+            pdb.lines()
+                .filter(|line| line.starts_with("ATOM  "))
+                .all(|line| line.len() == 78 && line.ends_with(&line[13..14]))
+        );
+    }
+
+    #[test]
+    fn pdb_serials_and_residue_numbers_wrap_around() {
+        let sequence: Vec<Nucleotide> = vec![Nucleotide::A; 34_000];
+        // Folded back every hundred nucleotides, so that coordinates keep to their columns
+        let trace: Vec<NucleotideFrame> = straight_trace(34_000)
+            .into_iter()
+            .enumerate()
+            .map(|(index, frame)| NucleotideFrame {
+                backbone: frame.backbone - Vec3A::X * 600.0 * (index / 100) as f32
+                    + Vec3A::Z * 20.0 * (index / 100 % 100) as f32,
+                ..frame
+            })
+            .collect();
+        let pdb: String = format_pdb("long", &[(&sequence, &trace)], PdbAtoms::Coarse);
+        let atoms: Vec<&str> = pdb
+            .lines()
+            .filter(|line| line.starts_with("ATOM  "))
+            .collect();
+        assert_eq!(atoms.len(), 102_000);
+
+        // Atom 100 000 and residue 10 000 are numbered 0, and numbering starts again
+        assert_eq!(&atoms.get(99_998).unwrap()[6..11], "99999");
+        assert_eq!(&atoms.get(99_999).unwrap()[6..11], "    0");
+        assert_eq!(&atoms.get(100_000).unwrap()[6..11], "    1");
+        assert_eq!(&atoms.get(3 * 9_998).unwrap()[22..26], "9999");
+        assert_eq!(&atoms.get(3 * 9_999).unwrap()[22..26], "   0");
+        assert_eq!(&atoms.get(3 * 10_000).unwrap()[22..26], "   1");
+        assert!(atoms.iter().all(|line| line.len() == 78));
+        assert!(pdb.ends_with("TER    2001        A A4000\nEND\n"));
+    }
+
+    #[test]
+    fn oxrna_files_list_strands_three_to_five_prime() {
+        let first: RnaSequence = "GAUC".parse().unwrap();
+        let second: RnaSequence = "GAU".parse().unwrap();
+        let (first_trace, second_trace): (Vec<NucleotideFrame>, Vec<NucleotideFrame>) =
+            (straight_trace(4), straight_trace(3));
+        // The A and U of the first strand pair with the U and A of the second
+        let files: OxrnaFiles = format_oxrna(
+            &[(&first, &first_trace), (&second, &second_trace)],
+            &[(1, 6), (2, 5)],
+        );
+
+        assert_eq!(
+            files.topology.lines().collect::<Vec<&str>>(),
+            vec![
+                "7 2", "1 C -1 1", "1 U 0 2", "1 A 1 3", "1 G 2 -1", "2 U -1 5", "2 A 4 6",
+                "2 G 5 -1",
+            ]
+        );
+
+        // Positions in the same order, the 3' end of the first strand first
+        let lines: Vec<&str> = files.configuration.lines().collect();
+        assert_eq!(lines.len(), 3 + 7);
+        let side: f32 = lines.get(1).unwrap()[4..]
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let x: Vec<f32> = lines[3..]
+            .iter()
+            .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+            .collect();
+        assert!(x.iter().all(|x| *x > 0.0 && *x < side));
+        let step: f32 = 6.0 / OXDNA_LENGTH_UNIT;
+        assert!((x[0] - x[3] - 3.0 * step).abs() < 1e-3);
+        assert!((x[4] - x[6] - 2.0 * step).abs() < 1e-3);
+        assert!((x[3] - x[6]).abs() < 1e-3);
+
+        // Each pair is trapped both ways, between the reordered particles
+        let traps: Vec<(usize, usize)> = files
+            .traps
+            .split("}\n")
+            .filter(|block| !block.is_empty())
+            .map(|block| {
+                let value = |key: &str| -> usize {
+                    block
+                        .lines()
+                        .find_map(|line| line.strip_prefix(key))
+                        .unwrap()
+                        .parse()
+                        .unwrap()
+                };
+                assert!(block.contains("type = mutual_trap\n"));
+                (value("particle = "), value("ref_particle = "))
+            })
+            .collect();
+        assert_eq!(traps, vec![(2, 4), (4, 2), (1, 5), (5, 1)]);
+    }
+}
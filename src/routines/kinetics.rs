@@ -0,0 +1,388 @@
+use std::fmt::{Display, Formatter};
+
+use nanorand::{Rng, WyRand};
+use rustc_hash::FxHashMap;
+
+use super::{
+    energy::{KT, MAX_INTERIOR, branched_loop_energy, can_pair},
+    nucleotide::Nucleotide,
+};
+
+// Time is in units of the elementary move attempt, as in Kinfold
+#[derive(Debug, Clone, Copy)]
+pub struct KineticSettings {
+    pub transcription_rate: f64, // Nucleotides appended per time unit
+    pub extra_time: f64,         // Simulated time after the last nucleotide is appended
+    pub trajectories: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for KineticSettings {
+    fn default() -> Self {
+        KineticSettings {
+            transcription_rate: 0.05,
+            extra_time: 1000.0,
+            trajectories: 10,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KineticResult {
+    pub trajectories: usize,
+    pub reached_at_transcription_end: usize, // Trajectories in the target structure when the last nucleotide is appended
+    pub reached_at_end: usize, // Trajectories in the target structure at the end of the extra time
+    pub mean_distance: f32, // Base pairs differing from the target at the end, averaged over trajectories
+}
+
+impl Display for KineticResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Target reached in {}/{} trajectories at the end of transcription, {}/{} at the end, mean distance {:.1} bp",
+            self.reached_at_transcription_end,
+            self.trajectories,
+            self.reached_at_end,
+            self.trajectories,
+            self.mean_distance
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    Add(usize, usize),
+    Remove(usize, usize),
+}
+
+// Loop of the current structure, with the single-pair moves changing it
+// Its moves are the pairs that can form in it and the removal of the pairs directly inside it
+struct Loop {
+    branches: Vec<(usize, usize)>, // Pairs directly inside the loop, in order
+    unpaired: usize,
+    energy: f32,
+    moves: Vec<(Move, f64)>,
+    total: f64,
+}
+
+// Loops keyed by their closing pair, None for the exterior loop
+type Loops = FxHashMap<Option<(usize, usize)>, Loop>;
+
+// Loop energy, without the interior loops and bulges the partition function leaves out
+fn capped_loop_energy(
+    sequence: &[Nucleotide],
+    closing: Option<(usize, usize)>,
+    branches: &[(usize, usize)],
+    unpaired: usize,
+) -> f32 {
+    if closing.is_some() && branches.len() == 1 && unpaired > MAX_INTERIOR {
+        f32::INFINITY
+    } else {
+        branched_loop_energy(sequence, closing, branches, unpaired)
+    }
+}
+
+// Metropolis rate of a move changing the free energy by the given amount
+fn metropolis(change: f32) -> f64 {
+    (-change as f64 / KT as f64).exp().min(1.0)
+}
+
+// Secondary structure of a growing transcript
+struct Trajectory<'a> {
+    sequence: &'a [Nucleotide],
+    partners: Vec<Option<usize>>, // One entry per transcribed nucleotide
+    loops: Loops,
+}
+
+impl Trajectory<'_> {
+    fn set_pair(&mut self, i: usize, j: usize, paired: bool) {
+        *self.partners.get_mut(i).unwrap() = paired.then_some(j);
+        *self.partners.get_mut(j).unwrap() = paired.then_some(i);
+    }
+
+    // Innermost pair enclosing position i, None if it is in the exterior loop
+    fn enclosing(&self, i: usize) -> Option<(usize, usize)> {
+        let mut k: usize = i;
+        while k > 0 {
+            k -= 1;
+            match *self.partners.get(k).unwrap() {
+                Some(l) if l > k => return Some((k, l)),
+                Some(l) => k = l,
+                None => {}
+            }
+        }
+        None
+    }
+
+    // Rates of every move changing the loop
+    // Energy changes only involve the loops a move splits or merges, the others are left as they are
+    fn update_loop(&mut self, closing: Option<(usize, usize)>) {
+        let (start, end): (usize, usize) = match closing {
+            Some((i, j)) => (i + 1, j),
+            None => (0, self.partners.len()),
+        };
+        let mut branches: Vec<(usize, usize)> = Vec::new();
+        let mut unpaired: Vec<usize> = Vec::new();
+        let mut k: usize = start;
+        while k < end {
+            match *self.partners.get(k).unwrap() {
+                Some(l) => {
+                    branches.push((k, l));
+                    k = l + 1;
+                }
+                None => {
+                    unpaired.push(k);
+                    k += 1;
+                }
+            }
+        }
+        let energy: f32 = capped_loop_energy(self.sequence, closing, &branches, unpaired.len());
+
+        let mut moves: Vec<(Move, f64)> = Vec::new();
+        // A new pair (i, j) splits the loop, taking the branches between i and j
+        unpaired.iter().enumerate().for_each(|(first, &i)| {
+            unpaired
+                .iter()
+                .enumerate()
+                .skip(first + 1)
+                .filter(|&(_, &j)| can_pair(self.sequence, i, j))
+                .for_each(|(second, &j)| {
+                    let inner_start: usize = branches.partition_point(|&(k, _)| k < i);
+                    let inner_end: usize = branches.partition_point(|&(k, _)| k < j);
+                    let outer: Vec<(usize, usize)> = branches[..inner_start]
+                        .iter()
+                        .copied()
+                        .chain([(i, j)])
+                        .chain(branches[inner_end..].iter().copied())
+                        .collect();
+                    let change: f32 = capped_loop_energy(
+                        self.sequence,
+                        Some((i, j)),
+                        &branches[inner_start..inner_end],
+                        second - first - 1,
+                    ) + capped_loop_energy(
+                        self.sequence,
+                        closing,
+                        &outer,
+                        unpaired.len() - (second - first + 1),
+                    ) - energy;
+                    moves.push((Move::Add(i, j), metropolis(change)));
+                })
+        });
+        // Removing a pair merges the loop it closes into this one
+        branches.iter().enumerate().for_each(|(index, &(k, l))| {
+            let inner: &Loop = self.loops.get(&Some((k, l))).unwrap();
+            let merged: Vec<(usize, usize)> = branches[..index]
+                .iter()
+                .chain(&inner.branches)
+                .chain(&branches[index + 1..])
+                .copied()
+                .collect();
+            let change: f32 = capped_loop_energy(
+                self.sequence,
+                closing,
+                &merged,
+                unpaired.len() + inner.unpaired + 2,
+            ) - energy
+                - inner.energy;
+            moves.push((Move::Remove(k, l), metropolis(change)));
+        });
+        moves.retain(|(_, rate)| *rate > 0.0);
+
+        let total: f64 = moves.iter().map(|(_, rate)| rate).sum();
+        self.loops.insert(
+            closing,
+            Loop {
+                branches,
+                unpaired: unpaired.len(),
+                energy,
+                moves,
+                total,
+            },
+        );
+    }
+
+    // Loop, its parent changes too as the removal of its closing pair is one of the parent moves
+    fn update_around(&mut self, closing: Option<(usize, usize)>) {
+        self.update_loop(closing);
+        if let Some((i, _)) = closing {
+            self.update_loop(self.enclosing(i));
+        }
+    }
+
+    fn apply(&mut self, changed: Option<(usize, usize)>, chosen: Move) {
+        match chosen {
+            Move::Add(i, j) => {
+                self.set_pair(i, j, true);
+                self.update_loop(Some((i, j)));
+            }
+            Move::Remove(i, j) => {
+                self.set_pair(i, j, false);
+                self.loops.remove(&Some((i, j)));
+            }
+        }
+        self.update_around(changed);
+    }
+
+    fn transcribe(&mut self) {
+        self.partners.push(None);
+        self.update_loop(None);
+    }
+
+    // Pick a move with probability proportional to its rate
+    fn pick(&self, mut target: f64) -> Option<(Option<(usize, usize)>, Move)> {
+        let mut last: Option<(Option<(usize, usize)>, Move)> = None;
+        for (closing, Loop { moves, total, .. }) in self.loops.iter() {
+            if target >= *total {
+                target -= total;
+                continue;
+            }
+            for (chosen, rate) in moves {
+                last = Some((*closing, *chosen));
+                if target < *rate {
+                    return last;
+                }
+                target -= rate;
+            }
+        }
+        // Rounding errors can leave the target past the last move
+        last
+    }
+
+    fn total_rate(&self) -> f64 {
+        self.loops.values().map(|current| current.total).sum()
+    }
+
+    // Advance the structure from time to end, returns end
+    fn run_until(&mut self, mut time: f64, end: f64, rng: &mut WyRand) -> f64 {
+        loop {
+            let total: f64 = self.total_rate();
+            if total <= 0.0 {
+                return end;
+            }
+            time += -(1.0 - rng.generate::<f64>()).ln() / total;
+            if time >= end {
+                return end;
+            }
+            if let Some((changed, chosen)) = self.pick(rng.generate::<f64>() * total) {
+                self.apply(changed, chosen);
+            }
+        }
+    }
+}
+
+// Base pairs present in one structure but not the other
+fn base_pair_distance(first: &[Option<usize>], second: &[Option<usize>]) -> usize {
+    first
+        .iter()
+        .zip(second)
+        .enumerate()
+        .map(|(i, (first, second))| {
+            [first, second]
+                .iter()
+                .filter(|partner| partner.is_some_and(|j| j > i))
+                .count()
+                * usize::from(first != second)
+        })
+        .sum()
+}
+
+// Fold a sequence as it is transcribed, with stochastic single base pair moves (Gillespie algorithm)
+// The target must be pseudoknot-free, as the simulated structures are
+pub fn simulate_cotranscriptional_folding(
+    sequence: &[Nucleotide],
+    target: &[Option<usize>],
+    settings: &KineticSettings,
+) -> KineticResult {
+    let rng: &mut WyRand = &mut match settings.seed {
+        Some(seed) => WyRand::new_seed(seed),
+        None => WyRand::new(),
+    };
+    let step: f64 = 1.0 / settings.transcription_rate.max(f64::EPSILON);
+
+    let mut result: KineticResult = KineticResult {
+        trajectories: settings.trajectories,
+        reached_at_transcription_end: 0,
+        reached_at_end: 0,
+        mean_distance: 0.0,
+    };
+    (0..settings.trajectories).for_each(|_| {
+        let mut trajectory: Trajectory = Trajectory {
+            sequence,
+            partners: Vec::new(),
+            loops: Loops::default(),
+        };
+        let mut time: f64 = 0.0;
+        (0..sequence.len()).for_each(|index| {
+            trajectory.transcribe();
+            if index + 1 < sequence.len() {
+                time = trajectory.run_until(time, time + step, rng);
+            }
+        });
+        if trajectory.partners == target {
+            result.reached_at_transcription_end += 1;
+        }
+        trajectory.run_until(time, time + settings.extra_time, rng);
+        if trajectory.partners == target {
+            result.reached_at_end += 1;
+        }
+        result.mean_distance += base_pair_distance(&trajectory.partners, target) as f32;
+    });
+    result.mean_distance /= settings.trajectories.max(1) as f32;
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routines::{energy::loop_energy, nucleotide::RnaSequence};
+
+    #[test]
+    fn loops_keep_up_with_the_structure() {
+        let sequence: RnaSequence = "GGGAAACCCAGCGUUAAACGCUAUGGACUUCGGUCCAU".parse().unwrap();
+        let rng: &mut WyRand = &mut WyRand::new_seed(3);
+        let mut trajectory: Trajectory = Trajectory {
+            sequence: &sequence,
+            partners: Vec::new(),
+            loops: Loops::default(),
+        };
+        let mut time: f64 = 0.0;
+        (0..sequence.len()).for_each(|_| {
+            trajectory.transcribe();
+            time = trajectory.run_until(time, time + 20.0, rng);
+            trajectory.loops.iter().for_each(|(closing, current)| {
+                let expected: f32 = loop_energy(&sequence, &trajectory.partners, *closing);
+                assert!((current.energy - expected).abs() < 1e-4);
+            });
+            let pairs: usize = trajectory
+                .partners
+                .iter()
+                .filter(|partner| partner.is_some())
+                .count();
+            assert_eq!(trajectory.loops.len(), pairs / 2 + 1);
+        });
+    }
+
+    #[test]
+    fn interior_loops_are_as_bounded_as_in_the_ensemble() {
+        let sequence: RnaSequence = format!("GGGG{}CCCC", "A".repeat(2 * MAX_INTERIOR))
+            .parse()
+            .unwrap();
+        let branches: [(usize, usize); 1] = [(2, sequence.len() - 3)];
+        assert!(
+            capped_loop_energy(&sequence, Some((0, sequence.len() - 1)), &branches, 2).is_finite()
+        );
+        let branches: [(usize, usize); 1] = [(3 + MAX_INTERIOR, sequence.len() - 3)];
+        assert!(
+            capped_loop_energy(
+                &sequence,
+                Some((0, sequence.len() - 1)),
+                &branches,
+                MAX_INTERIOR + 3
+            )
+            .is_infinite()
+        );
+    }
+}
@@ -1,4 +1,21 @@
+pub mod analysis;
+pub mod candidates;
+pub mod cli;
+pub mod constraints;
+pub mod cotranscription;
+pub mod energy;
+pub mod ensemble;
+pub mod geometry;
 pub mod graph;
+pub mod graph_file;
 pub mod io;
+pub mod kinetics;
+pub mod model;
+pub mod motifs;
+pub mod nucleotide;
+pub mod primers;
 pub mod sequencer;
+pub mod structure;
+pub mod template;
+pub mod thermodynamics;
 pub mod user_interface;
@@ -0,0 +1,312 @@
+use std::f32::consts::PI;
+
+use glam::Vec3A;
+use rustc_hash::FxHashMap;
+
+use super::{
+    geometry::HELIX_RISE_NM,
+    graph::{Tree, node_position},
+    sequencer::{GeneratedSequence, Role, Source},
+};
+
+// Coarse A-form helix, distances in ångströms
+pub const BACKBONE_RADIUS: f32 = 7.8; // C4' atoms from the helix axis
+pub const HELIX_TWIST: f32 = 32.7 * PI / 180.0; // Per base pair
+// Angle about the axis between the backbones of a base pair, across the minor groove
+pub const PAIR_ANGLE: f32 = 140.0 * PI / 180.0;
+// Distance between consecutive C4' atoms of single-stranded regions
+pub const BACKBONE_STEP: f32 = 6.0;
+// Distance from the C4' atom to the glycosidic nitrogen (N1 or N9)
+pub const BASE_DISTANCE: f32 = 3.4;
+
+// Position of a nucleotide in the coarse-grained model, in ångströms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NucleotideFrame {
+    pub backbone: Vec3A, // C4' atom
+    pub base: Vec3A,     // Unit vector from the backbone towards the base
+    pub normal: Vec3A,   // Unit normal of the base plane, facing the 5' neighbour it stacks on
+}
+
+impl NucleotideFrame {
+    pub fn base_atom(&self) -> Vec3A {
+        self.backbone + self.base * BASE_DISTANCE
+    }
+}
+
+// Coarse-grained 3D trace of a generated sequence, one frame per nucleotide
+// Sense strands turn along their edge as A-form helices, the antisense strands facing them
+// Loops, linkers and modules are laid on arcs between the helices they join
+pub fn build_trace(
+    sequence: &GeneratedSequence,
+    tree: &Tree,
+    node_coordinates: &[Vec3A],
+) -> Vec<NucleotideFrame> {
+    // One coordinate unit is one base pair
+    let scale: f32 = HELIX_RISE_NM * 10.0;
+    let mut frames: Vec<Option<NucleotideFrame>> = vec![None; sequence.len()];
+
+    let mut strands: FxHashMap<(usize, usize), Vec<usize>> = FxHashMap::default();
+    sequence
+        .nucleotides
+        .iter()
+        .enumerate()
+        .for_each(|(index, nucleotide)| {
+            if let (Source::Edge(edge), Role::Sense) = (nucleotide.source, nucleotide.role) {
+                strands.entry(edge).or_default().push(index);
+            }
+        });
+    strands
+        .iter()
+        .for_each(|(&(origin, destination), indices)| {
+            let start: Vec3A = node_position(tree, node_coordinates, origin) * scale;
+            let end: Vec3A = node_position(tree, node_coordinates, destination) * scale;
+            let Some(axis) = (end - start).try_normalize() else {
+                return;
+            };
+            let (first, second): (Vec3A, Vec3A) = axis.any_orthonormal_pair();
+            let spacing: f32 = start.distance(end) / indices.len() as f32;
+            // The sense strand runs along the axis and the antisense strand back
+            let frame = |centre: Vec3A, angle: f32, normal: Vec3A| {
+                let radial: Vec3A = first * angle.cos() + second * angle.sin();
+                NucleotideFrame {
+                    backbone: centre + radial * BACKBONE_RADIUS,
+                    base: -radial,
+                    normal,
+                }
+            };
+
+            indices.iter().enumerate().for_each(|(level, &index)| {
+                let centre: Vec3A = start + axis * spacing * (level as f32 + 0.5);
+                let angle: f32 = HELIX_TWIST * level as f32;
+                *frames.get_mut(index).unwrap() = Some(frame(centre, angle, -axis));
+                if let Some(partner) = sequence.nucleotides.get(index).unwrap().partner
+                    && sequence.nucleotides.get(partner).unwrap().role == Role::Antisense
+                {
+                    *frames.get_mut(partner).unwrap() =
+                        Some(frame(centre, angle + PAIR_ANGLE, axis));
+                }
+            });
+        });
+
+    let placed: Vec<usize> = (0..frames.len())
+        .filter(|index| frames.get(*index).unwrap().is_some())
+        .collect();
+    let (Some(&first), Some(&last)) = (placed.first(), placed.last()) else {
+        // Without any helix, the strand is laid straight
+        return (0..sequence.len())
+            .map(|index| NucleotideFrame {
+                backbone: Vec3A::X * BACKBONE_STEP * index as f32,
+                base: Vec3A::Y,
+                normal: -Vec3A::X,
+            })
+            .collect();
+    };
+    placed
+        .windows(2)
+        .filter(|pair| pair[1] > pair[0] + 1)
+        .for_each(|pair| lay_arc(&mut frames, pair[0], pair[1]));
+    lay_tail(&mut frames, first, placed.get(1).copied(), 0..first);
+    lay_tail(
+        &mut frames,
+        last,
+        placed.iter().rev().nth(1).copied(),
+        last + 1..sequence.len(),
+    );
+
+    frames.into_iter().map(Option::unwrap).collect()
+}
+
+// Nucleotides between two placed ones, on an arc long enough to keep the backbone step
+// The arc bulges away from the nucleotides on either side, out of the helices or junction
+fn lay_arc(frames: &mut [Option<NucleotideFrame>], from: usize, to: usize) {
+    let start: Vec3A = frames.get(from).unwrap().unwrap().backbone;
+    let end: Vec3A = frames.get(to).unwrap().unwrap().backbone;
+    let chord: Vec3A = end - start;
+    let neighbours: Vec<Vec3A> = (from.saturating_sub(3)..=from)
+        .chain(to..(to + 4).min(frames.len()))
+        .filter_map(|index| frames.get(index).unwrap().map(|frame| frame.backbone))
+        .collect();
+    let centre: Vec3A = neighbours.iter().sum::<Vec3A>() / neighbours.len() as f32;
+    let away: Vec3A = (start + end) * 0.5 - centre;
+    let outward: Vec3A = chord
+        .try_normalize()
+        .map_or(away, |direction| away - direction * away.dot(direction))
+        .try_normalize()
+        .or_else(|| {
+            chord
+                .try_normalize()
+                .map(|direction| direction.any_orthonormal_vector())
+        })
+        .unwrap_or(Vec3A::Z);
+
+    let count: usize = to - from - 1;
+    let length: f32 = (count + 1) as f32 * BACKBONE_STEP;
+    let span: f32 = chord.length();
+    let along: Vec3A = chord
+        .try_normalize()
+        .unwrap_or(outward.any_orthonormal_vector());
+    if length <= span {
+        // Stretched across a gap too long for the loop
+        (1..=count).for_each(|step| {
+            *frames.get_mut(from + step).unwrap() = Some(NucleotideFrame {
+                backbone: start + chord * step as f32 / (count + 1) as f32,
+                base: -outward,
+                normal: -along,
+            });
+        });
+        return;
+    }
+
+    // Circular arc of the loop length through both ends, its angle found by bisection
+    let mut bounds: (f32, f32) = (0.0, 2.0 * PI);
+    (0..40).for_each(|_| {
+        let angle: f32 = 0.5 * (bounds.0 + bounds.1);
+        if angle * span < 2.0 * length * (0.5 * angle).sin() {
+            bounds.0 = angle;
+        } else {
+            bounds.1 = angle;
+        }
+    });
+    let angle: f32 = 0.5 * (bounds.0 + bounds.1);
+    let radius: f32 = length / angle;
+    let centre: Vec3A = (start + end) * 0.5 - outward * radius * (0.5 * angle).cos();
+    (1..=count).for_each(|step| {
+        let position: f32 = angle * (step as f32 / (count + 1) as f32 - 0.5);
+        let radial: Vec3A = outward * position.cos() + along * position.sin();
+        *frames.get_mut(from + step).unwrap() = Some(NucleotideFrame {
+            backbone: centre + radial * radius,
+            base: -radial,
+            normal: outward * position.sin() - along * position.cos(),
+        });
+    });
+}
+
+// Nucleotides before the first or after the last placed one, continuing away from its neighbour
+// Single-stranded ends stack much like one strand of a helix, so they are coiled rather than laid straight
+fn lay_tail(
+    frames: &mut [Option<NucleotideFrame>],
+    end: usize,
+    neighbour: Option<usize>,
+    indices: std::ops::Range<usize>,
+) {
+    let anchor: NucleotideFrame = frames.get(end).unwrap().unwrap();
+    let axis: Vec3A = neighbour
+        .and_then(|neighbour| {
+            (anchor.backbone - frames.get(neighbour).unwrap().unwrap().backbone).try_normalize()
+        })
+        .unwrap_or(-anchor.base);
+    let (first, second): (Vec3A, Vec3A) = axis.any_orthonormal_pair();
+    let radial = |angle: f32| first * angle.cos() + second * angle.sin();
+    indices.for_each(|index| {
+        let level: f32 = index.abs_diff(end) as f32;
+        let angle: f32 = HELIX_TWIST * level;
+        *frames.get_mut(index).unwrap() = Some(NucleotideFrame {
+            backbone: anchor.backbone
+                + axis * HELIX_RISE_NM * 10.0 * level
+                + (radial(angle) - radial(0.0)) * BACKBONE_RADIUS,
+            base: -radial(angle),
+            normal: if index < end { axis } else { -axis },
+        });
+    });
+}
+
+// Phosphate of a nucleotide, between its C4' atom and that of the previous nucleotide
+pub fn phosphate(trace: &[NucleotideFrame], index: usize) -> Vec3A {
+    let frame: &NucleotideFrame = trace.get(index).unwrap();
+    let previous: Vec3A = match index.checked_sub(1) {
+        Some(previous) => trace.get(previous).unwrap().backbone,
+        None => match trace.get(1) {
+            Some(next) => 2.0 * frame.backbone - next.backbone,
+            None => frame.backbone - frame.base * BACKBONE_STEP,
+        },
+    };
+    // Phosphates sit on the outside of the backbone
+    0.5 * (previous + frame.backbone) - frame.base
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routines::{
+        graph::{construct_tree, find_rna_path},
+        sequencer::{NucleotideRecord, SequencerSettings, generate_sequence},
+    };
+
+    #[test]
+    fn helices_rise_and_turn_as_a_form() {
+        let coordinates: Vec<Vec3A> = vec![
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(20.0, 0.0, 0.0),
+            Vec3A::new(-10.0, 20.0, 0.0),
+            Vec3A::new(-10.0, -20.0, 0.0),
+            Vec3A::new(20.0, 0.0, 20.0),
+        ];
+        let mut tree: Tree = construct_tree(&[(0, 1), (0, 2), (0, 3), (1, 4)]);
+        let path: Vec<usize> = find_rna_path(&mut tree, &coordinates);
+        let settings: SequencerSettings = SequencerSettings {
+            seed: Some(0),
+            ..Default::default()
+        };
+        let sequence: GeneratedSequence =
+            generate_sequence(&path, &tree, &coordinates, &settings).unwrap();
+        let trace: Vec<NucleotideFrame> = build_trace(&sequence, &tree, &coordinates);
+        assert_eq!(trace.len(), sequence.len());
+
+        let rise: f32 = HELIX_RISE_NM * 10.0;
+        let pair_distance: f32 = 2.0 * BACKBONE_RADIUS * (0.5 * PAIR_ANGLE).sin();
+        tree.edges.iter().for_each(|&(origin, destination)| {
+            let axis: Vec3A = (node_position(&tree, &coordinates, destination)
+                - node_position(&tree, &coordinates, origin))
+            .normalize();
+            let sense: Vec<usize> = (0..sequence.len())
+                .filter(|&index| {
+                    let nucleotide: &NucleotideRecord = sequence.nucleotides.get(index).unwrap();
+                    nucleotide.source == Source::Edge((origin, destination))
+                        && nucleotide.role == Role::Sense
+                })
+                .collect();
+            assert!(!sense.is_empty());
+
+            // One base pair further along the axis and one twist further round it
+            sense.windows(2).for_each(|pair| {
+                let (first, second): (&NucleotideFrame, &NucleotideFrame) =
+                    (trace.get(pair[0]).unwrap(), trace.get(pair[1]).unwrap());
+                let step: Vec3A = second.backbone - first.backbone;
+                assert!((step.dot(axis) - rise).abs() < 0.05 * rise, "{step}");
+                let (first_radial, second_radial): (Vec3A, Vec3A) = (
+                    first.base.reject_from(axis).normalize(),
+                    second.base.reject_from(axis).normalize(),
+                );
+                assert!((first_radial.angle_between(second_radial) - HELIX_TWIST).abs() < 1e-3);
+            });
+
+            // Partners sit at the same level, across the minor groove
+            sense.iter().for_each(|&index| {
+                let partner: usize = sequence.nucleotides.get(index).unwrap().partner.unwrap();
+                let (frame, other): (&NucleotideFrame, &NucleotideFrame) =
+                    (trace.get(index).unwrap(), trace.get(partner).unwrap());
+                let across: Vec3A = other.backbone - frame.backbone;
+                assert!(across.dot(axis).abs() < 1e-3);
+                assert!((across.length() - pair_distance).abs() < 1e-3);
+                assert!(frame.normal.dot(other.normal) < -0.999);
+            });
+        });
+
+        // Loops keep their nucleotides a backbone step apart at most
+        trace
+            .windows(2)
+            .zip(sequence.nucleotides.windows(2))
+            .filter(|(_, nucleotides)| {
+                nucleotides
+                    .iter()
+                    .all(|nucleotide| !matches!(nucleotide.role, Role::Sense | Role::Antisense))
+            })
+            .for_each(|(frames, _)| {
+                let step: f32 = frames[0].backbone.distance(frames[1].backbone);
+                assert!(
+                    step > 0.5 * BACKBONE_STEP && step < BACKBONE_STEP + 1e-3,
+                    "{step}"
+                );
+            });
+    }
+}
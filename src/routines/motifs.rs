@@ -0,0 +1,227 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use super::{
+    analysis::{format_dot_bracket, parse_dot_bracket},
+    nucleotide::RnaSequence,
+};
+
+// Sequence with its own target structure, displayed on the design (aptamers, protein-binding hairpins...)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionalModule {
+    pub name: String,
+    pub sequence: RnaSequence,
+    pub partners: Vec<Option<usize>>, // Pairs within the module
+}
+
+impl FunctionalModule {
+    pub fn len(&self) -> usize {
+        self.sequence.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequence.is_empty()
+    }
+}
+
+impl Display for FunctionalModule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.name,
+            self.sequence,
+            format_dot_bracket(&self.partners)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidModuleError;
+
+impl FromStr for FunctionalModule {
+    type Err = InvalidModuleError;
+
+    // Modules are written as `<name> <sequence> <dot-bracket structure>`
+    // The structure must be as long as the sequence, and its pairs canonical
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let [name, sequence, structure] = words.as_slice() else {
+            return Err(InvalidModuleError);
+        };
+        let sequence: RnaSequence = sequence.parse().map_err(|_| InvalidModuleError)?;
+        let partners: Vec<Option<usize>> =
+            parse_dot_bracket(structure).map_err(|_| InvalidModuleError)?;
+        if partners.len() != sequence.len()
+            || partners.iter().enumerate().any(|(index, partner)| {
+                partner.is_some_and(|partner| {
+                    !sequence
+                        .get(index)
+                        .unwrap()
+                        .pairs_with(*sequence.get(partner).unwrap())
+                })
+            })
+        {
+            return Err(InvalidModuleError);
+        }
+
+        Ok(FunctionalModule {
+            name: name.to_string(),
+            sequence,
+            partners,
+        })
+    }
+}
+
+// Built-in modules, others can be written in the same format as custom modules
+// Pepper (Chen et al., 2019) is left out until its minimal sequence and fold are checked against the paper,
+// its published constructs differing in their stems, so the construct in use is given as a custom module
+const LIBRARY: [&str; 4] = [
+    // MS2 coat protein binding hairpin
+    "MS2 ACAUGAGGAUUACCCAUGU (((((.((....)))))))",
+    // PP7 coat protein binding hairpin
+    "PP7 GGAGCAGACGAUAUGGCGUCGCUCC (((((.((((......)))))))))",
+    // λN peptide binding hairpin
+    "BoxB GCCCUGAAAAAGGGC (((((.....)))))",
+    // DFHBI-1T binding fluorogenic aptamer, its G-quadruplex core is left unpaired
+    "Broccoli GAGACGGUCGGGUCCAGAUAUUCGUAUCUGUCGAGUAGAGUGUGGGCUC (((...........((((((....))))))................)))",
+];
+
+pub fn module_library() -> Vec<FunctionalModule> {
+    LIBRARY
+        .iter()
+        .map(|module| module.parse().unwrap())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModulePlacement {
+    Hairpin(usize), // Replaces the loop closing a leaf node
+    // Inserted in the strand travelling from origin to destination, after offset nucleotides
+    Edge { edge: (usize, usize), offset: usize },
+}
+
+impl Display for ModulePlacement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModulePlacement::Hairpin(node) => write!(f, "hairpin {node}"),
+            ModulePlacement::Edge {
+                edge: (origin, destination),
+                offset,
+            } => write!(f, "edge {origin} {destination} {offset}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleInsertion {
+    pub placement: ModulePlacement,
+    pub module: FunctionalModule,
+}
+
+impl Display for ModuleInsertion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.placement, self.module.name)
+    }
+}
+
+impl ModuleInsertion {
+    // Insertions are written as `hairpin <node> <module>` or `edge <origin> <destination> <offset> <module>`
+    // Modules are looked up by name, ignoring case
+    pub fn parse(s: &str, library: &[FunctionalModule]) -> Result<Self, InvalidModuleError> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let parse_index = |word: &str| word.parse::<usize>().map_err(|_| InvalidModuleError);
+        let (placement, name): (ModulePlacement, &str) = match words.as_slice() {
+            ["hairpin", node, name] => (ModulePlacement::Hairpin(parse_index(node)?), name),
+            ["edge", origin, destination, offset, name] => (
+                ModulePlacement::Edge {
+                    edge: (parse_index(origin)?, parse_index(destination)?),
+                    offset: parse_index(offset)?,
+                },
+                name,
+            ),
+            _ => return Err(InvalidModuleError),
+        };
+
+        Ok(ModuleInsertion {
+            placement,
+            module: library
+                .iter()
+                .find(|module| module.name.eq_ignore_ascii_case(name))
+                .ok_or(InvalidModuleError)?
+                .clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_structures_match_their_sequences() {
+        LIBRARY.iter().for_each(|module| {
+            let words: Vec<&str> = module.split_whitespace().collect();
+            let [name, sequence, structure] = words.as_slice() else {
+                panic!("{module}");
+            };
+            let sequence: RnaSequence = sequence.parse().unwrap();
+            assert_eq!(sequence.len(), structure.len(), "{name}");
+
+            // Every bracket closes, and each pair is canonical and listed from both ends
+            let partners: Vec<Option<usize>> = parse_dot_bracket(structure).unwrap();
+            assert_eq!(format_dot_bracket(&partners), *structure, "{name}");
+            partners.iter().enumerate().for_each(|(index, partner)| {
+                if let Some(partner) = partner {
+                    assert_eq!(*partners.get(*partner).unwrap(), Some(index), "{name}");
+                    assert!(
+                        sequence
+                            .get(index)
+                            .unwrap()
+                            .pairs_with(*sequence.get(*partner).unwrap()),
+                        "{name} {index}-{partner}"
+                    );
+                }
+            });
+            assert!(partners.iter().any(|partner| partner.is_some()), "{name}");
+        });
+
+        let library: Vec<FunctionalModule> = module_library();
+        assert_eq!(library.len(), LIBRARY.len());
+        library.iter().enumerate().for_each(|(index, module)| {
+            assert!(
+                library
+                    .iter()
+                    .skip(index + 1)
+                    .all(|other| !other.name.eq_ignore_ascii_case(&module.name))
+            );
+        });
+    }
+
+    #[test]
+    fn modules_with_unbalanced_or_mismatched_structures_are_rejected() {
+        assert!(
+            "Hairpin GGGAAACCC (((...)))"
+                .parse::<FunctionalModule>()
+                .is_ok()
+        );
+        assert!(
+            "Hairpin GGGAAACCC (((...))"
+                .parse::<FunctionalModule>()
+                .is_err()
+        );
+        assert!(
+            "Hairpin GGGAAACCC ((((...)))"
+                .parse::<FunctionalModule>()
+                .is_err()
+        );
+        assert!(
+            "Hairpin GGGAAAACC (((...)))"
+                .parse::<FunctionalModule>()
+                .is_err()
+        );
+        assert!("Hairpin GGGAAACCC".parse::<FunctionalModule>().is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    fmt::{Display, Formatter},
+    ops::Deref,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Nucleotide {
+    A,
+    C,
+    G,
+    U,
+}
+
+impl Nucleotide {
+    pub const ALL: [Nucleotide; 4] = [Nucleotide::A, Nucleotide::C, Nucleotide::G, Nucleotide::U];
+
+    // Watson-Crick complement
+    pub fn complement(self) -> Nucleotide {
+        match self {
+            Nucleotide::A => Nucleotide::U,
+            Nucleotide::C => Nucleotide::G,
+            Nucleotide::G => Nucleotide::C,
+            Nucleotide::U => Nucleotide::A,
+        }
+    }
+
+    pub fn is_watson_crick(self, other: Nucleotide) -> bool {
+        self.complement() == other
+    }
+
+    pub fn is_wobble(self, other: Nucleotide) -> bool {
+        matches!(
+            (self, other),
+            (Nucleotide::G, Nucleotide::U) | (Nucleotide::U, Nucleotide::G)
+        )
+    }
+
+    // Canonical pairing, including G·U wobble pairs
+    pub fn pairs_with(self, other: Nucleotide) -> bool {
+        self.is_watson_crick(other) || self.is_wobble(other)
+    }
+
+    pub fn is_gc(self) -> bool {
+        matches!(self, Nucleotide::G | Nucleotide::C)
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Nucleotide::A => 'A',
+            Nucleotide::C => 'C',
+            Nucleotide::G => 'G',
+            Nucleotide::U => 'U',
+        }
+    }
+}
+
+impl TryFrom<char> for Nucleotide {
+    type Error = InvalidNucleotideError;
+
+    // T is accepted as U, so DNA sequences can be read directly
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value.to_ascii_uppercase() {
+            'A' => Ok(Nucleotide::A),
+            'C' => Ok(Nucleotide::C),
+            'G' => Ok(Nucleotide::G),
+            'U' | 'T' => Ok(Nucleotide::U),
+            _ => Err(InvalidNucleotideError(value)),
+        }
+    }
+}
+
+impl Display for Nucleotide {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidNucleotideError(pub char);
+
+impl Display for InvalidNucleotideError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid nucleotide '{}'", self.0)
+    }
+}
+
+// IUPAC ambiguity code, stored as the set of nucleotides it stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IupacCode(u8);
+
+impl IupacCode {
+    pub const N: IupacCode = IupacCode(0b1111);
+
+    fn bit(nucleotide: Nucleotide) -> u8 {
+        match nucleotide {
+            Nucleotide::A => 0b0001,
+            Nucleotide::C => 0b0010,
+            Nucleotide::G => 0b0100,
+            Nucleotide::U => 0b1000,
+        }
+    }
+
+    pub fn contains(self, nucleotide: Nucleotide) -> bool {
+        self.0 & IupacCode::bit(nucleotide) != 0
+    }
+
+    pub fn nucleotides(self) -> impl Iterator<Item = Nucleotide> {
+        Nucleotide::ALL
+            .into_iter()
+            .filter(move |nucleotide| self.contains(*nucleotide))
+    }
+
+    // Code matching the complements of every nucleotide in this code
+    pub fn complement(self) -> IupacCode {
+        IupacCode(
+            self.nucleotides()
+                .map(|nucleotide| IupacCode::bit(nucleotide.complement()))
+                .fold(0, |bits, bit| bits | bit),
+        )
+    }
+
+    // Nucleotides allowed by both codes, None if they are incompatible
+    pub fn intersection(self, other: IupacCode) -> Option<IupacCode> {
+        match self.0 & other.0 {
+            0 => None,
+            bits => Some(IupacCode(bits)),
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self.0 {
+            0b0001 => 'A',
+            0b0010 => 'C',
+            0b0100 => 'G',
+            0b1000 => 'U',
+            0b0101 => 'R',
+            0b1010 => 'Y',
+            0b0110 => 'S',
+            0b1001 => 'W',
+            0b1100 => 'K',
+            0b0011 => 'M',
+            0b1110 => 'B',
+            0b1101 => 'D',
+            0b1011 => 'H',
+            0b0111 => 'V',
+            _ => 'N',
+        }
+    }
+}
+
+impl From<Nucleotide> for IupacCode {
+    fn from(value: Nucleotide) -> Self {
+        IupacCode(IupacCode::bit(value))
+    }
+}
+
+impl TryFrom<char> for IupacCode {
+    type Error = InvalidNucleotideError;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value.to_ascii_uppercase() {
+            'R' => Ok(IupacCode(0b0101)), // A or G
+            'Y' => Ok(IupacCode(0b1010)), // C or U
+            'S' => Ok(IupacCode(0b0110)), // G or C
+            'W' => Ok(IupacCode(0b1001)), // A or U
+            'K' => Ok(IupacCode(0b1100)), // G or U
+            'M' => Ok(IupacCode(0b0011)), // A or C
+            'B' => Ok(IupacCode(0b1110)), // not A
+            'D' => Ok(IupacCode(0b1101)), // not C
+            'H' => Ok(IupacCode(0b1011)), // not G
+            'V' => Ok(IupacCode(0b0111)), // not U
+            'N' => Ok(IupacCode::N),
+            _ => Nucleotide::try_from(value).map(IupacCode::from),
+        }
+    }
+}
+
+impl Display for IupacCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RnaSequence(Vec<Nucleotide>);
+
+impl RnaSequence {
+    pub fn new() -> Self {
+        RnaSequence(Vec::new())
+    }
+
+    pub fn push(&mut self, nucleotide: Nucleotide) {
+        self.0.push(nucleotide);
+    }
+
+    pub fn reverse_complement(&self) -> RnaSequence {
+        self.0
+            .iter()
+            .rev()
+            .map(|nucleotide| nucleotide.complement())
+            .collect()
+    }
+
+    // Fraction of G and C nucleotides, 0 for an empty sequence
+    pub fn gc_content(&self) -> f32 {
+        gc_content(&self.0)
+    }
+}
+
+// Fraction of G and C nucleotides in a slice, 0 if empty
+pub fn gc_content(nucleotides: &[Nucleotide]) -> f32 {
+    if nucleotides.is_empty() {
+        0.0
+    } else {
+        nucleotides
+            .iter()
+            .filter(|nucleotide| nucleotide.is_gc())
+            .count() as f32
+            / nucleotides.len() as f32
+    }
+}
+
+impl Deref for RnaSequence {
+    type Target = [Nucleotide];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromIterator<Nucleotide> for RnaSequence {
+    fn from_iter<T: IntoIterator<Item = Nucleotide>>(iter: T) -> Self {
+        RnaSequence(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Nucleotide> for RnaSequence {
+    fn extend<T: IntoIterator<Item = Nucleotide>>(&mut self, iter: T) {
+        self.0.extend(iter);
+    }
+}
+
+impl From<Vec<Nucleotide>> for RnaSequence {
+    fn from(value: Vec<Nucleotide>) -> Self {
+        RnaSequence(value)
+    }
+}
+
+impl FromStr for RnaSequence {
+    type Err = InvalidNucleotideError;
+
+    // Whitespace is ignored, so multi-line sequences can be parsed
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(Nucleotide::try_from)
+            .collect()
+    }
+}
+
+impl Display for RnaSequence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|nucleotide| write!(f, "{nucleotide}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nucleotides_parse_and_reject_invalid_characters() {
+        assert_eq!(Nucleotide::try_from('a'), Ok(Nucleotide::A));
+        assert_eq!(Nucleotide::try_from('G'), Ok(Nucleotide::G));
+        assert_eq!(Nucleotide::try_from('T'), Ok(Nucleotide::U));
+        assert_eq!(Nucleotide::try_from('u'), Ok(Nucleotide::U));
+        // The old 'O' sentinel and ambiguity codes are not nucleotides
+        assert_eq!(Nucleotide::try_from('O'), Err(InvalidNucleotideError('O')));
+        assert_eq!(Nucleotide::try_from('N'), Err(InvalidNucleotideError('N')));
+        assert_eq!(Nucleotide::try_from('-'), Err(InvalidNucleotideError('-')));
+
+        assert_eq!(IupacCode::try_from('n'), Ok(IupacCode::N));
+        assert_eq!(IupacCode::try_from('T'), Ok(IupacCode::from(Nucleotide::U)));
+        let purine: IupacCode = IupacCode::try_from('R').unwrap();
+        assert_eq!(
+            purine.nucleotides().collect::<Vec<Nucleotide>>(),
+            vec![Nucleotide::A, Nucleotide::G]
+        );
+        assert_eq!(IupacCode::try_from('X'), Err(InvalidNucleotideError('X')));
+        assert_eq!(IupacCode::try_from('O'), Err(InvalidNucleotideError('O')));
+
+        // Every code prints as the character it was parsed from
+        "ACGURYSWKMBDHVN".chars().for_each(|c| {
+            assert_eq!(IupacCode::try_from(c).unwrap().to_char(), c);
+        });
+    }
+
+    #[test]
+    fn sequences_read_t_as_u_and_ignore_whitespace() {
+        let sequence: RnaSequence = "ACGT\nacgu ".parse().unwrap();
+        assert_eq!(sequence.to_string(), "ACGUACGU");
+        assert_eq!(
+            "ACXG".parse::<RnaSequence>(),
+            Err(InvalidNucleotideError('X'))
+        );
+        assert_eq!("".parse::<RnaSequence>(), Ok(RnaSequence::new()));
+    }
+
+    #[test]
+    fn complements_and_reverse_complements() {
+        let complements: Vec<Nucleotide> = Nucleotide::ALL
+            .iter()
+            .map(|nucleotide| nucleotide.complement())
+            .collect();
+        assert_eq!(
+            complements,
+            vec![Nucleotide::U, Nucleotide::G, Nucleotide::C, Nucleotide::A]
+        );
+
+        let sequence: RnaSequence = "AACGU".parse().unwrap();
+        assert_eq!(sequence.reverse_complement().to_string(), "ACGUU");
+        assert_eq!(sequence.reverse_complement().reverse_complement(), sequence);
+        assert_eq!(RnaSequence::new().reverse_complement(), RnaSequence::new());
+    }
+
+    #[test]
+    fn pairing_includes_wobbles() {
+        let pairs: Vec<(Nucleotide, Nucleotide)> = Nucleotide::ALL
+            .iter()
+            .flat_map(|first| Nucleotide::ALL.iter().map(move |second| (*first, *second)))
+            .filter(|(first, second)| first.pairs_with(*second))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                (Nucleotide::A, Nucleotide::U),
+                (Nucleotide::C, Nucleotide::G),
+                (Nucleotide::G, Nucleotide::C),
+                (Nucleotide::G, Nucleotide::U),
+                (Nucleotide::U, Nucleotide::A),
+                (Nucleotide::U, Nucleotide::G),
+            ]
+        );
+
+        assert!(Nucleotide::G.is_wobble(Nucleotide::U));
+        assert!(!Nucleotide::G.is_watson_crick(Nucleotide::U));
+        assert!(!Nucleotide::A.is_wobble(Nucleotide::C));
+        assert!(Nucleotide::C.is_watson_crick(Nucleotide::G));
+    }
+
+    #[test]
+    fn iupac_intersection_and_complement() {
+        let code = |c: char| -> IupacCode { IupacCode::try_from(c).unwrap() };
+
+        assert_eq!(code('R').intersection(code('S')), Some(code('G')));
+        assert_eq!(code('N').intersection(code('Y')), Some(code('Y')));
+        assert_eq!(code('R').intersection(code('Y')), None);
+        assert_eq!(code('A').intersection(code('C')), None);
+
+        assert_eq!(code('A').complement(), code('U'));
+        assert_eq!(code('R').complement(), code('Y'));
+        assert_eq!(code('K').complement(), code('M'));
+        assert_eq!(code('S').complement(), code('S'));
+        assert_eq!(code('B').complement(), code('V'));
+        assert_eq!(code('N').complement(), IupacCode::N);
+    }
+
+    #[test]
+    fn gc_content_of_sequences() {
+        assert_eq!(RnaSequence::new().gc_content(), 0.0);
+        assert_eq!(gc_content(&[]), 0.0);
+
+        let sequence: RnaSequence = "GCAU".parse().unwrap();
+        assert_eq!(sequence.gc_content(), 0.5);
+        assert_eq!(gc_content(&[Nucleotide::G, Nucleotide::C]), 1.0);
+    }
+}
@@ -0,0 +1,479 @@
+use std::fmt::{Display, Formatter};
+
+use super::{
+    nucleotide::{Nucleotide, RnaSequence},
+    sequencer::GeneratedSequence,
+    template::{DnaTemplate, TemplateSettings, build_template},
+    thermodynamics::{SolutionConditions, dna_melting_temperature},
+};
+
+const MIN_HAIRPIN_LOOP: usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrimerSettings {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub conditions: SolutionConditions, // PCR buffer, at the annealing temperature
+    pub melting_margin: f32, // Primers should melt this many °C above the annealing temperature
+    pub melting_tolerance: f32, // Primers may melt this many °C either side of that target
+    pub max_melting_difference: f32, // Between the forward and reverse primers
+    pub concentration: f32,  // nM, of each primer
+    pub max_complementarity: usize, // Longest stretch allowed to pair within or between primers
+    pub max_three_prime_match: usize, // Longest 3' end stretch allowed to match elsewhere in the template
+}
+
+impl Default for PrimerSettings {
+    fn default() -> Self {
+        PrimerSettings {
+            min_length: 18,
+            max_length: 30,
+            conditions: SolutionConditions {
+                temperature: 55.0,
+                sodium: 50.0,
+                magnesium: 1.5,
+            },
+            melting_margin: 5.0,
+            melting_tolerance: 2.5,
+            max_melting_difference: 5.0,
+            concentration: 250.0,
+            max_complementarity: 4,
+            max_three_prime_match: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrimerIssue {
+    NoGcClamp, // The 3' end is not a G or C, or has more than 3 G or C in its last 5 bases
+    MeltingTemperature, // No length reaches the target melting temperature
+    SelfDimer(usize), // Longest stretch pairing with another copy of the primer
+    Hairpin(usize), // Longest stem the primer can fold into
+    NotUnique(usize), // Longest 3' end stretch matching elsewhere in the template
+    MeltingMismatch(f32), // Difference between the melting temperatures of the pair
+    PrimerDimer(usize), // Longest stretch pairing between the forward and reverse primers
+    ConstantRegion, // Within the promoter, ribozymes or terminator, so it amplifies any design with them
+}
+
+impl Display for PrimerIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrimerIssue::NoGcClamp => write!(f, "no GC clamp"),
+            PrimerIssue::MeltingTemperature => write!(f, "melting temperature off target"),
+            PrimerIssue::SelfDimer(length) => write!(f, "self-dimer of {length} bp"),
+            PrimerIssue::Hairpin(length) => write!(f, "hairpin stem of {length} bp"),
+            PrimerIssue::NotUnique(length) => {
+                write!(f, "3' end matches {length} nt elsewhere in the template")
+            }
+            PrimerIssue::MeltingMismatch(difference) => {
+                write!(f, "melting temperatures differ by {difference:.1} °C")
+            }
+            PrimerIssue::PrimerDimer(length) => write!(f, "primer dimer of {length} bp"),
+            PrimerIssue::ConstantRegion => write!(
+                f,
+                "lies entirely outside the designed RNA, shared by every design with the same template parts"
+            ),
+        }
+    }
+}
+
+// Whether the 3' end of a primer is unique in the template, and what the G·U wobbles did for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WobbleVerdict {
+    Unique,            // Pass, unique even without the wobbles
+    UniqueWithWobbles, // Pass, unique only because of the wobbles
+    NotUnique,         // Fail, matches elsewhere in the template despite the wobbles
+}
+
+impl WobbleVerdict {
+    fn new(three_prime_match: usize, without_wobbles: usize, max_three_prime_match: usize) -> Self {
+        if three_prime_match > max_three_prime_match {
+            WobbleVerdict::NotUnique
+        } else if without_wobbles > max_three_prime_match {
+            WobbleVerdict::UniqueWithWobbles
+        } else {
+            WobbleVerdict::Unique
+        }
+    }
+}
+
+impl Display for WobbleVerdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WobbleVerdict::Unique => write!(f, "pass, unique without wobbles"),
+            WobbleVerdict::UniqueWithWobbles => write!(f, "pass, unique thanks to the wobbles"),
+            WobbleVerdict::NotUnique => write!(f, "fail, not unique even with the wobbles"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Primer {
+    pub sequence: String, // 5' to 3'
+    pub melting_temperature: f32,
+    pub gc_content: f32,
+    pub self_dimer: usize,
+    pub hairpin: usize,
+    pub three_prime_match: usize, // Longest 3' end stretch matching elsewhere in the template
+    pub three_prime_match_without_wobbles: usize, // Same, had every G·U wobble been left as A·U
+    pub wobble_verdict: WobbleVerdict,
+    pub issues: Vec<PrimerIssue>,
+}
+
+impl Display for Primer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({} nt, Tm {:.1} °C, GC {:.0}%)",
+            self.sequence,
+            self.sequence.len(),
+            self.melting_temperature,
+            100.0 * self.gc_content
+        )?;
+        self.issues
+            .iter()
+            .try_for_each(|issue| write!(f, "\n  Warning: {issue}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PrimerPair {
+    pub forward: Primer, // Same sequence as the start of the template coding strand
+    pub reverse: Primer, // Same sequence as the start of the template strand
+    pub primer_dimer: usize,
+    pub issues: Vec<PrimerIssue>,
+}
+
+impl Display for PrimerPair {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Forward: {}", self.forward)?;
+        writeln!(f, "Reverse: {}", self.reverse)?;
+        self.issues
+            .iter()
+            .try_for_each(|issue| writeln!(f, "Warning: {issue}"))?;
+        writeln!(
+            f,
+            "Longest 3' end match elsewhere: forward {} nt ({} nt without wobbles), reverse {} nt ({} nt without wobbles)",
+            self.forward.three_prime_match,
+            self.forward.three_prime_match_without_wobbles,
+            self.reverse.three_prime_match,
+            self.reverse.three_prime_match_without_wobbles
+        )?;
+        write!(
+            f,
+            "Wobble check: forward {}, reverse {}",
+            self.forward.wobble_verdict, self.reverse.wobble_verdict
+        )
+    }
+}
+
+fn is_complementary(first: u8, second: u8) -> bool {
+    matches!(
+        (first, second),
+        (b'A', b'T') | (b'T', b'A') | (b'C', b'G') | (b'G', b'C')
+    )
+}
+
+// Longest stretch of first pairing antiparallel with second
+fn longest_complementarity(first: &[u8], second: &[u8]) -> usize {
+    (0..first.len())
+        .flat_map(|start| (0..second.len()).map(move |end| (start, end)))
+        .map(|(start, end)| {
+            (0..)
+                .take_while(|offset| {
+                    start + offset < first.len()
+                        && *offset <= end
+                        && is_complementary(
+                            *first.get(start + offset).unwrap(),
+                            *second.get(end - offset).unwrap(),
+                        )
+                })
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// Longest stem a sequence can fold into, closing a loop of at least MIN_HAIRPIN_LOOP bases
+fn longest_hairpin(sequence: &[u8]) -> usize {
+    (0..sequence.len())
+        .flat_map(|start| {
+            (start + MIN_HAIRPIN_LOOP + 1..sequence.len()).map(move |end| (start, end))
+        })
+        .map(|(start, end)| {
+            (0..)
+                .take_while(|offset| {
+                    start + offset + MIN_HAIRPIN_LOOP < end - offset
+                        && is_complementary(
+                            *sequence.get(start + offset).unwrap(),
+                            *sequence.get(end - offset).unwrap(),
+                        )
+                })
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// Last base G or C, and at most 3 G or C in the last 5 bases
+fn has_gc_clamp(sequence: &[u8]) -> bool {
+    let tail: &[u8] = sequence.get(sequence.len().saturating_sub(5)..).unwrap();
+    tail.last().is_some_and(|base| matches!(base, b'G' | b'C'))
+        && tail
+            .iter()
+            .filter(|base| matches!(base, b'G' | b'C'))
+            .count()
+            <= 3
+}
+
+// Longest stretch ending the primer found elsewhere on either strand of the template
+// The primer itself starts the given strand, where it is excluded
+fn longest_three_prime_match(primer: &[u8], own_strand: &[u8], other_strand: &[u8]) -> usize {
+    let matching = |strand: &[u8], end: usize| {
+        (0..=end.min(primer.len() - 1))
+            .take_while(|offset| primer.get(primer.len() - 1 - offset) == strand.get(end - offset))
+            .count()
+    };
+    (0..own_strand.len())
+        .filter(|end| *end != primer.len() - 1)
+        .map(|end| matching(own_strand, end))
+        .chain((0..other_strand.len()).map(|end| matching(other_strand, end)))
+        .max()
+        .unwrap_or(0)
+}
+
+// Primer of the preferred length at the start of a strand
+// Its 3' end is also matched against the same strands without wobbles, and primers not reaching past
+// the constant start of the strand are flagged
+fn design_primer(
+    (strand, other_strand): (&[u8], &[u8]),
+    (reference_strand, reference_other_strand): (&[u8], &[u8]),
+    constant: usize,
+    settings: &PrimerSettings,
+) -> Primer {
+    let target: f32 = settings.conditions.temperature + settings.melting_margin;
+    let mut primers: Vec<Primer> = (settings.min_length.max(1)..=settings.max_length)
+        .filter(|length| *length <= strand.len())
+        .map(|length| {
+            let sequence: &[u8] = strand.get(..length).unwrap();
+            let text: String = String::from_utf8_lossy(sequence).into_owned();
+            // Templates only hold the designed nucleotides and fixed DNA parts
+            let bases: RnaSequence = text.parse().unwrap();
+            let melting_temperature: f32 =
+                dna_melting_temperature(&bases, &settings.conditions, settings.concentration);
+            let self_dimer: usize = longest_complementarity(sequence, sequence);
+            let hairpin: usize = longest_hairpin(sequence);
+            let three_prime_match: usize =
+                longest_three_prime_match(sequence, strand, other_strand);
+            let three_prime_match_without_wobbles: usize = longest_three_prime_match(
+                reference_strand.get(..length).unwrap(),
+                reference_strand,
+                reference_other_strand,
+            );
+
+            let mut issues: Vec<PrimerIssue> = Vec::new();
+            if !has_gc_clamp(sequence) {
+                issues.push(PrimerIssue::NoGcClamp);
+            }
+            if (melting_temperature - target).abs() > settings.melting_tolerance {
+                issues.push(PrimerIssue::MeltingTemperature);
+            }
+            if self_dimer > settings.max_complementarity {
+                issues.push(PrimerIssue::SelfDimer(self_dimer));
+            }
+            if hairpin > settings.max_complementarity {
+                issues.push(PrimerIssue::Hairpin(hairpin));
+            }
+            if three_prime_match > settings.max_three_prime_match {
+                issues.push(PrimerIssue::NotUnique(three_prime_match));
+            }
+            if length <= constant {
+                issues.push(PrimerIssue::ConstantRegion);
+            }
+            Primer {
+                sequence: text,
+                melting_temperature,
+                gc_content: sequence
+                    .iter()
+                    .filter(|base| matches!(base, b'G' | b'C'))
+                    .count() as f32
+                    / length as f32,
+                self_dimer,
+                hairpin,
+                three_prime_match,
+                three_prime_match_without_wobbles,
+                wobble_verdict: WobbleVerdict::new(
+                    three_prime_match,
+                    three_prime_match_without_wobbles,
+                    settings.max_three_prime_match,
+                ),
+                issues,
+            }
+        })
+        .collect();
+
+    // Fewest issues first, then closest to the target melting temperature
+    primers.sort_by(|first, second| {
+        first.issues.len().cmp(&second.issues.len()).then(
+            (first.melting_temperature - target)
+                .abs()
+                .total_cmp(&(second.melting_temperature - target).abs()),
+        )
+    });
+    primers.into_iter().next().unwrap_or(Primer {
+        sequence: String::from_utf8_lossy(strand).into_owned(),
+        melting_temperature: f32::NAN,
+        gc_content: 0.0,
+        self_dimer: 0,
+        hairpin: 0,
+        three_prime_match: 0,
+        three_prime_match_without_wobbles: 0,
+        wobble_verdict: WobbleVerdict::Unique,
+        issues: vec![PrimerIssue::MeltingTemperature],
+    })
+}
+
+// Sequence with every G·U wobble pair turned back into the A·U pair it replaced
+pub fn without_wobbles(generated: &GeneratedSequence) -> RnaSequence {
+    let mut sequence: Vec<Nucleotide> = generated.sequence().to_vec();
+    generated
+        .wobbles
+        .iter()
+        .for_each(|wobble| *sequence.get_mut(*wobble).unwrap() = Nucleotide::A);
+    sequence.into()
+}
+
+// Design PCR primers amplifying the transcription template of a generated sequence
+// Their 3' ends are also matched against the template without wobbles, showing what the wobbles bought
+pub fn design_primers(
+    generated: &GeneratedSequence,
+    template_settings: &TemplateSettings,
+    settings: &PrimerSettings,
+) -> PrimerPair {
+    let template: DnaTemplate = build_template(&generated.sequence(), template_settings);
+    let template_strand: Vec<u8> = template.reverse_complement().into_bytes();
+    let coding: Vec<u8> = template.sequence.clone().into_bytes();
+    let reference: DnaTemplate = build_template(&without_wobbles(generated), template_settings);
+    let reference_template: Vec<u8> = reference.reverse_complement().into_bytes();
+    let reference_coding: Vec<u8> = reference.sequence.into_bytes();
+
+    let (design_start, design_end): (usize, usize) = template.designed();
+    let forward: Primer = design_primer(
+        (&coding, &template_strand),
+        (&reference_coding, &reference_template),
+        design_start,
+        settings,
+    );
+    let reverse: Primer = design_primer(
+        (&template_strand, &coding),
+        (&reference_template, &reference_coding),
+        coding.len() - design_end,
+        settings,
+    );
+
+    let primer_dimer: usize =
+        longest_complementarity(forward.sequence.as_bytes(), reverse.sequence.as_bytes());
+    let mut issues: Vec<PrimerIssue> = Vec::new();
+    let difference: f32 = (forward.melting_temperature - reverse.melting_temperature).abs();
+    if difference > settings.max_melting_difference {
+        issues.push(PrimerIssue::MeltingMismatch(difference));
+    }
+    if primer_dimer > settings.max_complementarity {
+        issues.push(PrimerIssue::PrimerDimer(primer_dimer));
+    }
+
+    PrimerPair {
+        forward,
+        reverse,
+        primer_dimer,
+        issues,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3A;
+
+    use super::*;
+    use crate::routines::{
+        graph::{Tree, construct_tree, find_rna_path},
+        sequencer::{SequencerSettings, generate_sequence},
+        template::reverse_complement_dna,
+    };
+
+    #[test]
+    fn wobble_verdicts_pass_or_fail() {
+        assert_eq!(WobbleVerdict::new(6, 6, 8), WobbleVerdict::Unique);
+        assert_eq!(
+            WobbleVerdict::new(6, 12, 8),
+            WobbleVerdict::UniqueWithWobbles
+        );
+        assert_eq!(WobbleVerdict::new(10, 12, 8), WobbleVerdict::NotUnique);
+        assert!(
+            WobbleVerdict::new(10, 12, 8)
+                .to_string()
+                .starts_with("fail")
+        );
+        assert!(WobbleVerdict::new(6, 12, 8).to_string().starts_with("pass"));
+    }
+
+    #[test]
+    fn primers_within_constant_parts_are_flagged() {
+        let coordinates: Vec<Vec3A> = vec![
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(20.0, 0.0, 0.0),
+            Vec3A::new(-10.0, 20.0, 0.0),
+            Vec3A::new(-10.0, -20.0, 0.0),
+        ];
+        let mut tree: Tree = construct_tree(&[(0, 1), (0, 2), (0, 3)]);
+        let path: Vec<usize> = find_rna_path(&mut tree, &coordinates);
+        let settings: SequencerSettings = SequencerSettings {
+            seed: Some(0),
+            ..Default::default()
+        };
+        let generated: GeneratedSequence =
+            generate_sequence(&path, &tree, &coordinates, &settings).unwrap();
+        let template_settings: TemplateSettings = TemplateSettings {
+            hdv: true,
+            ..Default::default()
+        };
+        let primers: PrimerPair =
+            design_primers(&generated, &template_settings, &PrimerSettings::default());
+
+        // The T7 promoter is shorter than the primers, which reach into the RNA
+        let template: DnaTemplate = build_template(&generated.sequence(), &template_settings);
+        assert!(primers.forward.sequence.len() > template.designed().0);
+        assert!(
+            !primers
+                .forward
+                .issues
+                .contains(&PrimerIssue::ConstantRegion)
+        );
+        // The HDV ribozyme is longer than any primer, which only amplifies the constant 3' end
+        assert!(
+            primers
+                .reverse
+                .issues
+                .contains(&PrimerIssue::ConstantRegion)
+        );
+    }
+
+    #[test]
+    fn melting_temperatures_are_checked_against_the_tolerance() {
+        let strand: &[u8] = b"GCTAGCTTAGGCATCGATCGGATCCATGCAAGTCGCAGTC";
+        let other: Vec<u8> = reverse_complement_dna(&String::from_utf8_lossy(strand)).into_bytes();
+        let design = |melting_tolerance: f32, max_melting_difference: f32| -> Primer {
+            let settings: PrimerSettings = PrimerSettings {
+                melting_tolerance,
+                max_melting_difference,
+                ..Default::default()
+            };
+            design_primer((strand, &other), (strand, &other), 0, &settings)
+        };
+
+        // Only the tolerance around the target matters, not the difference allowed between primers
+        let primer: Primer = design(100.0, 0.0);
+        assert!(!primer.issues.contains(&PrimerIssue::MeltingTemperature));
+        let primer: Primer = design(0.0, 100.0);
+        assert!(primer.issues.contains(&PrimerIssue::MeltingTemperature));
+    }
+}
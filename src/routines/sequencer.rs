@@ -1,24 +1,250 @@
-use std::borrow::Cow;
-
 use glam::Vec3A;
 use nanorand::{Rng, WyRand};
 use rustc_hash::FxHashMap;
 
-use super::graph::Tree;
+use super::{
+    analysis::{OffTargetMatch, find_off_targets, longest_off_target, rescan_off_targets},
+    constraints::{
+        ConstraintViolation, PinTarget, SequenceConstraints, SequencePin, check_constraints,
+    },
+    graph::{Tree, node_position},
+    motifs::{FunctionalModule, ModuleInsertion, ModulePlacement},
+    nucleotide::{IupacCode, Nucleotide, RnaSequence},
+    structure::TargetStructure,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct SequencerSettings {
+    pub constraints: SequenceConstraints,
+    pub pins: Vec<SequencePin>,        // User-locked sequences
+    pub modules: Vec<ModuleInsertion>, // Functional modules replacing hairpins or inserted in helices
+    pub wobble: WobblePolicy,
+    pub symmetry: SymmetryPolicy,
+    pub seed: Option<u64>, // Fixed seed for reproducible sequences, random if None
+}
+
+// Replacement of A·U pairs by G·U pairs in the antisense strands, for better DNA amplification
+#[derive(Debug, Clone, Copy)]
+pub struct WobblePolicy {
+    pub fraction: f32, // Probability of replacing an eligible A·U pair, 0 disables wobbles
+    pub max_per_helix: Option<usize>, // Maximum number of wobble pairs in a helix
+    pub max_consecutive: usize, // Longest run of adjacent wobble pairs
+    pub junction_exclusion: usize, // No wobbles within this many base pairs of a junction, kink or hairpin
+    pub kissing_loop_exclusion: usize, // No wobbles within this many base pairs of a kissing loop
+}
+
+impl Default for WobblePolicy {
+    fn default() -> Self {
+        WobblePolicy {
+            fraction: 0.2,
+            max_per_helix: None,
+            max_consecutive: 1,
+            junction_exclusion: 2,
+            kissing_loop_exclusion: 3,
+        }
+    }
+}
+
+// SEQUIN-style minimisation of the complementarity between helices, to avoid misfolding
+#[derive(Debug, Clone, Copy)]
+pub struct SymmetryPolicy {
+    pub max_match: usize,      // Longest allowed unintended complementary stretch
+    pub max_iterations: usize, // Rounds of mutations, 0 disables the minimisation
+}
+
+impl Default for SymmetryPolicy {
+    fn default() -> Self {
+        SymmetryPolicy {
+            max_match: 7,
+            max_iterations: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SequencerError {
+    UnknownPinTarget(PinTarget),
+    PinOutOfBounds {
+        target: PinTarget,
+        length: usize,
+    }, // Pin extending past the end of a target of this length
+    PinConflict {
+        target: PinTarget,
+        position: usize,
+    }, // Pin incompatible with another pin at this position
+    InvalidModulePlacement(ModulePlacement), // Not a hairpin, unknown edge, offset past the end or placed twice
+    PinOnModule(PinTarget),                  // Pin on a hairpin replaced by a module
+    PinOnKissingLoop {
+        target: PinTarget,
+        nodes: (usize, usize),
+    }, // Pin on an edge broken into these kissing loops
+}
+
+impl std::fmt::Display for SequencerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequencerError::UnknownPinTarget(target) => {
+                write!(f, "Pinned {target} does not exist")
+            }
+            SequencerError::PinOutOfBounds { target, length } => {
+                write!(f, "Pin extends past the end of {target} ({length} nt)")
+            }
+            SequencerError::PinConflict { target, position } => {
+                write!(f, "Conflicting pins on {target} at position {position}")
+            }
+            SequencerError::InvalidModulePlacement(placement) => {
+                write!(f, "Cannot place a module on {placement}")
+            }
+            SequencerError::PinOnModule(target) => {
+                write!(f, "Pinned {target} is replaced by a module")
+            }
+            SequencerError::PinOnKissingLoop {
+                target,
+                nodes: (first, second),
+            } => write!(
+                f,
+                "Pinned {target} closes a cycle and is replaced by a kissing loop, pin node {first} and node {second} instead"
+            ),
+        }
+    }
+}
 
-// TODO
-pub fn generate_sequence(rna_path: &[usize], tree: &Tree, node_coordinates: &[Vec3A]) -> String {
-    let mut sequence: String = String::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    Edge((usize, usize)), // Tree edge (parent, child), shared by the sense and antisense strands
+    Node(usize),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Edge((parent, child)) => write!(f, "edge {parent}-{child}"),
+            Source::Node(node) => write!(f, "node {node}"),
+        }
+    }
+}
 
-    let rng: &mut WyRand = &mut WyRand::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Sense,
+    Antisense,
+    Hairpin,
+    Kink,
+    Junction,
+    KissingLoop,
+    Module,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NucleotideRecord {
+    pub base: Nucleotide,
+    pub path_index: usize, // Index in the RNA path of the node (or edge origin) that produced it
+    pub source: Source,
+    pub role: Role,
+    pub partner: Option<usize>, // Index of the nucleotide it is designed to pair with
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedSequence {
+    pub nucleotides: Vec<NucleotideRecord>,
+    pub violations: Vec<ConstraintViolation>, // Constraints that could not be satisfied
+    pub wobbles: Vec<usize>,                  // Indices of the antisense G forming G·U wobble pairs
+    pub longest_off_target: Option<OffTargetMatch>, // Longest unintended Watson-Crick complementary stretch
+}
+
+impl GeneratedSequence {
+    pub fn len(&self) -> usize {
+        self.nucleotides.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nucleotides.is_empty()
+    }
+
+    pub fn sequence(&self) -> RnaSequence {
+        self.nucleotides
+            .iter()
+            .map(|nucleotide| nucleotide.base)
+            .collect()
+    }
+
+    // Last nucleotides of the sequence, enough for constraint checks without copying the whole transcript
+    fn tail(&self, length: usize) -> Vec<Nucleotide> {
+        self.nucleotides
+            .get(self.len().saturating_sub(length)..)
+            .unwrap()
+            .iter()
+            .map(|nucleotide| nucleotide.base)
+            .collect()
+    }
+
+    fn gc_count(&self) -> usize {
+        self.nucleotides
+            .iter()
+            .filter(|nucleotide| nucleotide.base.is_gc())
+            .count()
+    }
+
+    // Index of the designed partner of every nucleotide
+    pub fn partners(&self) -> Vec<Option<usize>> {
+        self.nucleotides
+            .iter()
+            .map(|nucleotide| nucleotide.partner)
+            .collect()
+    }
+
+    // Intended secondary structure, kissing loop pairs are written as []
+    pub fn dot_bracket(&self) -> String {
+        TargetStructure::from_generated(self).dot_bracket()
+    }
+
+    // Pair two nucleotides with each other
+    fn pair(&mut self, first: usize, second: usize) {
+        self.nucleotides.get_mut(first).unwrap().partner = Some(second);
+        self.nucleotides.get_mut(second).unwrap().partner = Some(first);
+    }
+}
+
+impl std::fmt::Display for GeneratedSequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.nucleotides
+            .iter()
+            .try_for_each(|nucleotide| write!(f, "{}", nucleotide.base))
+    }
+}
+
+pub fn generate_sequence(
+    rna_path: &[usize],
+    tree: &Tree,
+    node_coordinates: &[Vec3A],
+    settings: &SequencerSettings,
+) -> Result<GeneratedSequence, SequencerError> {
+    let mut sequence: GeneratedSequence = GeneratedSequence::default();
+
+    let rng: &mut WyRand = &mut match settings.seed {
+        Some(seed) => WyRand::new_seed(seed),
+        None => WyRand::new(),
+    };
     let node_types: FxHashMap<usize, NodeType> = get_node_types(tree);
+    let pins: ResolvedPins = resolve_pins(&settings.pins, tree, node_coordinates, &node_types)?;
+    let modules: ResolvedModules = resolve_modules(
+        &settings.modules,
+        &settings.pins,
+        tree,
+        node_coordinates,
+        &node_types,
+    )?;
+    // The antisense strand is the reverse complement of the sense strand
+    // Constraints are therefore enforced on both when generating the sense strand, across the
+    // motifs joining either strand, and wobbles are only made where they keep to the constraints
+    let edge_constraints: SequenceConstraints = settings.constraints.with_reverse_complements();
 
-    // TODO
     // For each node in path, associate sequence from node_types
     // Get distance to next node in path
     // Generate random sequence with given length
-    let mut visited_edges: FxHashMap<(usize, usize), String> = FxHashMap::default();
-    let mut visited_kls: FxHashMap<usize, String> = FxHashMap::default();
+    // Visited edges and kissing loops store where their sequence is, to pair them later
+    let mut visited_edges: FxHashMap<(usize, usize), Vec<usize>> = FxHashMap::default(); // edge, sense nucleotides
+    let mut visited_kls: FxHashMap<usize, usize> = FxHashMap::default(); // node_id, start
     rna_path
         .iter()
         .enumerate()
@@ -26,155 +252,1033 @@ pub fn generate_sequence(rna_path: &[usize], tree: &Tree, node_coordinates: &[Ve
             if let Some(&next_node_index) = rna_path.get(path_index + 1) {
                 // Generate node sequence and append to path
                 let node_type: NodeType = *node_types.get(&node_index).unwrap();
-                match node_type {
-                    NodeType::KissingLoop(other) => {
-                        if visited_kls.contains_key(&other) {
+                let node_start: usize = sequence.len();
+                if let Some(module) = modules.hairpins.get(&node_index) {
+                    push_module(&mut sequence, module, path_index, Source::Node(node_index));
+                } else {
+                    let node_sequence: RnaSequence = match node_type {
+                        NodeType::KissingLoop(other) => match visited_kls.get(&other) {
                             // Generate antisense sequence for complement
-                            let existing_seq: &str = visited_kls.get(&other).unwrap();
-                            sequence.extend(existing_seq.chars().rev().map(|c| match c {
-                                'A' => 'U',
-                                'G' => 'C',
-                                'C' => 'G',
-                                'U' => 'A',
-                                _ => 'O',
-                            }));
-                        } else {
-                            let node_sequence: String = generate_node_sequence(node_type, rng);
-                            visited_kls.insert(node_index, node_sequence.clone());
-                            sequence.push_str(&node_sequence);
-                        }
+                            Some(&other_start) => sequence.nucleotides
+                                [other_start..other_start + node_sequence_length(node_type)]
+                                .iter()
+                                .rev()
+                                .map(|nucleotide| nucleotide.base.complement())
+                                .collect(),
+                            None => {
+                                visited_kls.insert(node_index, node_start);
+                                generate_node_sequence(node_type, pins.nodes.get(&node_index), rng)
+                            }
+                        },
+                        _ => generate_node_sequence(node_type, pins.nodes.get(&node_index), rng),
+                    };
+                    sequence
+                        .nucleotides
+                        .extend(node_sequence.iter().map(|&base| NucleotideRecord {
+                            base,
+                            path_index,
+                            source: Source::Node(node_index),
+                            role: node_type.role(),
+                            partner: None,
+                        }));
+
+                    // Hairpins and kissing loops are closed by a two base pair stem
+                    // The loops of kissing loops pair with the loop of their complement
+                    if let NodeType::Hairpin | NodeType::KissingLoop(_) = node_type {
+                        sequence.pair(node_start, node_start + 8);
+                        sequence.pair(node_start + 1, node_start + 7);
+                    }
+                    if let NodeType::KissingLoop(other) = node_type
+                        && let Some(&other_start) = visited_kls.get(&other)
+                    {
+                        (2..7).for_each(|offset| {
+                            sequence.pair(node_start + offset, other_start + 8 - offset)
+                        });
                     }
-                    _ => sequence.push_str(&generate_node_sequence(node_type, rng)),
                 }
+
                 // Generate edge sequence
-                if let Some(sense_sequence) = visited_edges.get(&(next_node_index, node_index)) {
+                let strand_module: Option<&(usize, &FunctionalModule)> =
+                    modules.strands.get(&(node_index, next_node_index));
+                if let Some(sense_indices) = visited_edges.get(&(next_node_index, node_index)) {
                     // Generate antisense sequence
-                    // Replace 20% of AU bonds as GU bonds for better DNA amplification
-                    sequence.extend(sense_sequence.chars().rev().map(|c| match c {
-                        'A' => 'U',
-                        'G' => 'C',
-                        'C' => 'G',
-                        'U' => match rng.generate_range(0..10u8) {
-                            0 | 1 => 'G',
-                            _ => 'A',
-                        },
-                        _ => 'O',
-                    }));
+                    let sense: Vec<Nucleotide> = sense_indices
+                        .iter()
+                        .map(|&index| sequence.nucleotides.get(index).unwrap().base)
+                        .collect();
+                    let preceding: Vec<Nucleotide> = sequence.tail(settings.constraints.reach());
+                    let (following, _): (RnaSequence, RnaSequence) = motif_ends(
+                        next_node_index,
+                        &node_types,
+                        &pins,
+                        &modules,
+                        &sequence,
+                        &visited_kls,
+                    );
+                    let antisense: RnaSequence = generate_antisense_sequence(
+                        &sense,
+                        (&preceding, &following),
+                        &settings.constraints,
+                        pins.edges.get(&(next_node_index, node_index)),
+                        (
+                            wobble_exclusion(&node_types, &settings.wobble, next_node_index),
+                            wobble_exclusion(&node_types, &settings.wobble, node_index),
+                        ),
+                        &settings.wobble,
+                        rng,
+                    );
+                    let sense_indices: Vec<usize> = sense_indices.clone();
+                    let antisense_indices: Vec<usize> = push_strand(
+                        &mut sequence,
+                        &antisense,
+                        strand_module,
+                        path_index,
+                        (next_node_index, node_index),
+                        Role::Antisense,
+                    );
+                    antisense_indices
+                        .iter()
+                        .zip(sense_indices.iter().rev())
+                        .for_each(|(&antisense_index, &sense_index)| {
+                            sequence.pair(antisense_index, sense_index)
+                        });
                 } else {
-                    log::debug!("Node coordinates: {node_coordinates:?}");
-                    let seq: String = generate_edge_sequence(
-                        node_coordinates
-                            .get(node_index)
-                            .map(Cow::Borrowed)
-                            .unwrap_or_else(|| {
-                                let parent: usize = tree.nodes.get(&node_index).unwrap().0;
-                                let target: usize = tree
-                                    .nodes
-                                    .get(match tree.cycle_breakers.get(&node_index) {
-                                        // Find corresponding cycle-breaker node
-                                        // If current node is the key, other is the value
-                                        Some(cycle_breaker) => cycle_breaker,
-                                        // If the curret node is the value
-                                        // Find the pair with this node as the value and extract the key
-                                        None => {
-                                            tree.cycle_breakers
-                                                .iter()
-                                                .find(|(_key, val)| **val == node_index)
-                                                .unwrap()
-                                                .0
-                                        }
-                                    })
-                                    .unwrap()
-                                    .0;
-                                Cow::Owned(
-                                    0.5 * (node_coordinates.get(parent).unwrap()
-                                        + node_coordinates.get(target).unwrap()),
-                                )
-                            })
-                            .distance(
-                                *node_coordinates
-                                    .get(next_node_index)
-                                    .map(Cow::Borrowed)
-                                    .unwrap_or_else(|| {
-                                        let parent: usize = tree.nodes.get(&node_index).unwrap().0;
-                                        let target: usize = tree
-                                            .nodes
-                                            .get(match tree.cycle_breakers.get(&next_node_index) {
-                                                // Find corresponding cycle-breaker node
-                                                // If current node is the key, other is the value
-                                                Some(cycle_breaker) => cycle_breaker,
-                                                // If the curret node is the value
-                                                // Find the pair with this node as the value and extract the key
-                                                None => {
-                                                    tree.cycle_breakers
-                                                        .iter()
-                                                        .find(|(_key, val)| {
-                                                            **val == next_node_index
-                                                        })
-                                                        .unwrap()
-                                                        .0
-                                                }
-                                            })
-                                            .unwrap()
-                                            .0;
-                                        //log::debug!("Parent: {parent}, Child: {target}");
-                                        if parent == usize::MAX {
-                                            log::error!("This isn't supposed to happen!");
-                                            Cow::Owned(
-                                                0.5 * (node_coordinates.get(parent).unwrap()
-                                                    + node_coordinates.get(target).unwrap()),
-                                            )
-                                        } else {
-                                            Cow::Owned(
-                                                0.5 * (node_coordinates.get(parent).unwrap()
-                                                    + node_coordinates.get(target).unwrap()),
-                                            )
-                                        }
-                                    }),
-                            )
-                            .round() as usize,
+                    let length: usize =
+                        edge_length(tree, node_coordinates, (node_index, next_node_index));
+                    let (origin_start, _): (RnaSequence, RnaSequence) = motif_ends(
+                        node_index,
+                        &node_types,
+                        &pins,
+                        &modules,
+                        &sequence,
+                        &visited_kls,
+                    );
+                    let (destination_start, destination_end): (RnaSequence, RnaSequence) =
+                        motif_ends(
+                            next_node_index,
+                            &node_types,
+                            &pins,
+                            &modules,
+                            &sequence,
+                            &visited_kls,
+                        );
+                    let joins: StrandJoins = StrandJoins {
+                        module: strand_module
+                            .map(|&(offset, module)| (offset, &module.sequence[..])),
+                        following: destination_start,
+                        antisense_preceding: origin_start.reverse_complement(),
+                        antisense_following: destination_end.reverse_complement(),
+                    };
+                    let seq: RnaSequence = generate_edge_sequence(
+                        length,
+                        (
+                            &sequence.tail(edge_constraints.reach()),
+                            sequence.gc_count(),
+                            sequence.len(),
+                        ),
+                        &joins,
+                        &edge_constraints,
+                        pins.edges.get(&(node_index, next_node_index)),
                         rng,
                     );
-                    visited_edges.insert((node_index, next_node_index), seq.clone());
-                    sequence.push_str(&seq);
+                    let sense_indices: Vec<usize> = push_strand(
+                        &mut sequence,
+                        &seq,
+                        strand_module,
+                        path_index,
+                        (node_index, next_node_index),
+                        Role::Sense,
+                    );
+                    visited_edges.insert((node_index, next_node_index), sense_indices);
                 }
             }
         });
 
+    minimise_symmetry(&mut sequence, &pins, &node_types, settings, rng);
+
+    // Report anything the generation could not avoid
+    let bases: RnaSequence = sequence.sequence();
+    sequence.violations = check_constraints(&bases, &settings.constraints);
+    sequence.longest_off_target = longest_off_target(&bases, &sequence.partners(), false);
+    sequence.wobbles = (0..sequence.len())
+        .filter(|&index| {
+            let nucleotide: &NucleotideRecord = sequence.nucleotides.get(index).unwrap();
+            nucleotide.role == Role::Antisense
+                && nucleotide.base == Nucleotide::G
+                && nucleotide.partner.is_some_and(|partner| {
+                    sequence.nucleotides.get(partner).unwrap().base == Nucleotide::U
+                })
+        })
+        .collect();
+
+    Ok(sequence)
+}
+
+// Append a helix strand, with a functional module inserted after offset nucleotides if any
+// Returns the indices of the strand nucleotides, excluding the module
+fn push_strand(
+    sequence: &mut GeneratedSequence,
+    bases: &[Nucleotide],
+    module: Option<&(usize, &FunctionalModule)>,
+    path_index: usize,
+    edge: (usize, usize),
+    role: Role,
+) -> Vec<usize> {
+    let mut indices: Vec<usize> = Vec::with_capacity(bases.len());
+    bases.iter().enumerate().for_each(|(offset, &base)| {
+        if let Some(&(module_offset, module)) = module
+            && module_offset == offset
+        {
+            push_module(sequence, module, path_index, Source::Edge(edge));
+        }
+        indices.push(sequence.len());
+        sequence.nucleotides.push(NucleotideRecord {
+            base,
+            path_index,
+            source: Source::Edge(edge),
+            role,
+            partner: None,
+        });
+    });
+    if let Some(&(module_offset, module)) = module
+        && module_offset == bases.len()
+    {
+        push_module(sequence, module, path_index, Source::Edge(edge));
+    }
+    indices
+}
+
+// Append a functional module with its own pairs
+fn push_module(
+    sequence: &mut GeneratedSequence,
+    module: &FunctionalModule,
+    path_index: usize,
+    source: Source,
+) {
+    let start: usize = sequence.len();
     sequence
+        .nucleotides
+        .extend(module.sequence.iter().map(|&base| NucleotideRecord {
+            base,
+            path_index,
+            source,
+            role: Role::Module,
+            partner: None,
+        }));
+    module
+        .partners
+        .iter()
+        .enumerate()
+        .for_each(|(offset, partner)| {
+            if let Some(partner) = partner {
+                sequence.pair(start + offset, start + partner);
+            }
+        });
+}
+
+// Complement a sense strand, replacing some A·U pairs by G·U pairs for better DNA amplification
+// Pinned positions are kept as exact complements, and wobbles are only made within the constraints
+// between the preceding sequence and the start of the following motif
+fn generate_antisense_sequence(
+    sense: &[Nucleotide],
+    (preceding, following): (&[Nucleotide], &[Nucleotide]),
+    constraints: &SequenceConstraints,
+    sense_pins: Option<&Vec<IupacCode>>,
+    (origin_exclusion, destination_exclusion): (usize, usize),
+    policy: &WobblePolicy,
+    rng: &mut WyRand,
+) -> RnaSequence {
+    let length: usize = sense.len();
+    let complement: Vec<Nucleotide> = sense
+        .iter()
+        .rev()
+        .map(|base| base.complement())
+        .chain(following.iter().copied())
+        .collect();
+    let mut context: Vec<Nucleotide> = preceding.to_vec();
+    let mut helix_wobbles: usize = 0;
+    let mut consecutive_wobbles: usize = 0;
+    (0..length).rev().for_each(|offset| {
+        let pinned: bool =
+            sense_pins.is_some_and(|codes| *codes.get(offset).unwrap() != IupacCode::N);
+        let eligible: bool = !pinned
+            && offset >= origin_exclusion
+            && length - 1 - offset >= destination_exclusion
+            && consecutive_wobbles < policy.max_consecutive
+            && policy.max_per_helix.is_none_or(|max| helix_wobbles < max);
+        let nucleotide: Nucleotide = match *sense.get(offset).unwrap() {
+            Nucleotide::U
+                if eligible
+                    && rng.generate::<f32>() < policy.fraction
+                    && constraints.allows_before(
+                        &context,
+                        Nucleotide::G,
+                        &complement[length - offset..],
+                    ) =>
+            {
+                helix_wobbles += 1;
+                consecutive_wobbles += 1;
+                Nucleotide::G
+            }
+            base => {
+                consecutive_wobbles = 0;
+                base.complement()
+            }
+        };
+        context.push(nucleotide);
+    });
+
+    context.split_off(preceding.len()).into()
+}
+
+// Number of base pairs next to a node in which wobbles are not allowed
+fn wobble_exclusion(
+    node_types: &FxHashMap<usize, NodeType>,
+    policy: &WobblePolicy,
+    node: usize,
+) -> usize {
+    match node_types.get(&node).unwrap() {
+        NodeType::KissingLoop(_) => policy.kissing_loop_exclusion,
+        _ => policy.junction_exclusion,
+    }
 }
 
-fn generate_edge_sequence(length: usize, rng: &mut WyRand) -> String {
-    (0..length)
-        .map(|_| match rng.generate_range(0u8..5u8) {
-            0 => 'A',
-            1 => 'U',
-            2 => 'C',
-            _ => 'G',
+// Break up unintended complementary stretches longer than the allowed length, following SEQUIN
+// Each round mutates one helix nucleotide of every offending stretch (and its partner)
+// Partners are chosen with the wobble policy, and mutations are skipped if no base keeps to the
+// pins and constraints at both positions
+// Rounds that add constraint violations, lengthen the longest stretch or add stretches are reverted
+fn minimise_symmetry(
+    sequence: &mut GeneratedSequence,
+    pins: &ResolvedPins,
+    node_types: &FxHashMap<usize, NodeType>,
+    settings: &SequencerSettings,
+    rng: &mut WyRand,
+) {
+    let constraints: &SequenceConstraints = &settings.constraints;
+    let policy: &SymmetryPolicy = &settings.symmetry;
+    let wobble: &WobblePolicy = &settings.wobble;
+    let partners: Vec<Option<usize>> = sequence.partners();
+    // Score by constraint violations, longest offending stretch and number of stretches
+    let score = |bases: &[Nucleotide], matches: &[OffTargetMatch]| -> (usize, usize, usize) {
+        (
+            check_constraints(bases, constraints).len(),
+            matches
+                .iter()
+                .map(|off_target| off_target.length)
+                .max()
+                .unwrap_or(0),
+            matches.len(),
+        )
+    };
+
+    // Sense nucleotides of every helix, and offset of each within its helix, modules are not counted
+    let mut helices: FxHashMap<(usize, usize), Vec<usize>> = FxHashMap::default();
+    let sense_offsets: FxHashMap<usize, usize> = sequence
+        .nucleotides
+        .iter()
+        .enumerate()
+        .filter_map(
+            |(index, nucleotide)| match (nucleotide.source, nucleotide.role) {
+                (Source::Edge(edge), Role::Sense) => {
+                    let helix: &mut Vec<usize> = helices.entry(edge).or_default();
+                    helix.push(index);
+                    Some((index, helix.len() - 1))
+                }
+                _ => None,
+            },
+        )
+        .collect();
+    // Pin of a sense nucleotide, N if it is free
+    let pin = |index: usize, edge: (usize, usize)| -> IupacCode {
+        pins.edges.get(&edge).map_or(IupacCode::N, |codes| {
+            *codes.get(*sense_offsets.get(&index).unwrap()).unwrap()
         })
-        .collect()
+    };
+
+    // Only the stretches through mutated nucleotides are searched again after each round
+    let mut bases: Vec<Nucleotide> = sequence.sequence().to_vec();
+    let mut matches: Vec<OffTargetMatch> =
+        find_off_targets(&bases, &partners, policy.max_match + 1, false);
+    let mut current_score: (usize, usize, usize) = score(&bases, &matches);
+    for _ in 0..policy.max_iterations {
+        if matches.is_empty() {
+            break;
+        }
+
+        let mut mutated: Vec<Nucleotide> = bases.clone();
+        let mut changed: Vec<usize> = Vec::new();
+        matches.iter().for_each(|off_target| {
+            // Sense nucleotides of the stretch that are free to change
+            // Antisense nucleotides are changed through their sense partner
+            let mut candidates: Vec<usize> = off_target
+                .pairs()
+                .flat_map(|(first, second)| [first, second])
+                .filter_map(|index| {
+                    let nucleotide: &NucleotideRecord = sequence.nucleotides.get(index).unwrap();
+                    match (nucleotide.source, nucleotide.role) {
+                        (Source::Edge(_), Role::Sense) => Some(index),
+                        (Source::Edge(_), Role::Antisense) => nucleotide.partner,
+                        _ => None,
+                    }
+                })
+                .filter(|&index| {
+                    let Source::Edge(edge) = sequence.nucleotides.get(index).unwrap().source else {
+                        return false;
+                    };
+                    pin(index, edge).nucleotides().count() > 1
+                })
+                .collect();
+            if candidates.is_empty() {
+                return;
+            }
+            rng.shuffle(&mut candidates);
+
+            let index: usize = *candidates.first().unwrap();
+            let Source::Edge(edge) = sequence.nucleotides.get(index).unwrap().source else {
+                return;
+            };
+            let code: IupacCode = pin(index, edge);
+            let partner: Option<usize> = sequence.nucleotides.get(index).unwrap().partner;
+            let current: Nucleotide = *mutated.get(index).unwrap();
+            let mut alternatives: Vec<Nucleotide> = code
+                .nucleotides()
+                .filter(|&nucleotide| nucleotide != current)
+                .collect();
+            rng.shuffle(&mut alternatives);
+
+            // A U may be paired with a G, as when the antisense strand was generated
+            let wobble_allowed: bool = code == IupacCode::N
+                && partner.is_some()
+                && wobble_eligible(
+                    &mutated,
+                    helices.get(&edge).unwrap(),
+                    *sense_offsets.get(&index).unwrap(),
+                    (
+                        wobble_exclusion(node_types, wobble, edge.0),
+                        wobble_exclusion(node_types, wobble, edge.1),
+                    ),
+                    wobble,
+                    &sequence.nucleotides,
+                )
+                && rng.generate::<f32>() < wobble.fraction;
+
+            // First replacement, and partner, that keep to the constraints at both positions
+            let allowed = |bases: &[Nucleotide], position: usize| -> bool {
+                constraints.allows_before(
+                    &bases[..position],
+                    *bases.get(position).unwrap(),
+                    &bases[position + 1..],
+                )
+            };
+            let mut trial: Vec<Nucleotide> = mutated.clone();
+            let mutation: Option<(Nucleotide, Option<Nucleotide>)> =
+                alternatives.iter().find_map(|&replacement| {
+                    *trial.get_mut(index).unwrap() = replacement;
+                    let Some(partner) = partner else {
+                        return allowed(&trial, index).then_some((replacement, None));
+                    };
+                    let wobble_pair: Option<Nucleotide> =
+                        (wobble_allowed && replacement == Nucleotide::U).then_some(Nucleotide::G);
+                    wobble_pair
+                        .into_iter()
+                        .chain([replacement.complement()])
+                        .find(|&partner_base| {
+                            *trial.get_mut(partner).unwrap() = partner_base;
+                            allowed(&trial, index) && allowed(&trial, partner)
+                        })
+                        .map(|partner_base| (replacement, Some(partner_base)))
+                });
+            let Some((replacement, partner_base)) = mutation else {
+                return;
+            };
+
+            *mutated.get_mut(index).unwrap() = replacement;
+            changed.push(index);
+            if let (Some(partner), Some(partner_base)) = (partner, partner_base) {
+                *mutated.get_mut(partner).unwrap() = partner_base;
+                changed.push(partner);
+            }
+        });
+
+        let mut mutated_matches: Vec<OffTargetMatch> = matches.clone();
+        rescan_off_targets(
+            &mutated,
+            &partners,
+            policy.max_match + 1,
+            false,
+            &mut mutated_matches,
+            &changed,
+        );
+        let mutated_score: (usize, usize, usize) = score(&mutated, &mutated_matches);
+        if mutated_score <= current_score {
+            bases = mutated;
+            matches = mutated_matches;
+            current_score = mutated_score;
+        }
+    }
+
+    sequence
+        .nucleotides
+        .iter_mut()
+        .zip(bases)
+        .for_each(|(nucleotide, base)| nucleotide.base = base);
 }
 
-fn generate_node_sequence(node_type: NodeType, rng: &mut WyRand) -> String {
-    match node_type {
-        NodeType::Hairpin | NodeType::KissingLoop(_) => {
-            let mut seq: String = "GC".to_string();
-            let first_half: String = (0..3)
-                .map(|_| match rng.generate_range(0u8..4u8) {
-                    0 => 'A',
-                    1 => 'U',
-                    2 => 'C',
-                    _ => 'G',
+// Whether the pair at an offset of a helix may become a G·U wobble under the policy
+// The pair itself is not counted, so an existing wobble can be kept
+fn wobble_eligible(
+    bases: &[Nucleotide],
+    helix: &[usize],
+    offset: usize,
+    (origin_exclusion, destination_exclusion): (usize, usize),
+    policy: &WobblePolicy,
+    nucleotides: &[NucleotideRecord],
+) -> bool {
+    let is_wobble = |offset: usize| -> bool {
+        let index: usize = *helix.get(offset).unwrap();
+        *bases.get(index).unwrap() == Nucleotide::U
+            && nucleotides
+                .get(index)
+                .unwrap()
+                .partner
+                .is_some_and(|partner| *bases.get(partner).unwrap() == Nucleotide::G)
+    };
+    let before: usize = (0..offset)
+        .rev()
+        .take_while(|&other| is_wobble(other))
+        .count();
+    let after: usize = (offset + 1..helix.len())
+        .take_while(|&other| is_wobble(other))
+        .count();
+    let helix_wobbles: usize = (0..helix.len())
+        .filter(|&other| other != offset && is_wobble(other))
+        .count();
+
+    offset >= origin_exclusion
+        && helix.len() - 1 - offset >= destination_exclusion
+        && before + after < policy.max_consecutive
+        && policy.max_per_helix.is_none_or(|max| helix_wobbles < max)
+}
+
+// Modules replacing the loop of leaf nodes, and modules inserted in the strand travelling along an edge
+struct ResolvedModules<'a> {
+    hairpins: FxHashMap<usize, &'a FunctionalModule>,
+    strands: FxHashMap<(usize, usize), (usize, &'a FunctionalModule)>, // Strand (origin, destination), (offset, module)
+}
+
+fn resolve_modules<'a>(
+    modules: &'a [ModuleInsertion],
+    pins: &[SequencePin],
+    tree: &Tree,
+    node_coordinates: &[Vec3A],
+    node_types: &FxHashMap<usize, NodeType>,
+) -> Result<ResolvedModules<'a>, SequencerError> {
+    let mut resolved: ResolvedModules = ResolvedModules {
+        hairpins: FxHashMap::default(),
+        strands: FxHashMap::default(),
+    };
+
+    modules.iter().try_for_each(|insertion| {
+        let invalid: SequencerError = SequencerError::InvalidModulePlacement(insertion.placement);
+        match insertion.placement {
+            ModulePlacement::Hairpin(node) => {
+                if !matches!(node_types.get(&node), Some(NodeType::Hairpin))
+                    || resolved.hairpins.insert(node, &insertion.module).is_some()
+                {
+                    return Err(invalid);
+                }
+            }
+            ModulePlacement::Edge {
+                edge: (origin, destination),
+                offset,
+            } => {
+                let tree_edge: (usize, usize) = if tree.edges.contains(&(origin, destination)) {
+                    (origin, destination)
+                } else if tree.edges.contains(&(destination, origin)) {
+                    (destination, origin)
+                } else {
+                    return Err(invalid);
+                };
+                if offset > edge_length(tree, node_coordinates, tree_edge)
+                    || resolved
+                        .strands
+                        .insert((origin, destination), (offset, &insertion.module))
+                        .is_some()
+                {
+                    return Err(invalid);
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    // The motif of a replaced hairpin no longer exists
+    if let Some(pin) = pins.iter().find(|pin| match pin.target {
+        PinTarget::Node(node) => resolved.hairpins.contains_key(&node),
+        PinTarget::Edge(_) => false,
+    }) {
+        return Err(SequencerError::PinOnModule(pin.target));
+    }
+
+    Ok(resolved)
+}
+
+// Pins resolved onto the sense strand of each tree edge, and onto the motif of each node
+struct ResolvedPins {
+    edges: FxHashMap<(usize, usize), Vec<IupacCode>>,
+    nodes: FxHashMap<usize, Vec<IupacCode>>,
+}
+
+fn resolve_pins(
+    pins: &[SequencePin],
+    tree: &Tree,
+    node_coordinates: &[Vec3A],
+    node_types: &FxHashMap<usize, NodeType>,
+) -> Result<ResolvedPins, SequencerError> {
+    let mut resolved: ResolvedPins = ResolvedPins {
+        edges: FxHashMap::default(),
+        nodes: FxHashMap::default(),
+    };
+
+    // Restrict a position to the nucleotides allowed by both the current and new codes
+    fn restrict(
+        codes: &mut [IupacCode],
+        position: usize,
+        code: IupacCode,
+        target: PinTarget,
+        pin_position: usize,
+    ) -> Result<(), SequencerError> {
+        let current: &mut IupacCode = codes.get_mut(position).unwrap();
+        *current = current
+            .intersection(code)
+            .ok_or(SequencerError::PinConflict {
+                target,
+                position: pin_position,
+            })?;
+        Ok(())
+    }
+
+    pins.iter().try_for_each(|pin| {
+        let positions = (pin.offset..).zip(pin.pattern.iter().copied());
+        match pin.target {
+            PinTarget::Edge((origin, destination)) => {
+                // Antisense pins are stored as the complement on the sense strand
+                let (edge, antisense): ((usize, usize), bool) =
+                    if tree.edges.contains(&(origin, destination)) {
+                        ((origin, destination), false)
+                    } else if tree.edges.contains(&(destination, origin)) {
+                        ((destination, origin), true)
+                    } else if let Some(nodes) = kissing_loop_nodes(tree, (origin, destination)) {
+                        // Edges closing a cycle have no helix of their own
+                        return Err(SequencerError::PinOnKissingLoop {
+                            target: pin.target,
+                            nodes,
+                        });
+                    } else {
+                        return Err(SequencerError::UnknownPinTarget(pin.target));
+                    };
+                let length: usize = edge_length(tree, node_coordinates, edge);
+                if pin.offset + pin.pattern.len() > length {
+                    return Err(SequencerError::PinOutOfBounds {
+                        target: pin.target,
+                        length,
+                    });
+                }
+
+                let codes: &mut Vec<IupacCode> = resolved
+                    .edges
+                    .entry(edge)
+                    .or_insert_with(|| vec![IupacCode::N; length]);
+                positions.into_iter().try_for_each(|(position, code)| {
+                    if antisense {
+                        restrict(
+                            codes,
+                            length - 1 - position,
+                            code.complement(),
+                            pin.target,
+                            position,
+                        )
+                    } else {
+                        restrict(codes, position, code, pin.target, position)
+                    }
+                })
+            }
+            PinTarget::Node(node) => {
+                let node_type: NodeType = *node_types
+                    .get(&node)
+                    .ok_or(SequencerError::UnknownPinTarget(pin.target))?;
+                let length: usize = node_sequence_length(node_type);
+                if pin.offset + pin.pattern.len() > length {
+                    return Err(SequencerError::PinOutOfBounds {
+                        target: pin.target,
+                        length,
+                    });
+                }
+
+                positions.into_iter().try_for_each(|(position, code)| {
+                    // Stem nucleotides of hairpins and kissing loops pair across the stem
+                    let mut restrictions: Vec<(usize, usize, IupacCode)> =
+                        vec![(node, position, code)];
+                    if let NodeType::Hairpin | NodeType::KissingLoop(_) = node_type
+                        && !(2..7).contains(&position)
+                    {
+                        restrictions.push((node, length - 1 - position, code.complement()));
+                    }
+                    // Kissing loops are the reverse complement of their partner
+                    if let NodeType::KissingLoop(other) = node_type {
+                        let mirrored: Vec<(usize, usize, IupacCode)> = restrictions
+                            .iter()
+                            .map(|&(_, restricted, code)| {
+                                (other, length - 1 - restricted, code.complement())
+                            })
+                            .collect();
+                        restrictions.extend(mirrored);
+                    }
+                    restrictions
+                        .into_iter()
+                        .try_for_each(|(restricted_node, restricted, code)| {
+                            restrict(
+                                resolved
+                                    .nodes
+                                    .entry(restricted_node)
+                                    .or_insert_with(|| vec![IupacCode::N; length]),
+                                restricted,
+                                code,
+                                pin.target,
+                                position,
+                            )
+                        })
                 })
+            }
+        }
+    })?;
+
+    Ok(resolved)
+}
+
+// Kissing loop nodes replacing an edge that closed a cycle, the one next to the origin first
+fn kissing_loop_nodes(
+    tree: &Tree,
+    (origin, destination): (usize, usize),
+) -> Option<(usize, usize)> {
+    let parent = |node: &usize| tree.nodes.get(node).map(|(parent, _)| *parent);
+    tree.cycle_breakers
+        .iter()
+        .find_map(|(first, second)| match (parent(first), parent(second)) {
+            (Some(a), Some(b)) if (a, b) == (origin, destination) => Some((*first, *second)),
+            (Some(a), Some(b)) if (b, a) == (origin, destination) => Some((*second, *first)),
+            _ => None,
+        })
+}
+
+// Number of nucleotides in a helix strand, from the length of the edge
+fn edge_length(
+    tree: &Tree,
+    node_coordinates: &[Vec3A],
+    (origin, destination): (usize, usize),
+) -> usize {
+    node_position(tree, node_coordinates, origin)
+        .distance(node_position(tree, node_coordinates, destination))
+        .round() as usize
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthEstimate {
+    pub helices: usize,       // Sense and antisense strands of every edge
+    pub hairpins: usize,      // Hairpin loops closing leaf nodes
+    pub kissing_loops: usize, // Loops of cycle-breaker nodes
+    pub linkers: usize,       // Kinks between two consecutive helices
+    pub junctions: usize,     // Open junctions between three or more helices
+    pub modules: usize,       // Functional modules
+}
+
+impl LengthEstimate {
+    pub fn total(&self) -> usize {
+        self.helices
+            + self.hairpins
+            + self.kissing_loops
+            + self.linkers
+            + self.junctions
+            + self.modules
+    }
+
+    // Account for functional modules, each replacing a hairpin loop or inserted in a helix strand
+    pub fn with_modules(mut self, modules: &[ModuleInsertion]) -> LengthEstimate {
+        modules.iter().for_each(|insertion| {
+            if let ModulePlacement::Hairpin(_) = insertion.placement {
+                self.hairpins = self
+                    .hairpins
+                    .saturating_sub(node_sequence_length(NodeType::Hairpin));
+            }
+            self.modules += insertion.module.len();
+        });
+        self
+    }
+}
+
+// Length of the sequence generate_sequence would produce, without generating it
+// Each edge is travelled twice (sense and antisense)
+// A node motif is added every time the path passes through a node, i.e. once per connected edge
+pub fn estimate_sequence_length(tree: &Tree, node_coordinates: &[Vec3A]) -> LengthEstimate {
+    let mut estimate: LengthEstimate = LengthEstimate {
+        helices: tree
+            .edges
+            .iter()
+            .map(|&edge| 2 * edge_length(tree, node_coordinates, edge))
+            .sum(),
+        ..Default::default()
+    };
+
+    get_node_types(tree).iter().for_each(|(node, node_type)| {
+        let (parent, children): &(usize, Vec<usize>) = tree.nodes.get(node).unwrap();
+        let degree: usize = children.len() + usize::from(*parent != usize::MAX);
+        let length: usize = degree * node_sequence_length(*node_type);
+        match node_type {
+            NodeType::KissingLoop(_) => estimate.kissing_loops += length,
+            NodeType::Hairpin => estimate.hairpins += length,
+            NodeType::Kink => estimate.linkers += length,
+            NodeType::OpenJunction => estimate.junctions += length,
+        }
+    });
+
+    estimate
+}
+
+// Fixed nucleotides next to a helix strand, which the constraints must also hold across
+// The antisense strand is checked as its reverse complement, read along the sense strand
+struct StrandJoins<'a> {
+    module: Option<(usize, &'a [Nucleotide])>, // Module inserted after offset nucleotides of the sense strand
+    following: RnaSequence,                    // Start of the motif following the sense strand
+    antisense_preceding: RnaSequence, // Reverse complement of the motif start following the antisense strand
+    antisense_following: RnaSequence, // Reverse complement of the motif end preceding the antisense strand
+}
+
+// Generate a helix strand following the preceding sequence, within the constraints if possible
+// Nucleotides are drawn to steer the GC content towards the middle of the allowed range
+// Only the end of the preceding sequence is given, with the GC count and length of the whole of it
+fn generate_edge_sequence(
+    length: usize,
+    (preceding, mut gc_count, mut total): (&[Nucleotide], usize, usize),
+    joins: &StrandJoins,
+    constraints: &SequenceConstraints,
+    pins: Option<&Vec<IupacCode>>,
+    rng: &mut WyRand,
+) -> RnaSequence {
+    let target_gc: f32 = 0.5 * (constraints.gc_range.0 + constraints.gc_range.1);
+    let mut context: Vec<Nucleotide> = preceding.to_vec();
+    let mut antisense: Vec<Nucleotide> = joins.antisense_preceding.to_vec();
+    let mut strand: Vec<Nucleotide> = Vec::with_capacity(length);
+
+    (0..length).for_each(|offset| {
+        if let Some((module_offset, module)) = joins.module
+            && module_offset == offset
+        {
+            context.extend_from_slice(module);
+            gc_count += module.iter().filter(|n| n.is_gc()).count();
+            total += module.len();
+        }
+
+        // Nucleotides already known to follow this one, in the sense and antisense strands
+        let mut following: Vec<Nucleotide> = Vec::new();
+        if let Some((module_offset, module)) = joins.module
+            && module_offset == offset + 1
+        {
+            following.extend_from_slice(module);
+        }
+        if offset + 1 == length {
+            following.extend_from_slice(&joins.following);
+        }
+        let antisense_following: &[Nucleotide] = if offset + 1 == length {
+            &joins.antisense_following
+        } else {
+            &[]
+        };
+
+        let code: IupacCode = pins.map_or(IupacCode::N, |codes| *codes.get(offset).unwrap());
+        let current_gc: f32 = if total == 0 {
+            target_gc
+        } else {
+            gc_count as f32 / total as f32
+        };
+        let gc_probability: f32 = (2.0 * target_gc - current_gc).clamp(0.1, 0.9);
+
+        // Candidates in order of preference, the first allowed one is used
+        // Joins with the following nucleotides are given up before the preceding ones
+        // If none are allowed, the violation is kept and reported after generation
+        // Pins always take precedence over the constraints
+        let mut candidates: [Nucleotide; 4] = if rng.generate::<f32>() < gc_probability {
+            [Nucleotide::G, Nucleotide::C, Nucleotide::A, Nucleotide::U]
+        } else {
+            [Nucleotide::A, Nucleotide::U, Nucleotide::G, Nucleotide::C]
+        };
+        if rng.generate_range(0u8..2u8) == 0 {
+            candidates.swap(0, 1);
+        }
+        if rng.generate_range(0u8..2u8) == 0 {
+            candidates.swap(2, 3);
+        }
+        let nucleotide: Nucleotide = candidates
+            .into_iter()
+            .filter(|&candidate| code.contains(candidate))
+            .find(|&candidate| {
+                constraints.allows_before(&context, candidate, &following)
+                    && constraints.allows_before(&antisense, candidate, antisense_following)
+            })
+            .or_else(|| {
+                candidates
+                    .into_iter()
+                    .filter(|&candidate| code.contains(candidate))
+                    .find(|&candidate| constraints.allows(&context, candidate))
+            })
+            .unwrap_or_else(|| {
+                candidates
+                    .into_iter()
+                    .find(|&candidate| code.contains(candidate))
+                    .unwrap()
+            });
+
+        gc_count += usize::from(nucleotide.is_gc());
+        total += 1;
+        context.push(nucleotide);
+        antisense.push(nucleotide);
+        strand.push(nucleotide);
+    });
+
+    strand.into()
+}
+
+// Fixed motifs
+const GC_CLAMP: [Nucleotide; 2] = [Nucleotide::G, Nucleotide::C];
+const KINK_SEQUENCE: [Nucleotide; 4] = [Nucleotide::A; 4];
+const OPEN_JUNCTION_SEQUENCE: [Nucleotide; 7] = [
+    Nucleotide::C,
+    Nucleotide::G,
+    Nucleotide::U,
+    Nucleotide::U,
+    Nucleotide::U,
+    Nucleotide::C,
+    Nucleotide::G,
+];
+
+fn generate_node_sequence(
+    node_type: NodeType,
+    pins: Option<&Vec<IupacCode>>,
+    rng: &mut WyRand,
+) -> RnaSequence {
+    let motif: RnaSequence = match node_type {
+        NodeType::Hairpin | NodeType::KissingLoop(_) => {
+            let mut seq: RnaSequence = RnaSequence::from(GC_CLAMP.to_vec());
+            let first_half: RnaSequence = (0..3)
+                .map(|_| Nucleotide::ALL[rng.generate_range(0usize..4usize)])
                 .collect();
-            let second_half = first_half.chars().rev().skip(1);
-            seq.push_str(&first_half);
-            seq.extend(second_half);
-            seq.push_str("GC");
+            let second_half = first_half.iter().rev().skip(1);
+            seq.extend(first_half.iter().copied());
+            seq.extend(second_half.copied());
+            seq.extend(GC_CLAMP);
             seq
         }
-        NodeType::Kink => "AAAA".to_string(),
-        NodeType::OpenJunction => "CGUUUCG".to_string(),
+        NodeType::Kink => RnaSequence::from(KINK_SEQUENCE.to_vec()),
+        NodeType::OpenJunction => RnaSequence::from(OPEN_JUNCTION_SEQUENCE.to_vec()),
+    };
+
+    // Replace any nucleotide not matching its pin by one that does
+    let mut motif: Vec<Nucleotide> = match pins {
+        Some(codes) => motif
+            .iter()
+            .zip(codes.iter())
+            .map(|(&nucleotide, &code)| {
+                if code.contains(nucleotide) {
+                    nucleotide
+                } else {
+                    let allowed: Vec<Nucleotide> = code.nucleotides().collect();
+                    *allowed.get(rng.generate_range(0..allowed.len())).unwrap()
+                }
+            })
+            .collect(),
+        None => motif.to_vec(),
+    };
+
+    // Keep the stem paired, pins on either side were restricted to complements of each other
+    if let NodeType::Hairpin | NodeType::KissingLoop(_) = node_type {
+        let length: usize = motif.len();
+        (0..2).for_each(|position| {
+            let complement: Nucleotide = motif.get(position).unwrap().complement();
+            *motif.get_mut(length - 1 - position).unwrap() = complement;
+        });
+    }
+    motif.into()
+}
+
+// Nucleotides a node motif is known to start and end with before it is generated
+// Loops are random, so only the stems of hairpins and kissing loops are known, unless pinned
+fn motif_ends(
+    node: usize,
+    node_types: &FxHashMap<usize, NodeType>,
+    pins: &ResolvedPins,
+    modules: &ResolvedModules,
+    sequence: &GeneratedSequence,
+    visited_kls: &FxHashMap<usize, usize>,
+) -> (RnaSequence, RnaSequence) {
+    if let Some(module) = modules.hairpins.get(&node) {
+        return (module.sequence.clone(), module.sequence.clone());
+    }
+
+    let node_type: NodeType = *node_types.get(&node).unwrap();
+    let length: usize = node_sequence_length(node_type);
+    let motif: Vec<Option<Nucleotide>> = match node_type {
+        // The second loop of a kissing loop is the reverse complement of the first
+        NodeType::KissingLoop(other) if visited_kls.contains_key(&other) => {
+            let other_start: usize = *visited_kls.get(&other).unwrap();
+            sequence.nucleotides[other_start..other_start + length]
+                .iter()
+                .rev()
+                .map(|nucleotide| Some(nucleotide.base.complement()))
+                .collect()
+        }
+        _ => {
+            let template: Vec<Option<Nucleotide>> = match node_type {
+                NodeType::Hairpin | NodeType::KissingLoop(_) => GC_CLAMP
+                    .iter()
+                    .map(|&base| Some(base))
+                    .chain([None; 5])
+                    .chain(GC_CLAMP.iter().map(|&base| Some(base)))
+                    .collect(),
+                NodeType::Kink => KINK_SEQUENCE.iter().map(|&base| Some(base)).collect(),
+                NodeType::OpenJunction => OPEN_JUNCTION_SEQUENCE
+                    .iter()
+                    .map(|&base| Some(base))
+                    .collect(),
+            };
+            // Pinned nucleotides replace the template if it does not match them
+            match pins.nodes.get(&node) {
+                Some(codes) => template
+                    .into_iter()
+                    .zip(codes.iter())
+                    .map(|(base, code)| match base {
+                        Some(base) if code.contains(base) => Some(base),
+                        _ => {
+                            let mut allowed = code.nucleotides();
+                            allowed.next().filter(|_| allowed.next().is_none())
+                        }
+                    })
+                    .collect(),
+                None => template,
+            }
+        }
+    };
+
+    let start: RnaSequence = motif.iter().map_while(|base| *base).collect();
+    let mut end: Vec<Nucleotide> = motif.iter().rev().map_while(|base| *base).collect();
+    end.reverse();
+    (start, end.into())
+}
+
+fn node_sequence_length(node_type: NodeType) -> usize {
+    match node_type {
+        NodeType::Hairpin | NodeType::KissingLoop(_) => 9,
+        NodeType::Kink => 4,
+        NodeType::OpenJunction => 7,
     }
 }
 
@@ -211,3 +1315,285 @@ enum NodeType {
     Kink,
     OpenJunction,
 }
+
+impl NodeType {
+    fn role(&self) -> Role {
+        match self {
+            NodeType::KissingLoop(_) => Role::KissingLoop,
+            NodeType::Hairpin => Role::Hairpin,
+            NodeType::Kink => Role::Kink,
+            NodeType::OpenJunction => Role::Junction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routines::{
+        graph::{construct_tree, find_rna_path},
+        motifs::module_library,
+    };
+
+    // Junction of three helices, one of which continues past a kink
+    fn branches() -> (Tree, Vec<usize>, Vec<Vec3A>) {
+        let coordinates: Vec<Vec3A> = vec![
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(20.0, 0.0, 0.0),
+            Vec3A::new(-10.0, 20.0, 0.0),
+            Vec3A::new(-10.0, -20.0, 0.0),
+            Vec3A::new(20.0, 0.0, 20.0),
+        ];
+        let mut tree: Tree = construct_tree(&[(0, 1), (0, 2), (0, 3), (1, 4)]);
+        let path: Vec<usize> = find_rna_path(&mut tree, &coordinates);
+        (tree, path, coordinates)
+    }
+
+    #[test]
+    fn joins_keep_to_the_constraints() {
+        let (tree, path, coordinates): (Tree, Vec<usize>, Vec<Vec3A>) = branches();
+        (0..64).for_each(|seed| {
+            let settings: SequencerSettings = SequencerSettings {
+                constraints: SequenceConstraints {
+                    gc_range: (0.0, 1.0),
+                    window: None,
+                    ..Default::default()
+                },
+                wobble: WobblePolicy {
+                    fraction: 1.0,
+                    max_consecutive: 4,
+                    junction_exclusion: 0,
+                    ..Default::default()
+                },
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let sequence: GeneratedSequence =
+                generate_sequence(&path, &tree, &coordinates, &settings).unwrap();
+            // Random loops can break the constraints on their own, but no join may
+            let joins: Vec<&ConstraintViolation> = sequence
+                .violations
+                .iter()
+                .filter(|violation| {
+                    let (start, length): (usize, usize) = match violation {
+                        ConstraintViolation::Homopolymer { start, length, .. } => (*start, *length),
+                        ConstraintViolation::Forbidden { start, motif } => (*start, motif.len()),
+                        _ => return true,
+                    };
+                    let nucleotides: &[NucleotideRecord] =
+                        &sequence.nucleotides[start..start + length];
+                    !nucleotides.iter().all(|nucleotide| {
+                        matches!(nucleotide.source, Source::Node(_))
+                            && nucleotide.path_index == nucleotides[0].path_index
+                    })
+                })
+                .collect();
+            assert!(
+                joins.is_empty(),
+                "seed {seed}: {joins:?} in {}",
+                sequence.sequence()
+            );
+        });
+    }
+
+    #[test]
+    fn length_estimates_match_generated_sequences() {
+        let check = |edges: &[(usize, usize)], coordinates: &[Vec3A], modules: &[&str]| {
+            let estimate: LengthEstimate =
+                estimate_sequence_length(&construct_tree(edges), coordinates);
+            let mut tree: Tree = construct_tree(edges);
+            let path: Vec<usize> = find_rna_path(&mut tree, coordinates);
+            let settings: SequencerSettings = SequencerSettings {
+                modules: modules
+                    .iter()
+                    .map(|module| ModuleInsertion::parse(module, &module_library()).unwrap())
+                    .collect(),
+                seed: Some(0),
+                ..Default::default()
+            };
+            let generated: GeneratedSequence =
+                generate_sequence(&path, &tree, coordinates, &settings).unwrap();
+            assert_eq!(
+                estimate.with_modules(&settings.modules).total(),
+                generated.nucleotides.len(),
+                "{edges:?} {modules:?}"
+            );
+        };
+
+        // Kink and three-way junction
+        let (_, _, coordinates): (Tree, Vec<usize>, Vec<Vec3A>) = branches();
+        let edges: [(usize, usize); 4] = [(0, 1), (0, 2), (0, 3), (1, 4)];
+        check(&edges, &coordinates, &[]);
+        // Modules replacing a hairpin and inserted in either strand of a helix
+        check(
+            &edges,
+            &coordinates,
+            &["hairpin 2 BoxB", "edge 0 3 4 MS2", "edge 3 0 2 PP7"],
+        );
+
+        // Four-way junction
+        let star: Vec<Vec3A> = vec![
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(20.0, 0.0, 0.0),
+            Vec3A::new(0.0, 20.0, 0.0),
+            Vec3A::new(-20.0, 0.0, 0.0),
+            Vec3A::new(0.0, -20.0, 5.0),
+        ];
+        check(&[(0, 1), (0, 2), (0, 3), (0, 4)], &star, &[]);
+
+        // Cycles broken by kissing loops
+        let pyramid: Vec<Vec3A> = vec![
+            Vec3A::new(-10.0, 10.0, 0.0),
+            Vec3A::new(10.0, 10.0, 0.0),
+            Vec3A::new(10.0, -10.0, 0.0),
+            Vec3A::new(-10.0, -10.0, 0.0),
+            Vec3A::new(0.0, 0.0, 20.0),
+        ];
+        let cycles: [(usize, usize); 8] = [
+            (4, 0),
+            (4, 1),
+            (4, 2),
+            (4, 3),
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 0),
+        ];
+        check(&cycles, &pyramid, &[]);
+        check(&cycles, &pyramid, &["edge 4 0 3 Broccoli"]);
+    }
+
+    #[test]
+    fn symmetry_mutations_keep_wobbles_and_pins() {
+        let (tree, path, coordinates): (Tree, Vec<usize>, Vec<Vec3A>) = branches();
+        let mut mutated_wobbles: usize = 0;
+        (0..32).for_each(|seed| {
+            let settings = |max_iterations: usize| -> SequencerSettings {
+                SequencerSettings {
+                    pins: vec!["edge 0 1 0 RYRYRY".parse().unwrap()],
+                    wobble: WobblePolicy {
+                        fraction: 1.0,
+                        max_consecutive: 4,
+                        junction_exclusion: 0,
+                        ..Default::default()
+                    },
+                    // Short stretches are allowed, so that many nucleotides are mutated
+                    symmetry: SymmetryPolicy {
+                        max_match: 3,
+                        max_iterations,
+                    },
+                    seed: Some(seed),
+                    ..Default::default()
+                }
+            };
+            let original: GeneratedSequence =
+                generate_sequence(&path, &tree, &coordinates, &settings(0)).unwrap();
+            let sequence: GeneratedSequence =
+                generate_sequence(&path, &tree, &coordinates, &settings(100)).unwrap();
+            assert!(sequence.violations.len() <= original.violations.len());
+
+            let pinned: Vec<Nucleotide> = sequence
+                .nucleotides
+                .iter()
+                .filter(|nucleotide| {
+                    nucleotide.source == Source::Edge((0, 1)) && nucleotide.role == Role::Sense
+                })
+                .map(|nucleotide| nucleotide.base)
+                .take(6)
+                .collect();
+            assert_eq!(pinned.len(), 6);
+            "RYRYRY".chars().zip(pinned).for_each(|(code, base)| {
+                assert!(IupacCode::try_from(code).unwrap().contains(base));
+            });
+
+            sequence
+                .nucleotides
+                .iter()
+                .zip(original.nucleotides.iter())
+                .filter(|(nucleotide, _)| nucleotide.role == Role::Sense)
+                .for_each(|(nucleotide, before)| {
+                    let partner: Nucleotide = sequence
+                        .nucleotides
+                        .get(nucleotide.partner.unwrap())
+                        .unwrap()
+                        .base;
+                    let wobble: bool = nucleotide.base == Nucleotide::U && partner == Nucleotide::G;
+                    assert!(nucleotide.base.is_watson_crick(partner) || wobble);
+                    if wobble && nucleotide.base != before.base {
+                        mutated_wobbles += 1;
+                    }
+                });
+        });
+        // Mutated pairs get wobbles as the antisense strands did
+        assert!(mutated_wobbles > 0);
+    }
+
+    fn pinned(pins: &[&str]) -> SequencerSettings {
+        SequencerSettings {
+            pins: pins.iter().map(|pin| pin.parse().unwrap()).collect(),
+            seed: Some(0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stem_pins_pair_across_the_stem() {
+        let (tree, path, coordinates): (Tree, Vec<usize>, Vec<Vec3A>) = branches();
+        let sequence: GeneratedSequence =
+            generate_sequence(&path, &tree, &coordinates, &pinned(&["node 2 0 UW"])).unwrap();
+        let motif: Vec<Nucleotide> = sequence
+            .nucleotides
+            .iter()
+            .filter(|nucleotide| nucleotide.source == Source::Node(2))
+            .map(|nucleotide| nucleotide.base)
+            .collect();
+        assert_eq!(motif.len(), 9);
+        assert_eq!(motif[0], Nucleotide::U);
+        assert_eq!(motif[8], Nucleotide::A);
+        assert_eq!(motif[7], motif[1].complement());
+
+        assert_eq!(
+            generate_sequence(
+                &path,
+                &tree,
+                &coordinates,
+                &pinned(&["node 2 0 U", "node 2 8 U"])
+            )
+            .unwrap_err(),
+            SequencerError::PinConflict {
+                target: PinTarget::Node(2),
+                position: 8
+            }
+        );
+    }
+
+    #[test]
+    fn pins_on_edges_closing_cycles_name_the_kissing_loops() {
+        let coordinates: Vec<Vec3A> = vec![
+            Vec3A::new(-10.0, 10.0, 0.0),
+            Vec3A::new(10.0, 10.0, 0.0),
+            Vec3A::new(10.0, -10.0, 0.0),
+            Vec3A::new(-10.0, -10.0, 0.0),
+            Vec3A::new(0.0, 0.0, 20.0),
+        ];
+        let mut tree: Tree = construct_tree(&[
+            (4, 0),
+            (4, 1),
+            (4, 2),
+            (4, 3),
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 0),
+        ]);
+        let path: Vec<usize> = find_rna_path(&mut tree, &coordinates);
+        let error: SequencerError =
+            generate_sequence(&path, &tree, &coordinates, &pinned(&["edge 1 0 0 G"])).unwrap_err();
+        let SequencerError::PinOnKissingLoop { nodes, .. } = error else {
+            panic!("{error}");
+        };
+        assert_eq!(tree.nodes.get(&nodes.0).unwrap().0, 1);
+        assert_eq!(tree.nodes.get(&nodes.1).unwrap().0, 0);
+        assert_eq!(tree.cycle_breakers.get(&nodes.1), Some(&nodes.0));
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt::{Display, Formatter};
+
+use super::{
+    analysis::BRACKETS,
+    nucleotide::RnaSequence,
+    sequencer::{GeneratedSequence, Role, Source},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructurePair {
+    pub first: usize,  // 5' nucleotide
+    pub second: usize, // 3' nucleotide
+    pub level: usize,  // Pseudoknot level, pairs of a level never cross each other
+    pub kissing_loop: bool,
+}
+
+impl StructurePair {
+    fn crosses(&self, other: &StructurePair) -> bool {
+        crossing((self.first, self.second), (other.first, other.second))
+    }
+}
+
+fn crossing((first, second): (usize, usize), (other_first, other_second): (usize, usize)) -> bool {
+    (first < other_first && other_first < second && second < other_second)
+        || (other_first < first && first < other_second && other_second < second)
+}
+
+// Target secondary structure with its pseudoknots, such as kissing loops, stored explicitly
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetStructure {
+    pub sequence: RnaSequence,
+    pub pairs: Vec<StructurePair>, // Ordered by 5' nucleotide
+}
+
+impl TargetStructure {
+    // Pairs are given as (first, second, kissing loop), and each placed on the lowest level it does not cross
+    // Pairs other than kissing loops are placed first, so they form the pseudoknot-free level 0 when nested
+    fn from_pairs(sequence: RnaSequence, mut pairs: Vec<(usize, usize, bool)>) -> Self {
+        pairs.sort_by_key(|&(first, _, kissing_loop)| (kissing_loop, first));
+        let mut placed: Vec<StructurePair> = Vec::with_capacity(pairs.len());
+        pairs.into_iter().for_each(|(first, second, kissing_loop)| {
+            let mut pair: StructurePair = StructurePair {
+                first,
+                second,
+                level: 0,
+                kissing_loop,
+            };
+            while placed
+                .iter()
+                .any(|other| other.level == pair.level && pair.crosses(other))
+            {
+                pair.level += 1;
+            }
+            placed.push(pair);
+        });
+        placed.sort_by_key(|pair| pair.first);
+
+        TargetStructure {
+            sequence,
+            pairs: placed,
+        }
+    }
+
+    pub fn new(sequence: RnaSequence, partners: &[Option<usize>]) -> Self {
+        let pairs: Vec<(usize, usize, bool)> = partners
+            .iter()
+            .enumerate()
+            .filter_map(|(first, partner)| {
+                partner
+                    .filter(|second| *second > first)
+                    .map(|second| (first, second, false))
+            })
+            .collect();
+        TargetStructure::from_pairs(sequence, pairs)
+    }
+
+    // Structure read from a file, which only gives the pairs
+    // Pseudoknots in designs are kissing loops, so the pairs left out of the nested level are taken as kissing loops
+    // The nested level keeps the pairs crossing the fewest others first, as helices cross fewer pairs than kissing loops
+    pub fn with_kissing_loops(sequence: RnaSequence, partners: &[Option<usize>]) -> Self {
+        let pairs: Vec<(usize, usize)> = partners
+            .iter()
+            .enumerate()
+            .filter_map(|(first, partner)| {
+                partner
+                    .filter(|second| *second > first)
+                    .map(|second| (first, second))
+            })
+            .collect();
+        let mut order: Vec<(usize, (usize, usize))> = pairs
+            .iter()
+            .map(|&pair| {
+                let crossings: usize = pairs.iter().filter(|&&other| crossing(pair, other)).count();
+                (crossings, pair)
+            })
+            .collect();
+        order.sort();
+        let mut nested: Vec<(usize, usize)> = Vec::new();
+        order.into_iter().for_each(|(_, pair)| {
+            if !nested.iter().any(|&other| crossing(pair, other)) {
+                nested.push(pair);
+            }
+        });
+
+        let pairs: Vec<(usize, usize, bool)> = pairs
+            .into_iter()
+            .map(|(first, second)| (first, second, !nested.contains(&(first, second))))
+            .collect();
+        TargetStructure::from_pairs(sequence, pairs)
+    }
+
+    // Designed structure, kissing loop pairs being those joining the loops of two different nodes
+    pub fn from_generated(generated: &GeneratedSequence) -> Self {
+        let pairs: Vec<(usize, usize, bool)> = generated
+            .nucleotides
+            .iter()
+            .enumerate()
+            .filter_map(|(first, nucleotide)| {
+                let second: usize = nucleotide.partner.filter(|second| *second > first)?;
+                let kissing_loop: bool = nucleotide.role == Role::KissingLoop
+                    && matches!(
+                        (nucleotide.source, generated.nucleotides.get(second).unwrap().source),
+                        (Source::Node(node), Source::Node(other)) if node != other
+                    );
+                Some((first, second, kissing_loop))
+            })
+            .collect();
+        TargetStructure::from_pairs(generated.sequence(), pairs)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sequence.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequence.is_empty()
+    }
+
+    pub fn max_level(&self) -> Option<usize> {
+        self.pairs.iter().map(|pair| pair.level).max()
+    }
+
+    // Partner of every nucleotide, keeping the pairs up to the given level
+    pub fn partners_up_to(&self, max_level: usize) -> Vec<Option<usize>> {
+        let mut partners: Vec<Option<usize>> = vec![None; self.len()];
+        self.pairs
+            .iter()
+            .filter(|pair| pair.level <= max_level)
+            .for_each(|pair| {
+                *partners.get_mut(pair.first).unwrap() = Some(pair.second);
+                *partners.get_mut(pair.second).unwrap() = Some(pair.first);
+            });
+        partners
+    }
+
+    pub fn partners(&self) -> Vec<Option<usize>> {
+        self.partners_up_to(usize::MAX)
+    }
+
+    // Pseudoknot-free part of the structure, as folded by secondary structure tools
+    pub fn nested_partners(&self) -> Vec<Option<usize>> {
+        self.partners_up_to(0)
+    }
+
+    // Extended dot-bracket notation, successive levels using (), [], {} and <>
+    // Pairs on further levels cannot be written and are left as dots
+    pub fn dot_bracket(&self) -> String {
+        let mut characters: Vec<char> = vec!['.'; self.len()];
+        self.pairs.iter().for_each(|pair| {
+            if let Some((opening, closing)) = BRACKETS.get(pair.level) {
+                *characters.get_mut(pair.first).unwrap() = *opening;
+                *characters.get_mut(pair.second).unwrap() = *closing;
+            }
+        });
+        characters.into_iter().collect()
+    }
+
+    // Connectivity table: length and title, then one 1-based line per nucleotide
+    // index, base, previous, next, partner (0 if unpaired), natural numbering
+    pub fn ct(&self, title: &str) -> String {
+        let partners: Vec<Option<usize>> = self.partners();
+        let mut ct: String = format!("{:>5} {title}\n", self.len());
+        self.sequence
+            .iter()
+            .zip(&partners)
+            .enumerate()
+            .for_each(|(index, (base, partner))| {
+                ct.push_str(&format!(
+                    "{:>5} {base} {:>5} {:>5} {:>5} {:>5}\n",
+                    index + 1,
+                    index,
+                    if index + 1 < self.len() { index + 2 } else { 0 },
+                    partner.map_or(0, |partner| partner + 1),
+                    index + 1
+                ));
+            });
+        ct
+    }
+
+    // BPSEQ: one 1-based line per nucleotide, index, base and partner (0 if unpaired)
+    pub fn bpseq(&self) -> String {
+        self.sequence
+            .iter()
+            .zip(self.partners())
+            .enumerate()
+            .map(|(index, (base, partner))| {
+                format!(
+                    "{} {base} {}\n",
+                    index + 1,
+                    partner.map_or(0, |partner| partner + 1)
+                )
+            })
+            .collect()
+    }
+
+    // Pairs of a predicted structure of the same length, such as from an external folding tool, against this target
+    pub fn compare(&self, predicted: &TargetStructure) -> Option<StructureComparison> {
+        if predicted.len() != self.len() {
+            return None;
+        }
+        let predicted_partners: Vec<Option<usize>> = predicted.partners();
+        let target_partners: Vec<Option<usize>> = self.partners();
+        let (correct, missing): (Vec<StructurePair>, Vec<StructurePair>) =
+            self.pairs.iter().copied().partition(|pair| {
+                *predicted_partners.get(pair.first).unwrap() == Some(pair.second)
+            });
+        let extra: Vec<(usize, usize)> = predicted
+            .pairs
+            .iter()
+            .filter(|pair| *target_partners.get(pair.first).unwrap() != Some(pair.second))
+            .map(|pair| (pair.first, pair.second))
+            .collect();
+
+        Some(StructureComparison {
+            correct: correct.len(),
+            missing,
+            extra,
+            sequence_mismatches: self
+                .sequence
+                .iter()
+                .zip(predicted.sequence.iter())
+                .filter(|(first, second)| first != second)
+                .count(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructureComparison {
+    pub correct: usize,              // Target pairs found in the prediction
+    pub missing: Vec<StructurePair>, // Target pairs absent from the prediction
+    pub extra: Vec<(usize, usize)>,  // Predicted pairs absent from the target
+    pub sequence_mismatches: usize,  // Positions where the predicted sequence differs
+}
+
+impl StructureComparison {
+    // Fraction of the target pairs predicted, 1 if the target has none
+    pub fn sensitivity(&self) -> f32 {
+        match self.correct + self.missing.len() {
+            0 => 1.0,
+            total => self.correct as f32 / total as f32,
+        }
+    }
+
+    // Fraction of the predicted pairs in the target, 1 if none are predicted
+    pub fn ppv(&self) -> f32 {
+        match self.correct + self.extra.len() {
+            0 => 1.0,
+            total => self.correct as f32 / total as f32,
+        }
+    }
+
+    pub fn f1(&self) -> f32 {
+        let (sensitivity, ppv): (f32, f32) = (self.sensitivity(), self.ppv());
+        if sensitivity + ppv > 0.0 {
+            2.0 * sensitivity * ppv / (sensitivity + ppv)
+        } else {
+            0.0
+        }
+    }
+
+    // Base pairs present in one structure but not the other
+    pub fn distance(&self) -> usize {
+        self.missing.len() + self.extra.len()
+    }
+}
+
+impl Display for StructureComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} target pairs predicted (sensitivity {:.1}%, PPV {:.1}%, F1 {:.3}), base pair distance {}",
+            self.correct,
+            self.correct + self.missing.len(),
+            100.0 * self.sensitivity(),
+            100.0 * self.ppv(),
+            self.f1(),
+            self.distance()
+        )?;
+        let missing_kissing_loops: usize =
+            self.missing.iter().filter(|pair| pair.kissing_loop).count();
+        if missing_kissing_loops > 0 {
+            write!(
+                f,
+                "\n{missing_kissing_loops} missing pairs are kissing loops, which pseudoknot-free tools cannot predict"
+            )?;
+        }
+        if self.sequence_mismatches > 0 {
+            write!(
+                f,
+                "\nWarning: the predicted sequence differs at {} positions",
+                self.sequence_mismatches
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routines::analysis::parse_dot_bracket;
+
+    fn structure(dot_bracket: &str) -> (RnaSequence, Vec<Option<usize>>) {
+        let partners: Vec<Option<usize>> = parse_dot_bracket(dot_bracket).unwrap();
+        let sequence: RnaSequence = "A".repeat(partners.len()).parse().unwrap();
+        (sequence, partners)
+    }
+
+    #[test]
+    fn crossing_pairs_are_placed_on_higher_levels() {
+        let (sequence, partners): (RnaSequence, Vec<Option<usize>>) =
+            structure("((..[[..))..{{..]]..}}");
+        let target: TargetStructure = TargetStructure::new(sequence, &partners);
+        assert_eq!(
+            target
+                .pairs
+                .iter()
+                .map(|pair| pair.level)
+                .collect::<Vec<usize>>(),
+            vec![0, 0, 1, 1, 0, 0]
+        );
+        assert_eq!(target.max_level(), Some(1));
+        assert_eq!(target.dot_bracket(), "((..[[..))..((..]]..))");
+        assert_eq!(target.partners(), partners);
+        assert_eq!(
+            target.nested_partners(),
+            parse_dot_bracket("((......))..((......))").unwrap()
+        );
+    }
+
+    #[test]
+    fn kissing_loops_stay_off_the_nested_level() {
+        // Read in 5' order, the short stem would take the nested level
+        let (sequence, partners): (RnaSequence, Vec<Option<usize>>) =
+            structure("[[...((((..]]...))))");
+        let read: TargetStructure = TargetStructure::new(sequence.clone(), &partners);
+        assert_eq!(read.dot_bracket(), "((...[[[[..))...]]]]");
+        assert!(read.pairs.iter().all(|pair| !pair.kissing_loop));
+
+        // Crossing more pairs, it is the kissing loop
+        let read: TargetStructure = TargetStructure::with_kissing_loops(sequence, &partners);
+        assert_eq!(read.dot_bracket(), "[[...((((..]]...))))");
+        assert!(
+            read.pairs
+                .iter()
+                .all(|pair| pair.kissing_loop == (pair.level == 1))
+        );
+        assert_eq!(
+            read.pairs.iter().filter(|pair| pair.kissing_loop).count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use super::nucleotide::Nucleotide;
+
+// Promoter sequences, transcription starts right after them
+const T7_PROMOTER: &str = "TAATACGACTCACTATA";
+const SP6_PROMOTER: &str = "ATTTAGGTGACACTATA";
+// Hammerhead core following the arm that pairs with the start of the RNA, cleaves right before the RNA
+const HAMMERHEAD_CORE: &str = "CTGATGAGTCCGTGAGGACGAAACGAGTAAGCTCGTC";
+const HAMMERHEAD_ARM_LENGTH: usize = 6;
+// Hepatitis delta virus ribozyme, cleaves right after the RNA
+const HDV_RIBOZYME: &str =
+    "GGCCGGCATGGTCCCAGCCTCCTCGCTGGCGCCGGCTGGGCAACATTCCGAGGGGACCGTCCCCTCGGTAATGGCGAATGGGAC";
+const T7_TERMINATOR: &str = "CTAGCATAACCCCTTGGGGCCTCTAAACGGGTCTTGAGGGGTTTTTTG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Promoter {
+    T7,
+    Sp6,
+}
+
+impl Promoter {
+    fn sequence(self) -> &'static str {
+        match self {
+            Promoter::T7 => T7_PROMOTER,
+            Promoter::Sp6 => SP6_PROMOTER,
+        }
+    }
+
+    // Guanines the transcript must start with to be transcribed efficiently
+    fn required_start(self) -> usize {
+        match self {
+            Promoter::T7 => 2,
+            Promoter::Sp6 => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Promoter::T7 => "T7 promoter",
+            Promoter::Sp6 => "SP6 promoter",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TemplateSettings {
+    pub promoter: Option<Promoter>,
+    pub hammerhead: bool, // 5' hammerhead ribozyme, for a homogeneous 5' end whatever the RNA starts with
+    pub hdv: bool,        // 3' HDV ribozyme, for a homogeneous 3' end
+    pub terminator: bool, // T7 terminator after the transcript
+}
+
+impl Default for TemplateSettings {
+    fn default() -> Self {
+        TemplateSettings {
+            promoter: Some(Promoter::T7),
+            hammerhead: false,
+            hdv: false,
+            terminator: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureKind {
+    Promoter,
+    StartGuanines, // Guanines added so the transcript starts as the promoter requires
+    Ribozyme,
+    Rna,
+    Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateFeature {
+    pub kind: FeatureKind,
+    pub name: String,
+    pub start: usize, // 0-based, inclusive
+    pub end: usize,   // 0-based, exclusive
+}
+
+// Coding strand of a transcription template, 5' to 3'
+#[derive(Debug, Clone, Default)]
+pub struct DnaTemplate {
+    pub sequence: String,
+    pub features: Vec<TemplateFeature>,
+}
+
+impl DnaTemplate {
+    fn push(&mut self, kind: FeatureKind, name: &str, sequence: &str) {
+        if sequence.is_empty() {
+            return;
+        }
+        self.features.push(TemplateFeature {
+            kind,
+            name: name.to_string(),
+            start: self.sequence.len(),
+            end: self.sequence.len() + sequence.len(),
+        });
+        self.sequence.push_str(sequence);
+    }
+
+    // Template strand, as ordered for the other half of the duplex
+    pub fn reverse_complement(&self) -> String {
+        reverse_complement_dna(&self.sequence)
+    }
+
+    // Location of the designed RNA within the template
+    pub fn rna(&self) -> Option<&TemplateFeature> {
+        self.features
+            .iter()
+            .find(|feature| feature.kind == FeatureKind::Rna)
+    }
+
+    // Range of the template that differs between designs, the RNA and the hammerhead arm pairing with it
+    pub fn designed(&self) -> (usize, usize) {
+        let Some(rna) = self.rna() else {
+            return (0, self.sequence.len());
+        };
+        let start: usize = self
+            .features
+            .iter()
+            .find(|feature| feature.kind == FeatureKind::Ribozyme && feature.end == rna.start)
+            .map_or(rna.start, |hammerhead| hammerhead.start);
+        (start, rna.end)
+    }
+}
+
+pub fn rna_to_dna(sequence: &[Nucleotide]) -> String {
+    sequence
+        .iter()
+        .map(|nucleotide| match nucleotide {
+            Nucleotide::U => 'T',
+            nucleotide => nucleotide.to_char(),
+        })
+        .collect()
+}
+
+pub fn reverse_complement_dna(sequence: &str) -> String {
+    sequence
+        .chars()
+        .rev()
+        .map(|base| match base.to_ascii_uppercase() {
+            'A' => 'T',
+            'T' | 'U' => 'A',
+            'C' => 'G',
+            'G' => 'C',
+            other => other,
+        })
+        .collect()
+}
+
+// Build the transcription template of an RNA
+// promoter | start guanines | 5' hammerhead | RNA | 3' HDV | terminator
+pub fn build_template(rna: &[Nucleotide], settings: &TemplateSettings) -> DnaTemplate {
+    let rna_dna: String = rna_to_dna(rna);
+    // The hammerhead arm is complementary to the start of the RNA, forming its first helix
+    let hammerhead: String = if settings.hammerhead {
+        let arm: String =
+            reverse_complement_dna(&rna_dna[..HAMMERHEAD_ARM_LENGTH.min(rna_dna.len())]);
+        format!("{arm}{HAMMERHEAD_CORE}")
+    } else {
+        String::new()
+    };
+
+    let mut template: DnaTemplate = DnaTemplate::default();
+    if let Some(promoter) = settings.promoter {
+        template.push(FeatureKind::Promoter, promoter.name(), promoter.sequence());
+        // Transcripts only start efficiently with enough guanines, add the missing ones
+        let transcript_start: &str = if hammerhead.is_empty() {
+            &rna_dna
+        } else {
+            &hammerhead
+        };
+        let present: usize = transcript_start
+            .chars()
+            .take(promoter.required_start())
+            .take_while(|base| *base == 'G')
+            .count();
+        template.push(
+            FeatureKind::StartGuanines,
+            "Transcription start",
+            &"G".repeat(promoter.required_start() - present),
+        );
+    }
+    template.push(FeatureKind::Ribozyme, "Hammerhead ribozyme", &hammerhead);
+    template.push(FeatureKind::Rna, "Designed RNA", &rna_dna);
+    if settings.hdv {
+        template.push(FeatureKind::Ribozyme, "HDV ribozyme", HDV_RIBOZYME);
+    }
+    if settings.terminator {
+        template.push(FeatureKind::Terminator, "T7 terminator", T7_TERMINATOR);
+    }
+
+    template
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routines::nucleotide::RnaSequence;
+
+    fn rna(sequence: &str) -> RnaSequence {
+        sequence.parse().unwrap()
+    }
+
+    fn feature(template: &DnaTemplate, name: &str) -> (usize, usize) {
+        let feature: &TemplateFeature = template
+            .features
+            .iter()
+            .find(|feature| feature.name == name)
+            .unwrap();
+        (feature.start, feature.end)
+    }
+
+    #[test]
+    fn promoters_get_the_guanines_they_need() {
+        // T7 needs two guanines, one of which the RNA already has
+        let template: DnaTemplate = build_template(&rna("GAUCCA"), &TemplateSettings::default());
+        assert_eq!(template.sequence, format!("{T7_PROMOTER}GGATCCA"));
+        assert_eq!(feature(&template, "T7 promoter"), (0, 17));
+        assert_eq!(feature(&template, "Transcription start"), (17, 18));
+        assert_eq!(
+            template.rna().map(|rna| (rna.start, rna.end)),
+            Some((18, 24))
+        );
+
+        // SP6 needs a single guanine, which the RNA has
+        let sp6: TemplateSettings = TemplateSettings {
+            promoter: Some(Promoter::Sp6),
+            ..Default::default()
+        };
+        let template: DnaTemplate = build_template(&rna("GAUCCA"), &sp6);
+        assert_eq!(template.sequence, format!("{SP6_PROMOTER}GATCCA"));
+        assert!(
+            template
+                .features
+                .iter()
+                .all(|feature| feature.kind != FeatureKind::StartGuanines)
+        );
+        let template: DnaTemplate = build_template(&rna("AUCCA"), &sp6);
+        assert_eq!(template.sequence, format!("{SP6_PROMOTER}GATCCA"));
+        assert_eq!(feature(&template, "Transcription start"), (17, 18));
+
+        let template: DnaTemplate = build_template(
+            &rna("AUCCA"),
+            &TemplateSettings {
+                promoter: None,
+                ..Default::default()
+            },
+        );
+        assert_eq!(template.sequence, "ATCCA");
+        assert_eq!(template.designed(), (0, 5));
+    }
+
+    #[test]
+    fn ribozymes_and_terminator_surround_the_rna() {
+        let settings: TemplateSettings = TemplateSettings {
+            promoter: Some(Promoter::T7),
+            hammerhead: true,
+            hdv: true,
+            terminator: true,
+        };
+        let template: DnaTemplate = build_template(&rna("ACGUUCAGGA"), &settings);
+
+        // The arm pairs with the first six nucleotides, and the hammerhead starts the transcript
+        let arm: String = reverse_complement_dna("ACGTTC");
+        assert_eq!(arm, "GAACGT");
+        let hammerhead: (usize, usize) = feature(&template, "Hammerhead ribozyme");
+        assert_eq!(
+            &template.sequence[hammerhead.0..hammerhead.1],
+            format!("{arm}{HAMMERHEAD_CORE}")
+        );
+        assert_eq!(feature(&template, "Transcription start"), (17, 18));
+        assert_eq!(hammerhead.0, 18);
+
+        let rna_range: (usize, usize) = feature(&template, "Designed RNA");
+        assert_eq!(rna_range.0, hammerhead.1);
+        assert_eq!(&template.sequence[rna_range.0..rna_range.1], "ACGTTCAGGA");
+        assert_eq!(template.designed(), (hammerhead.0, rna_range.1));
+
+        let hdv: (usize, usize) = feature(&template, "HDV ribozyme");
+        assert_eq!(hdv, (rna_range.1, rna_range.1 + HDV_RIBOZYME.len()));
+        let terminator: (usize, usize) = feature(&template, "T7 terminator");
+        assert_eq!(terminator, (hdv.1, template.sequence.len()));
+        assert!(template.sequence.ends_with(T7_TERMINATOR));
+
+        let kinds: Vec<FeatureKind> = template
+            .features
+            .iter()
+            .map(|feature| feature.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                FeatureKind::Promoter,
+                FeatureKind::StartGuanines,
+                FeatureKind::Ribozyme,
+                FeatureKind::Rna,
+                FeatureKind::Ribozyme,
+                FeatureKind::Terminator,
+            ]
+        );
+        assert_eq!(
+            reverse_complement_dna(&template.reverse_complement()),
+            template.sequence
+        );
+    }
+}
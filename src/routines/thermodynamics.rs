@@ -0,0 +1,389 @@
+use std::fmt::{Display, Formatter};
+
+use rustc_hash::FxHashMap;
+
+use super::{
+    nucleotide::Nucleotide,
+    sequencer::{GeneratedSequence, Role, Source},
+};
+
+const KELVIN: f32 = 273.15;
+
+type BasePair = (Nucleotide, Nucleotide);
+
+// Turner 1999 Watson-Crick nearest-neighbour parameters (Xia et al., 1998), at 1 M NaCl
+// Stacks are written 5'-XY-3' / 3'-X'Y'-5', with enthalpy in kcal/mol and entropy in cal/(mol·K)
+// The other six stacks are the same as one of these read from the other strand
+const STACKS: [((Nucleotide, Nucleotide), (f32, f32)); 10] = [
+    ((Nucleotide::A, Nucleotide::A), (-6.82, -19.0)),
+    ((Nucleotide::A, Nucleotide::U), (-9.38, -26.7)),
+    ((Nucleotide::U, Nucleotide::A), (-7.69, -20.5)),
+    ((Nucleotide::C, Nucleotide::U), (-10.48, -27.1)),
+    ((Nucleotide::C, Nucleotide::A), (-10.44, -26.9)),
+    ((Nucleotide::G, Nucleotide::U), (-11.40, -29.5)),
+    ((Nucleotide::G, Nucleotide::A), (-12.44, -32.5)),
+    ((Nucleotide::C, Nucleotide::G), (-10.64, -26.7)),
+    ((Nucleotide::G, Nucleotide::G), (-13.39, -32.7)),
+    ((Nucleotide::G, Nucleotide::C), (-14.88, -36.9)),
+];
+const TERMINAL_AU: (f32, f32) = (3.72, 10.5); // Per helix end closed by an A·U or G·U pair
+
+// Unified DNA nearest-neighbour parameters (SantaLucia, 1998), at 1 M NaCl, written as the RNA stacks
+// with U standing for T
+const DNA_STACKS: [((Nucleotide, Nucleotide), (f32, f32)); 10] = [
+    ((Nucleotide::A, Nucleotide::A), (-7.9, -22.2)),
+    ((Nucleotide::A, Nucleotide::U), (-7.2, -20.4)),
+    ((Nucleotide::U, Nucleotide::A), (-7.2, -21.3)),
+    ((Nucleotide::C, Nucleotide::A), (-8.5, -22.7)),
+    ((Nucleotide::G, Nucleotide::U), (-8.4, -22.4)),
+    ((Nucleotide::C, Nucleotide::U), (-7.8, -21.0)),
+    ((Nucleotide::G, Nucleotide::A), (-8.2, -22.2)),
+    ((Nucleotide::C, Nucleotide::G), (-10.6, -27.2)),
+    ((Nucleotide::G, Nucleotide::C), (-9.8, -24.4)),
+    ((Nucleotide::G, Nucleotide::G), (-8.0, -19.9)),
+];
+const DNA_INITIATION_GC: (f32, f32) = (0.1, -2.8); // Per duplex end closed by a G·C pair
+const DNA_INITIATION_AT: (f32, f32) = (2.3, 4.1); // Per duplex end closed by an A·T pair
+const GAS_CONSTANT: f32 = 1.987; // cal/(mol·K)
+
+#[derive(Debug, Clone, Copy)]
+pub struct SolutionConditions {
+    pub temperature: f32, // °C
+    pub sodium: f32,      // mM
+    pub magnesium: f32,   // mM
+}
+
+impl Default for SolutionConditions {
+    fn default() -> Self {
+        SolutionConditions {
+            temperature: 37.0,
+            sodium: 100.0,
+            magnesium: 2.0,
+        }
+    }
+}
+
+impl SolutionConditions {
+    // Monovalent concentration with the same stabilising effect, in M (von Ahsen et al., 2001)
+    pub fn sodium_equivalent(&self) -> f32 {
+        ((self.sodium.max(0.0) + 120.0 * self.magnesium.max(0.0).sqrt()) / 1000.0).clamp(1e-3, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StabilitySettings {
+    pub conditions: SolutionConditions,
+    pub min_melting_margin: f32, // Helices melting less than this many °C above the temperature are weak
+    pub max_melting_excess: f32, // Helices melting this many °C above the mean of their neighbours are strong
+}
+
+impl Default for StabilitySettings {
+    fn default() -> Self {
+        StabilitySettings {
+            conditions: SolutionConditions::default(),
+            min_melting_margin: 15.0,
+            max_melting_excess: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HelixStability {
+    pub edge: (usize, usize),
+    pub length: usize,            // Base pairs
+    pub enthalpy: f32,            // kcal/mol
+    pub entropy: f32,             // cal/(mol·K), salt corrected
+    pub free_energy: f32,         // kcal/mol, at the temperature of the conditions
+    pub melting_temperature: f32, // °C
+    pub weak: bool,               // Too unstable to form reliably
+    pub strong: bool,             // Much more stable than the helices it meets
+}
+
+impl Display for HelixStability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "edge {}-{}: {} bp, ΔG {:.1} kcal/mol, Tm {:.0} °C",
+            self.edge.0, self.edge.1, self.length, self.free_energy, self.melting_temperature
+        )?;
+        if self.weak {
+            write!(f, " (weak)")?;
+        }
+        if self.strong {
+            write!(f, " (strong)")?;
+        }
+        Ok(())
+    }
+}
+
+// Enthalpy and entropy of a stack, G·U pairs are approximated as the A·U pair in the same orientation
+fn stack_parameters(top: (Nucleotide, Nucleotide)) -> (f32, f32) {
+    let key: (Nucleotide, Nucleotide) = (top.1.complement(), top.0.complement());
+    STACKS
+        .iter()
+        .find(|(stack, _)| *stack == top || *stack == key)
+        .map(|(_, parameters)| *parameters)
+        .unwrap()
+}
+
+// Nearest-neighbour enthalpy and entropy (at 1 M NaCl) of a helix, given as its base pairs 5' to 3' along one strand
+// Helices are intramolecular, so there is no bimolecular initiation term
+pub fn helix_parameters(pairs: &[BasePair]) -> (f32, f32) {
+    // Top strand base of the Watson-Crick pair standing in for each pair
+    let top: Vec<Nucleotide> = pairs
+        .iter()
+        .map(|&(first, second)| match (first, second) {
+            (Nucleotide::G, Nucleotide::U) => Nucleotide::A,
+            _ => first,
+        })
+        .collect();
+    let (mut enthalpy, mut entropy): (f32, f32) = (0.0, 0.0);
+    top.windows(2).for_each(|stack| {
+        let (stack_enthalpy, stack_entropy) =
+            stack_parameters((*stack.first().unwrap(), *stack.last().unwrap()));
+        enthalpy += stack_enthalpy;
+        entropy += stack_entropy;
+    });
+    [top.first(), top.last()]
+        .into_iter()
+        .flatten()
+        .filter(|nucleotide| !nucleotide.is_gc())
+        .for_each(|_| {
+            enthalpy += TERMINAL_AU.0;
+            entropy += TERMINAL_AU.1;
+        });
+    (enthalpy, entropy)
+}
+
+// Entropy change of an RNA helix of this many base pairs, from 1 M NaCl to this sodium concentration (M)
+// Tan and Chen, 2007, for monovalent ions
+fn rna_salt_entropy(length: usize, sodium: f32) -> f32 {
+    let salt_log: f32 = sodium.ln();
+    let a: f32 = -0.075 * salt_log + 0.012 * salt_log * salt_log;
+    let b: f32 = 0.018 * salt_log * salt_log;
+    -3.22 * length.saturating_sub(1) as f32 * (a + b / length.max(1) as f32)
+}
+
+// Stability of every helix of a generated sequence
+// Helices are intramolecular, so they melt at ΔH/ΔS independently of the RNA concentration
+// A module inserted in either strand splits the helix into two, each reported for the same edge
+pub fn helix_stabilities(
+    generated: &GeneratedSequence,
+    settings: &StabilitySettings,
+) -> Vec<HelixStability> {
+    // Base pairs of every helix, following its sense strand
+    // Stacks continue only while both strands run on without anything in between
+    let mut helices: Vec<((usize, usize), Vec<BasePair>)> = Vec::new();
+    let mut last_pair: Option<(usize, usize)> = None;
+    generated
+        .nucleotides
+        .iter()
+        .enumerate()
+        .for_each(|(index, nucleotide)| {
+            if let (Source::Edge(edge), Role::Sense, Some(partner)) =
+                (nucleotide.source, nucleotide.role, nucleotide.partner)
+            {
+                let pair: BasePair = (
+                    nucleotide.base,
+                    generated.nucleotides.get(partner).unwrap().base,
+                );
+                let stacked: bool = last_pair.is_some_and(|(last_index, last_partner)| {
+                    last_index + 1 == index && last_partner == partner + 1
+                });
+                match helices.last_mut() {
+                    Some((last_edge, pairs)) if *last_edge == edge && stacked => pairs.push(pair),
+                    _ => helices.push((edge, vec![pair])),
+                }
+                last_pair = Some((index, partner));
+            }
+        });
+
+    let sodium: f32 = settings.conditions.sodium_equivalent();
+    let temperature: f32 = settings.conditions.temperature + KELVIN;
+    let mut stabilities: Vec<HelixStability> = helices
+        .iter()
+        .map(|(edge, pairs)| {
+            let (enthalpy, entropy) = helix_parameters(pairs);
+            let entropy: f32 = entropy + rna_salt_entropy(pairs.len(), sodium);
+            // A helix without any stack never forms
+            let melting_temperature: f32 = if enthalpy < 0.0 {
+                1000.0 * enthalpy / entropy - KELVIN
+            } else {
+                -KELVIN
+            };
+            HelixStability {
+                edge: *edge,
+                length: pairs.len(),
+                enthalpy,
+                entropy,
+                free_energy: enthalpy - temperature * entropy / 1000.0,
+                melting_temperature,
+                weak: melting_temperature
+                    < settings.conditions.temperature + settings.min_melting_margin,
+                strong: false,
+            }
+        })
+        .collect();
+
+    // Compare each helix with the helices sharing one of its junctions
+    let mut melting_at_node: FxHashMap<usize, Vec<(usize, f32)>> = FxHashMap::default();
+    stabilities.iter().enumerate().for_each(|(index, helix)| {
+        [helix.edge.0, helix.edge.1].into_iter().for_each(|node| {
+            melting_at_node
+                .entry(node)
+                .or_default()
+                .push((index, helix.melting_temperature))
+        })
+    });
+    stabilities
+        .iter_mut()
+        .enumerate()
+        .for_each(|(index, helix)| {
+            let neighbours: Vec<f32> = [helix.edge.0, helix.edge.1]
+                .iter()
+                .flat_map(|node| melting_at_node.get(node).unwrap())
+                .filter(|(other, _)| *other != index)
+                .map(|(_, melting_temperature)| *melting_temperature)
+                .collect();
+            if !neighbours.is_empty() {
+                let mean: f32 = neighbours.iter().sum::<f32>() / neighbours.len() as f32;
+                helix.strong = helix.melting_temperature > mean + settings.max_melting_excess;
+            }
+        });
+
+    stabilities
+}
+
+// Melting temperature (°C) of a DNA oligonucleotide on its perfect complement, U standing for T
+// The oligonucleotide is in excess at the given concentration (nM), as PCR primers are,
+// so that concentration sets the melting temperature rather than a quarter of the total
+pub fn dna_melting_temperature(
+    bases: &[Nucleotide],
+    conditions: &SolutionConditions,
+    concentration: f32,
+) -> f32 {
+    let (mut enthalpy, mut entropy): (f32, f32) = (0.0, 0.0);
+    bases.windows(2).for_each(|stack| {
+        let top: (Nucleotide, Nucleotide) = (*stack.first().unwrap(), *stack.last().unwrap());
+        let key: (Nucleotide, Nucleotide) = (top.1.complement(), top.0.complement());
+        let (stack_enthalpy, stack_entropy) = DNA_STACKS
+            .iter()
+            .find(|(stack, _)| *stack == top || *stack == key)
+            .map(|(_, parameters)| *parameters)
+            .unwrap();
+        enthalpy += stack_enthalpy;
+        entropy += stack_entropy;
+    });
+    [bases.first(), bases.last()]
+        .into_iter()
+        .flatten()
+        .for_each(|base| {
+            let (end_enthalpy, end_entropy) = if base.is_gc() {
+                DNA_INITIATION_GC
+            } else {
+                DNA_INITIATION_AT
+            };
+            enthalpy += end_enthalpy;
+            entropy += end_entropy;
+        });
+
+    let entropy: f32 = entropy
+        + 0.368 * bases.len().saturating_sub(1) as f32 * conditions.sodium_equivalent().ln();
+    1000.0 * enthalpy / (entropy + GAS_CONSTANT * (concentration.max(1e-3) * 1e-9).ln()) - KELVIN
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3A;
+
+    use super::*;
+    use crate::routines::{
+        graph::{Tree, construct_tree, find_rna_path},
+        motifs::{ModuleInsertion, ModulePlacement, module_library},
+        nucleotide::RnaSequence,
+        sequencer::{SequencerSettings, generate_sequence},
+        template::reverse_complement_dna,
+    };
+
+    #[test]
+    fn helices_have_no_initiation_term() {
+        let pairs: [BasePair; 2] = [
+            (Nucleotide::G, Nucleotide::C),
+            (Nucleotide::C, Nucleotide::G),
+        ];
+        assert_eq!(helix_parameters(&pairs), (-14.88, -36.9));
+    }
+
+    #[test]
+    fn salt_lowers_the_entropy_of_longer_helices() {
+        assert_eq!(rna_salt_entropy(10, 1.0), 0.0);
+        assert!(rna_salt_entropy(10, 0.1) < 0.0);
+        assert!(rna_salt_entropy(20, 0.1) < rna_salt_entropy(10, 0.1));
+        assert!(rna_salt_entropy(10, 0.01) < rna_salt_entropy(10, 0.1));
+    }
+
+    #[test]
+    fn dna_melting_reads_either_strand() {
+        let conditions: SolutionConditions = SolutionConditions::default();
+        let melting = |sequence: &str, concentration: f32| -> f32 {
+            let bases: RnaSequence = sequence.parse().unwrap();
+            dna_melting_temperature(&bases, &conditions, concentration)
+        };
+        // Every stack, including the six only found on the other strand
+        let primer: &str = "GAAGTTCTTCCAGGACCTCGAGTCAT";
+        let complement: String = reverse_complement_dna(primer);
+        assert!((melting(primer, 250.0) - melting(&complement, 250.0)).abs() < 1e-3);
+        assert_eq!(
+            melting(primer, 250.0),
+            melting(&primer.replace('T', "U"), 250.0)
+        );
+
+        assert!(melting("GCGCGGCCGCGC", 250.0) > melting("ATATTAATATAT", 250.0));
+        assert!(melting(primer, 1000.0) > melting(primer, 250.0));
+    }
+
+    #[test]
+    fn modules_split_helices() {
+        let coordinates: Vec<Vec3A> = vec![
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(20.0, 0.0, 0.0),
+            Vec3A::new(-10.0, 20.0, 0.0),
+            Vec3A::new(-10.0, -20.0, 0.0),
+        ];
+        let mut tree: Tree = construct_tree(&[(0, 1), (0, 2), (0, 3)]);
+        let path: Vec<usize> = find_rna_path(&mut tree, &coordinates);
+        let module = module_library()
+            .into_iter()
+            .find(|module| module.name == "BoxB");
+        let insert = |edge: (usize, usize)| ModuleInsertion {
+            placement: ModulePlacement::Edge { edge, offset: 3 },
+            module: module.clone().unwrap(),
+        };
+        let stabilities = |modules: Vec<ModuleInsertion>| -> Vec<HelixStability> {
+            let settings: SequencerSettings = SequencerSettings {
+                modules,
+                seed: Some(0),
+                ..Default::default()
+            };
+            let generated: GeneratedSequence =
+                generate_sequence(&path, &tree, &coordinates, &settings).unwrap();
+            helix_stabilities(&generated, &StabilitySettings::default())
+        };
+        let lengths = |stabilities: &[HelixStability]| -> Vec<usize> {
+            stabilities
+                .iter()
+                .filter(|helix| helix.edge == (0, 1))
+                .map(|helix| helix.length)
+                .collect()
+        };
+
+        let whole: Vec<usize> = lengths(&stabilities(vec![]));
+        assert_eq!(whole.len(), 1);
+        // In the sense strand, three pairs precede the module
+        let sense: Vec<usize> = lengths(&stabilities(vec![insert((0, 1))]));
+        assert_eq!(sense, vec![3, whole[0] - 3]);
+        // In the antisense strand, three pairs follow it
+        let antisense: Vec<usize> = lengths(&stabilities(vec![insert((1, 0))]));
+        assert_eq!(antisense, vec![whole[0] - 3, 3]);
+    }
+}
@@ -1,16 +1,41 @@
+use std::thread::{self, JoinHandle};
+
 use glam::Vec3A;
 use rustc_hash::FxHashSet;
 use three_d::{
     Camera, ClearState, Context, CpuMaterial, CpuMesh, DirectionalLight, FrameOutput, GUI, Gm,
     InnerSpace, Mat4, Mesh, OrbitControl, PhysicalMaterial, Quat, Srgba, Window, WindowSettings,
     degrees,
-    egui::{Response, SidePanel, TopBottomPanel},
+    egui::{Button, Color32, DragValue, Response, SidePanel, TopBottomPanel},
     vec3,
 };
 
 use super::{
+    analysis::{OffTargetReport, parse_dot_bracket, report_off_targets},
+    constraints::{GcWindow, SequencePin},
+    cotranscription::{FoldingSettings, FoldingStep, folding_order},
+    ensemble::{DefectReport, design_defect},
+    geometry::{Clash, ScaleTarget, find_clashes, preview_rescale, rescale, units_to_nm},
     graph::{Tree, construct_tree, find_rna_path},
-    sequencer::generate_sequence,
+    graph_file::{import_graph, is_graph_file},
+    io::{
+        EdgeRow, ImportSettings, OxrnaFiles, PdbAtoms, StructureFormat, Wireframe, edge_modules,
+        edge_pins, format_edge_table, format_edges, format_fasta, format_genbank,
+        format_node_coordinates, format_oxrna, format_pdb, format_structure, format_vertex_table,
+        import_mesh, parse_edges, parse_node_coordinates, parse_structure,
+    },
+    kinetics::{KineticResult, KineticSettings, simulate_cotranscriptional_folding},
+    model::{NucleotideFrame, build_trace},
+    motifs::{FunctionalModule, ModuleInsertion, module_library},
+    nucleotide::RnaSequence,
+    primers::{PrimerSettings, design_primers},
+    sequencer::{
+        GeneratedSequence, LengthEstimate, NucleotideRecord, SequencerSettings,
+        estimate_sequence_length, generate_sequence,
+    },
+    structure::TargetStructure,
+    template::{DnaTemplate, Promoter, TemplateSettings, build_template},
+    thermodynamics::{HelixStability, SolutionConditions, StabilitySettings, helix_stabilities},
 };
 
 // TODO
@@ -27,7 +52,14 @@ pub fn run_gui() {
     // RNA stuff
     let mut sequence: String = "No sequence generated yet...".to_string();
     let mut node_coordinates: Vec<Vec3A> = Vec::new();
-    let mut edges: FxHashSet<(usize, usize)> = FxHashSet::default();
+    let mut edges: Vec<(usize, usize)> = Vec::new(); // In the order the tree is grown
+    let mut edge_rows: Vec<EdgeRow> = Vec::new(); // Edges as typed, with their design options
+    let mut clashes: Vec<Clash> = Vec::new();
+    let mut settings: SequencerSettings = SequencerSettings::default();
+    let mut generation_report: String = String::new();
+    let mut generated_sequence: Option<GeneratedSequence> = None;
+    let mut generated_trace: Vec<NucleotideFrame> = Vec::new(); // 3D model of the generated sequence
+    let mut stability_settings: StabilitySettings = StabilitySettings::default();
 
     // 3D rendering stuff
     let mut camera: Camera = Camera::new_perspective(
@@ -51,6 +83,45 @@ pub fn run_gui() {
     // UI-specific editables
     let mut node_coordinates_text: String = "[-10.0, 0.0, 0.0]\n[10.0, 0.0, 0.0]\n".to_string();
     let mut edges_text: String = "(0, 1)".to_string();
+    let mut scale_mode: u8 = 0;
+    let mut scale_value: f32 = 20.0;
+    let mut scale_preview_text: String = String::new();
+    let mut mesh_path_text: String = String::new();
+    let mut import_settings: ImportSettings = ImportSettings::default();
+    let mut rescale_on_import: bool = false;
+    let mut mesh_import_text: String = String::new();
+    let mut length_estimate_text: String = String::new();
+    let mut length_estimate_outdated: bool = true;
+    let mut forbidden_text: String = settings
+        .constraints
+        .forbidden
+        .iter()
+        .map(|motif| motif.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+    let mut forbidden_error: bool = false;
+    let mut pins_text: String = String::new();
+    let mut pins_error: Option<usize> = None;
+    let mut custom_modules_text: String = String::new();
+    let mut modules_text: String = String::new();
+    let mut modules_error: Option<String> = None;
+    let mut analysis_sequence_text: String = String::new();
+    let mut analysis_structure_text: String = String::new();
+    let mut off_target_min_length: usize = 6;
+    let mut off_target_report: String = String::new();
+    let mut folding_settings: FoldingSettings = FoldingSettings::default();
+    let mut folding_report: String = String::new();
+    let mut kinetic_settings: KineticSettings = KineticSettings::default();
+    let mut folding_job: Option<JoinHandle<KineticResult>> = None;
+    let mut defect_report: String = String::new();
+    let mut defect_job: Option<JoinHandle<DefectReport>> = None;
+    let mut structure_file_text: String = String::new();
+    let mut pdb_atoms: PdbAtoms = PdbAtoms::Coarse;
+    let mut predicted_structure_text: String = String::new();
+    let mut comparison_report: String = String::new();
+    let mut template_settings: TemplateSettings = TemplateSettings::default();
+    let mut template_text: String = String::new();
+    let mut primer_settings: PrimerSettings = PrimerSettings::default();
 
     let mut gui: GUI = GUI::new(&context);
     window.render_loop(move |mut frame_input| {
@@ -64,55 +135,751 @@ pub fn run_gui() {
             |gui_context| {
                 SidePanel::left("side_panel").show(gui_context, |ui| {
                     ui.heading("Structure Vertices");
-                    ui.text_edit_multiline(&mut node_coordinates_text);
+                    if ui.text_edit_multiline(&mut node_coordinates_text).changed() {
+                        length_estimate_outdated = true;
+                    }
 
                     ui.heading("Structure Edges");
-                    ui.text_edit_multiline(&mut edges_text);
+                    if ui.text_edit_multiline(&mut edges_text).changed() {
+                        length_estimate_outdated = true;
+                    }
+
+                    // Rewrite both lists as tables, to be copied into a spreadsheet or file
+                    ui.horizontal(|ui| {
+                        ui.label("Tables");
+                        [("CSV", ','), ("TSV", '\t')]
+                            .into_iter()
+                            .for_each(|(label, separator)| {
+                                if ui.button(label).clicked() {
+                                    match parse_shape(&node_coordinates_text, &edges_text) {
+                                        Ok((coordinates, rows)) => {
+                                            node_coordinates_text =
+                                                format_vertex_table(&coordinates, separator);
+                                            edges_text = format_edge_table(&rows, separator);
+                                        }
+                                        Err(error) => sequence = format!("Error: {error}"),
+                                    }
+                                }
+                            });
+                    });
+
+                    // Live estimate of the sequence length, updated as the structure is edited
+                    if length_estimate_outdated {
+                        length_estimate_text = describe_length_estimate(
+                            &node_coordinates_text,
+                            &edges_text,
+                            &settings.modules,
+                        );
+                        length_estimate_outdated = false;
+                    }
+                    ui.label(&length_estimate_text);
+
+                    ui.collapsing("Import mesh or graph", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("OBJ, PLY, GraphML or JSON file");
+                            ui.text_edit_singleline(&mut mesh_path_text);
+                        });
+                        ui.checkbox(&mut import_settings.recentre, "Recentre");
+                        ui.checkbox(&mut rescale_on_import, "Rescale with the settings below");
+                        if ui.button("Import").clicked() {
+                            import_settings.scale =
+                                rescale_on_import.then(|| scale_target(scale_mode, scale_value));
+                            let path: &str = mesh_path_text.trim();
+                            // Graphs come with edge options, kept by writing the edges as a table
+                            let imported: Result<(Wireframe, Option<Vec<EdgeRow>>), String> =
+                                std::fs::read_to_string(path)
+                                    .map_err(|error| error.to_string())
+                                    .and_then(|input| {
+                                        if is_graph_file(path) {
+                                            import_graph(&input, &import_settings)
+                                                .map(|(wireframe, rows)| (wireframe, Some(rows)))
+                                                .map_err(|error| error.to_string())
+                                        } else {
+                                            import_mesh(&input, &import_settings)
+                                                .map(|wireframe| (wireframe, None))
+                                                .map_err(|error| error.to_string())
+                                        }
+                                    });
+                            match imported {
+                                Ok((wireframe, rows)) => {
+                                    match rows {
+                                        Some(rows) => {
+                                            mesh_import_text = format!(
+                                                "Imported {} vertices and {} edges",
+                                                wireframe.vertices.len(),
+                                                wireframe.edges.len()
+                                            );
+                                            node_coordinates_text =
+                                                format_vertex_table(&wireframe.vertices, ',');
+                                            edges_text = format_edge_table(&rows, ',');
+                                            edge_rows = rows;
+                                        }
+                                        None => {
+                                            mesh_import_text = format!(
+                                                "Imported {} vertices, {} edges and {} faces",
+                                                wireframe.vertices.len(),
+                                                wireframe.edges.len(),
+                                                wireframe.faces.len()
+                                            );
+                                            node_coordinates_text =
+                                                format_node_coordinates(&wireframe.vertices);
+                                            edges_text = format_edges(&wireframe.edges);
+                                        }
+                                    }
+                                    length_estimate_outdated = true;
+                                    node_coordinates = wireframe.vertices;
+                                    clashes = find_clashes(&wireframe.edges, &node_coordinates);
+                                    edges = wireframe.edges;
+                                    update_rendered_object(
+                                        &mut rendered_nodes,
+                                        &node_coordinates,
+                                        &mut rendered_edges,
+                                        &edges,
+                                        &clashes,
+                                        &context,
+                                    );
+                                }
+                                Err(error) => mesh_import_text = format!("Error: {error}"),
+                            }
+                        }
+                        if !mesh_import_text.is_empty() {
+                            ui.label(&mesh_import_text);
+                        }
+                    });
+
+                    ui.collapsing("Sequence constraints", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("GC content");
+                            ui.add(
+                                DragValue::new(&mut settings.constraints.gc_range.0).speed(0.01),
+                            );
+                            ui.add(
+                                DragValue::new(&mut settings.constraints.gc_range.1).speed(0.01),
+                            );
+                        });
+
+                        let mut window_enabled: bool = settings.constraints.window.is_some();
+                        if ui
+                            .checkbox(&mut window_enabled, "GC content window")
+                            .changed()
+                        {
+                            settings.constraints.window = window_enabled.then_some(GcWindow {
+                                size: 20,
+                                min: 0.2,
+                                max: 0.8,
+                            });
+                        }
+                        if let Some(window) = settings.constraints.window.as_mut() {
+                            ui.horizontal(|ui| {
+                                ui.label("Size");
+                                ui.add(DragValue::new(&mut window.size));
+                                ui.label("GC");
+                                ui.add(DragValue::new(&mut window.min).speed(0.01));
+                                ui.add(DragValue::new(&mut window.max).speed(0.01));
+                            });
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.label("Max homopolymer");
+                            ui.add(DragValue::new(&mut settings.constraints.max_homopolymer));
+                        });
+
+                        ui.label("Forbidden motifs");
+                        if ui.text_edit_singleline(&mut forbidden_text).changed() {
+                            match forbidden_text
+                                .split(',')
+                                .filter(|motif| !motif.trim().is_empty())
+                                .map(|motif| motif.parse::<RnaSequence>())
+                                .collect::<Result<Vec<RnaSequence>, _>>()
+                            {
+                                Ok(forbidden) => {
+                                    settings.constraints.forbidden = forbidden;
+                                    forbidden_error = false;
+                                }
+                                Err(_) => forbidden_error = true,
+                            }
+                        }
+                        if forbidden_error {
+                            ui.colored_label(Color32::RED, "Invalid motif, previous motifs kept");
+                        }
+                    });
+
+                    ui.collapsing("G·U wobble pairs", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Fraction");
+                            ui.add(DragValue::new(&mut settings.wobble.fraction).speed(0.01));
+                        });
+                        let mut limit_per_helix: bool = settings.wobble.max_per_helix.is_some();
+                        if ui
+                            .checkbox(&mut limit_per_helix, "Limit per helix")
+                            .changed()
+                        {
+                            settings.wobble.max_per_helix = limit_per_helix.then_some(2);
+                        }
+                        if let Some(max_per_helix) = settings.wobble.max_per_helix.as_mut() {
+                            ui.add(DragValue::new(max_per_helix));
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Max consecutive");
+                            ui.add(DragValue::new(&mut settings.wobble.max_consecutive));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Distance to junctions");
+                            ui.add(DragValue::new(&mut settings.wobble.junction_exclusion));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Distance to kissing loops");
+                            ui.add(DragValue::new(&mut settings.wobble.kissing_loop_exclusion));
+                        });
+                    });
+
+                    ui.collapsing("Symmetry minimisation", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Longest unintended match");
+                            ui.add(DragValue::new(&mut settings.symmetry.max_match));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Iterations");
+                            ui.add(DragValue::new(&mut settings.symmetry.max_iterations));
+                        });
+                    });
+
+                    ui.collapsing("Helix stability", |ui| {
+                        let conditions: &mut SolutionConditions =
+                            &mut stability_settings.conditions;
+                        ui.horizontal(|ui| {
+                            ui.label("Temperature (°C)");
+                            ui.add(DragValue::new(&mut conditions.temperature));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Na+ (mM)");
+                            ui.add(DragValue::new(&mut conditions.sodium));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Mg2+ (mM)");
+                            ui.add(DragValue::new(&mut conditions.magnesium).speed(0.1));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Weak below Tm margin (°C)");
+                            ui.add(DragValue::new(&mut stability_settings.min_melting_margin));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Strong above neighbours (°C)");
+                            ui.add(DragValue::new(&mut stability_settings.max_melting_excess));
+                        });
+                    });
+
+                    ui.collapsing("Pinned sequences", |ui| {
+                        ui.label("edge <origin> <destination> <offset> <IUPAC sequence>");
+                        ui.label("node <id> <offset> <IUPAC sequence>");
+                        if ui.text_edit_multiline(&mut pins_text).changed() {
+                            match pins_text
+                                .lines()
+                                .enumerate()
+                                .filter(|(_, line)| !line.trim().is_empty())
+                                .map(|(index, line)| {
+                                    line.parse::<SequencePin>().map_err(|_| index + 1)
+                                })
+                                .collect::<Result<Vec<SequencePin>, usize>>()
+                            {
+                                Ok(pins) => {
+                                    settings.pins = pins;
+                                    pins_error = None;
+                                }
+                                Err(line) => pins_error = Some(line),
+                            }
+                        }
+                        if let Some(line) = pins_error {
+                            ui.colored_label(
+                                Color32::RED,
+                                format!("Invalid pin on line {line}, previous pins kept"),
+                            );
+                        }
+                    });
+
+                    ui.collapsing("Functional modules", |ui| {
+                        ui.label(format!(
+                            "Library: {}",
+                            module_library()
+                                .iter()
+                                .map(|module| module.name.as_str())
+                                .collect::<Vec<&str>>()
+                                .join(", ")
+                        ));
+                        ui.label("Custom modules: <name> <sequence> <dot-bracket structure>");
+                        let custom_changed: bool =
+                            ui.text_edit_multiline(&mut custom_modules_text).changed();
+                        ui.label("hairpin <node> <module>");
+                        ui.label("edge <origin> <destination> <offset> <module>");
+                        let modules_changed: bool =
+                            ui.text_edit_multiline(&mut modules_text).changed();
+                        if custom_changed || modules_changed {
+                            match parse_module_insertions(&custom_modules_text, &modules_text) {
+                                Ok(modules) => {
+                                    settings.modules = modules;
+                                    modules_error = None;
+                                    length_estimate_outdated = true;
+                                }
+                                Err(error) => modules_error = Some(error),
+                            }
+                        }
+                        if let Some(error) = &modules_error {
+                            ui.colored_label(
+                                Color32::RED,
+                                format!("{error}, previous modules kept"),
+                            );
+                        }
+                    });
 
                     let btn_genseq: Response = ui.button("Generate sequence");
                     let btn_vis: Response = ui.button("Visualise");
                     if btn_vis.clicked() {
-                        update_node_coordinates(
+                        if let Err(error) = try_update_shape(
                             &mut node_coordinates,
+                            &mut edges,
+                            &mut edge_rows,
                             &node_coordinates_text,
-                            &mut sequence,
-                        );
-                        if try_update_edges(&mut edges, &edges_text, &node_coordinates).is_err() {
-                            sequence = "Error: Invalid edge data".to_string();
+                            &edges_text,
+                        ) {
+                            sequence = format!("Error: {error}");
                         }
+                        clashes = find_clashes(&edges, &node_coordinates);
                         update_rendered_object(
                             &mut rendered_nodes,
                             &node_coordinates,
                             &mut rendered_edges,
                             &edges,
+                            &clashes,
                             &context,
                         );
                     }
 
                     if btn_genseq.clicked() && !edges_text.is_empty() {
-                        update_node_coordinates(
+                        if let Err(error) = try_update_shape(
                             &mut node_coordinates,
+                            &mut edges,
+                            &mut edge_rows,
                             &node_coordinates_text,
-                            &mut sequence,
-                        );
-                        if try_update_edges(&mut edges, &edges_text, &node_coordinates).is_err() {
-                            sequence = "Error: Invalid edge data".to_string();
+                            &edges_text,
+                        ) {
+                            sequence = format!("Error: {error}");
                         } else {
-                            log::debug!("Nodes: {node_coordinates:?}");
-                            log::debug!("Edges: {edges:?}");
+                            match with_edge_options(&settings, &edge_rows, &custom_modules_text) {
+                                Err(error) => sequence = format!("Error: {error}"),
+                                Ok(design_settings) => {
+                                    log::debug!("Nodes: {node_coordinates:?}");
+                                    log::debug!("Edges: {edges:?}");
 
-                            let mut tree: Tree = construct_tree(
-                                &edges.iter().copied().collect::<Vec<(usize, usize)>>(),
-                            );
-                            let path: Vec<usize> = find_rna_path(&mut tree, &node_coordinates);
-                            log::debug!("Path: {path:?}");
-                            log::debug!("Last coords: {node_coordinates:?}");
-                            log::debug!("Tree: {tree:?}");
-                            sequence = generate_sequence(&path, &tree, &node_coordinates);
+                                    let mut tree: Tree = construct_tree(&edges);
+                                    let path: Vec<usize> = find_rna_path(&mut tree, &node_coordinates);
+                                    log::debug!("Path: {path:?}");
+                                    log::debug!("Last coords: {node_coordinates:?}");
+                                    log::debug!("Tree: {tree:?}");
+                                    match generate_sequence(&path, &tree, &node_coordinates, &design_settings) {
+                                        Ok(generated) => {
+                                            sequence = generated.to_string();
+                                            generation_report = format!(
+                                                "{}\n{}",
+                                                describe_generation(&generated),
+                                                describe_stabilities(&helix_stabilities(
+                                                    &generated,
+                                                    &stability_settings
+                                                ))
+                                            );
+                                            analysis_sequence_text = sequence.clone();
+                                            analysis_structure_text = generated.dot_bracket();
+                                            generated_trace =
+                                                build_trace(&generated, &tree, &node_coordinates);
+                                            generated_sequence = Some(generated);
+                                        }
+                                        Err(error) => {
+                                            sequence = format!("Error: {error}");
+                                            generation_report.clear();
+                                            generated_sequence = None;
+                                            generated_trace.clear();
+                                        }
+                                    }
+                                }
+                            }
                         }
                     } else if btn_genseq.clicked() && edges.is_empty() {
                         sequence = "Error: Invalid shape".to_string();
                     }
+
+                    ui.collapsing("Off-target analysis", |ui| {
+                        ui.label("Sequence");
+                        ui.text_edit_multiline(&mut analysis_sequence_text);
+                        ui.label("Intended structure (dot-bracket)");
+                        ui.text_edit_multiline(&mut analysis_structure_text);
+                        ui.horizontal(|ui| {
+                            ui.label("Shortest stretch");
+                            ui.add(DragValue::new(&mut off_target_min_length));
+                        });
+                        if ui.button("Search off-targets").clicked() {
+                            off_target_report = describe_off_targets(
+                                &analysis_sequence_text,
+                                &analysis_structure_text,
+                                off_target_min_length,
+                                generated_sequence.as_ref(),
+                            );
+                        }
+                        if !off_target_report.is_empty() {
+                            ui.label(&off_target_report);
+                        }
+                    });
+
+                    ui.collapsing("Cotranscriptional folding", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Longest delay (nt)");
+                            ui.add(DragValue::new(&mut folding_settings.max_delay));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Shortest trap (nt)");
+                            ui.add(DragValue::new(&mut folding_settings.trap_length));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Transcription rate (nt per time unit)");
+                            ui.add(
+                                DragValue::new(&mut kinetic_settings.transcription_rate)
+                                    .speed(0.01)
+                                    .range(0.001..=10.0),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Time after transcription");
+                            ui.add(
+                                DragValue::new(&mut kinetic_settings.extra_time).range(0.0..=1e6),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Trajectories");
+                            ui.add(
+                                DragValue::new(&mut kinetic_settings.trajectories).range(1..=1000),
+                            );
+                        });
+                        // Simulations take a while, they run in the background so the window stays responsive
+                        if ui
+                            .add_enabled(folding_job.is_none(), Button::new("Simulate folding"))
+                            .clicked()
+                        {
+                            match &generated_sequence {
+                                Some(generated) => {
+                                    let sequence: RnaSequence = generated.sequence();
+                                    let target: Vec<Option<usize>> =
+                                        TargetStructure::from_generated(generated).nested_partners();
+                                    let settings: KineticSettings = kinetic_settings;
+                                    folding_job = Some(thread::spawn(move || {
+                                        simulate_cotranscriptional_folding(
+                                            &sequence, &target, &settings,
+                                        )
+                                    }));
+                                    folding_report = "Simulating folding...".to_string();
+                                }
+                                None => {
+                                    folding_report = "Error: No sequence generated yet".to_string()
+                                }
+                            }
+                        }
+                        if folding_job.as_ref().is_some_and(|job| job.is_finished()) {
+                            folding_report = match folding_job.take().unwrap().join() {
+                                Ok(result) => result.to_string(),
+                                Err(_) => "Error: The simulation failed".to_string(),
+                            };
+                        }
+                        if ui.button("Folding order").clicked() {
+                            folding_report = match &generated_sequence {
+                                Some(generated) => describe_folding_order(&folding_order(
+                                    generated,
+                                    &folding_settings,
+                                )),
+                                None => "Error: No sequence generated yet".to_string(),
+                            };
+                        }
+                        if !folding_report.is_empty() {
+                            ui.label(&folding_report);
+                        }
+                    });
+
+                    ui.collapsing("Ensemble defect", |ui| {
+                        // The partition function is cubic in the length, it runs in the background too
+                        if ui
+                            .add_enabled(
+                                defect_job.is_none(),
+                                Button::new("Compute base pair probabilities"),
+                            )
+                            .clicked()
+                        {
+                            match &generated_sequence {
+                                Some(generated) => {
+                                    let generated: GeneratedSequence = generated.clone();
+                                    defect_job =
+                                        Some(thread::spawn(move || design_defect(&generated)));
+                                    defect_report =
+                                        "Computing base pair probabilities...".to_string();
+                                }
+                                None => {
+                                    defect_report = "Error: No sequence generated yet".to_string()
+                                }
+                            }
+                        }
+                        if defect_job.as_ref().is_some_and(|job| job.is_finished()) {
+                            defect_report = match defect_job.take().unwrap().join() {
+                                Ok(report) => report.to_string(),
+                                Err(_) => "Error: The partition function failed".to_string(),
+                            };
+                        }
+                        if !defect_report.is_empty() {
+                            ui.label(&defect_report);
+                        }
+                    });
+
+                    ui.collapsing("Structure files", |ui| {
+                        ui.horizontal(|ui| {
+                            [
+                                (StructureFormat::Ct, "CT"),
+                                (StructureFormat::Bpseq, "BPSEQ"),
+                                (StructureFormat::Dbn, "Dot-bracket"),
+                            ]
+                            .into_iter()
+                            .for_each(|(format, label)| {
+                                if ui.button(label).clicked() {
+                                    structure_file_text = match &generated_sequence {
+                                        Some(generated) => format_structure(
+                                            "rrna_design",
+                                            &TargetStructure::from_generated(generated),
+                                            format,
+                                        ),
+                                        None => "Error: No sequence generated yet".to_string(),
+                                    };
+                                }
+                            });
+                        });
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut pdb_atoms, PdbAtoms::Single, "C4' only");
+                            ui.radio_value(&mut pdb_atoms, PdbAtoms::Coarse, "P, C4' and N");
+                            if ui.button("PDB model").clicked() {
+                                structure_file_text = match &generated_sequence {
+                                    Some(generated) => format_pdb(
+                                        "rrna_design",
+                                        &[(&generated.sequence(), &generated_trace)],
+                                        pdb_atoms,
+                                    ),
+                                    None => "Error: No sequence generated yet".to_string(),
+                                };
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("oxRNA");
+                            [0, 1, 2]
+                                .into_iter()
+                                .zip(["Topology", "Configuration", "Mutual traps"])
+                                .for_each(|(file, label)| {
+                                    if ui.button(label).clicked() {
+                                        structure_file_text = match &generated_sequence {
+                                            Some(generated) => {
+                                                let pairs: Vec<(usize, usize)> =
+                                                    TargetStructure::from_generated(generated)
+                                                        .pairs
+                                                        .iter()
+                                                        .map(|pair| (pair.first, pair.second))
+                                                        .collect();
+                                                let files: OxrnaFiles = format_oxrna(
+                                                    &[(&generated.sequence(), &generated_trace)],
+                                                    &pairs,
+                                                );
+                                                match file {
+                                                    0 => files.topology,
+                                                    1 => files.configuration,
+                                                    _ => files.traps,
+                                                }
+                                            }
+                                            None => "Error: No sequence generated yet".to_string(),
+                                        };
+                                    }
+                                });
+                        });
+                        if !structure_file_text.is_empty() {
+                            ui.code_editor(&mut structure_file_text.as_str());
+                            if ui.button("Copy structure").clicked() {
+                                ui.output_mut(|o| o.copied_text = structure_file_text.clone());
+                            }
+                        }
+                        ui.label("Predicted structure (CT, BPSEQ or dot-bracket)");
+                        ui.text_edit_multiline(&mut predicted_structure_text);
+                        if ui.button("Compare with design").clicked() {
+                            comparison_report = match (
+                                &generated_sequence,
+                                parse_structure(&predicted_structure_text),
+                            ) {
+                                (None, _) => "Error: No sequence generated yet".to_string(),
+                                (_, Err(error)) => format!("Error: {error}"),
+                                (Some(generated), Ok(predicted)) => {
+                                    match TargetStructure::from_generated(generated)
+                                        .compare(&predicted)
+                                    {
+                                        Some(comparison) => comparison.to_string(),
+                                        None => format!(
+                                            "Error: The prediction has {} nucleotides, the design {}",
+                                            predicted.len(),
+                                            generated.nucleotides.len()
+                                        ),
+                                    }
+                                }
+                            };
+                        }
+                        if !comparison_report.is_empty() {
+                            ui.label(&comparison_report);
+                        }
+                    });
+
+                    ui.collapsing("DNA template", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut template_settings.promoter, None, "No promoter");
+                            ui.radio_value(
+                                &mut template_settings.promoter,
+                                Some(Promoter::T7),
+                                "T7",
+                            );
+                            ui.radio_value(
+                                &mut template_settings.promoter,
+                                Some(Promoter::Sp6),
+                                "SP6",
+                            );
+                        });
+                        ui.checkbox(&mut template_settings.hammerhead, "5' hammerhead ribozyme");
+                        ui.checkbox(&mut template_settings.hdv, "3' HDV ribozyme");
+                        ui.checkbox(&mut template_settings.terminator, "T7 terminator");
+                        ui.horizontal(|ui| {
+                            ui.label("Primer annealing temperature (°C):");
+                            ui.add(
+                                DragValue::new(&mut primer_settings.conditions.temperature)
+                                    .speed(0.5),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            let btn_fasta: Response = ui.button("FASTA");
+                            let btn_genbank: Response = ui.button("GenBank");
+                            let btn_primers: Response = ui.button("Primers");
+                            if btn_primers.clicked() {
+                                template_text = match &generated_sequence {
+                                    Some(generated) => design_primers(
+                                        generated,
+                                        &template_settings,
+                                        &primer_settings,
+                                    )
+                                    .to_string(),
+                                    None => "Error: No sequence generated yet".to_string(),
+                                };
+                            }
+                            if btn_fasta.clicked() || btn_genbank.clicked() {
+                                template_text = match &generated_sequence {
+                                    Some(generated) => {
+                                        let template: DnaTemplate = build_template(
+                                            &generated.sequence(),
+                                            &template_settings,
+                                        );
+                                        if btn_genbank.clicked() {
+                                            format_genbank("rrna_design", &template)
+                                        } else {
+                                            format_fasta(&[
+                                                ("rrna_design template", &template.sequence),
+                                                (
+                                                    "rrna_design template reverse complement",
+                                                    &template.reverse_complement(),
+                                                ),
+                                            ])
+                                        }
+                                    }
+                                    None => "Error: No sequence generated yet".to_string(),
+                                };
+                            }
+                        });
+                        if !template_text.is_empty() {
+                            ui.code_editor(&mut template_text.as_str());
+                            if ui.button("Copy template").clicked() {
+                                ui.output_mut(|o| o.copied_text = template_text.clone());
+                            }
+                        }
+                    });
+
+                    ui.heading("Rescale");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut scale_mode, 0, "Bounding box");
+                        ui.radio_value(&mut scale_mode, 1, "Mean edge length");
+                        ui.radio_value(&mut scale_mode, 2, "Sequence length");
+                    });
+                    ui.add(DragValue::new(&mut scale_value).speed(1.0));
+                    let btn_preview: Response = ui.button("Preview");
+                    let btn_rescale: Response = ui.button("Rescale");
+                    if btn_preview.clicked() || btn_rescale.clicked() {
+                        if let Err(error) = try_update_shape(
+                            &mut node_coordinates,
+                            &mut edges,
+                            &mut edge_rows,
+                            &node_coordinates_text,
+                            &edges_text,
+                        ) {
+                            sequence = format!("Error: {error}");
+                        } else {
+                            let target: ScaleTarget = scale_target(scale_mode, scale_value);
+                            match preview_rescale(&edges, &node_coordinates, target) {
+                                Ok(preview) => {
+                                    scale_preview_text = format!(
+                                        "Factor: {:.3} | Sequence length: {} nt",
+                                        preview.factor, preview.sequence_length
+                                    );
+
+                                    // Apply the scaling and write it back to the vertices
+                                    if btn_rescale.clicked() {
+                                        rescale(&mut node_coordinates, preview.factor);
+                                        node_coordinates_text =
+                                            format_node_coordinates(&node_coordinates);
+                                        length_estimate_outdated = true;
+                                        clashes = find_clashes(&edges, &node_coordinates);
+                                        update_rendered_object(
+                                            &mut rendered_nodes,
+                                            &node_coordinates,
+                                            &mut rendered_edges,
+                                            &edges,
+                                            &clashes,
+                                            &context,
+                                        );
+                                    }
+                                }
+                                Err(error) => scale_preview_text = format!("Error: {error}"),
+                            }
+                        }
+                    }
+                    if !scale_preview_text.is_empty() {
+                        ui.label(&scale_preview_text);
+                    }
+
+                    if !clashes.is_empty() {
+                        ui.heading("Steric clashes");
+                        clashes.iter().for_each(|clash| {
+                            ui.colored_label(
+                                Color32::RED,
+                                match clash {
+                                    Clash::Helices {
+                                        first,
+                                        second,
+                                        distance,
+                                    } => format!(
+                                        "Edges {first:?} & {second:?}: axes {:.1} nm apart",
+                                        units_to_nm(*distance)
+                                    ),
+                                    Clash::Junction {
+                                        edge,
+                                        node,
+                                        distance,
+                                    } => format!(
+                                        "Edge {edge:?} & vertex {node}: axis {:.1} nm away",
+                                        units_to_nm(*distance)
+                                    ),
+                                },
+                            );
+                        });
+                    }
                     panel_width = gui_context.used_rect().width();
                 });
                 TopBottomPanel::bottom("bottom_panel").show(gui_context, |ui| {
@@ -121,6 +888,9 @@ pub fn run_gui() {
                     if ui.button("Copy to clipboard").clicked() {
                         ui.output_mut(|o| o.copied_text = sequence.clone());
                     }
+                    if !generation_report.is_empty() {
+                        ui.label(&generation_report);
+                    }
                 });
             },
         );
@@ -145,93 +915,228 @@ pub fn run_gui() {
     });
 }
 
-fn update_node_coordinates(coordinates: &mut Vec<Vec3A>, input: &str, sequence: &mut String) {
-    // Parse the input coordinate string to Vec3A coordinate values
-
-    let mut new_coords: Vec<Vec3A> = Vec::with_capacity(coordinates.len());
-
-    input
-        .chars()
-        .filter(|c| !(c.is_whitespace() || *c == '['))
-        .collect::<String>()
-        .split(']')
-        .for_each(|coords| {
-            if !coords.is_empty() {
-                let num_coords = coords
-                    .split(',')
-                    .map(|val| match val.parse::<f32>() {
-                        Ok(coord) => coord,
-                        Err(_) => f32::NAN,
-                    })
-                    .collect::<Vec<f32>>();
-
-                if num_coords.contains(&f32::NAN) {
-                    *sequence = "Failed to parse input coordinates...".to_string();
-                } else {
-                    new_coords.push(Vec3A::from_slice(&num_coords));
-                }
-            }
-        });
+fn describe_generation(generated: &GeneratedSequence) -> String {
+    let constraints_report: String = if generated.violations.is_empty() {
+        "All sequence constraints satisfied".to_string()
+    } else {
+        generated.violations.iter().fold(
+            "Unsatisfied constraints:".to_string(),
+            |report, violation| format!("{report}\n- {violation}"),
+        )
+    };
+    let symmetry_report: String = match generated.longest_off_target {
+        Some(off_target) => format!("Longest unintended match: {off_target}"),
+        None => "No unintended match".to_string(),
+    };
+    format!(
+        "{constraints_report}\n{} G·U wobble pairs at {:?}\n{symmetry_report}",
+        generated.wobbles.len(),
+        generated.wobbles
+    )
+}
 
-    *coordinates = new_coords;
+fn describe_stabilities(stabilities: &[HelixStability]) -> String {
+    stabilities
+        .iter()
+        .fold("Helix stability:".to_string(), |report, helix| {
+            format!("{report}\n- {helix}")
+        })
 }
 
-fn try_update_edges(
-    edges: &mut FxHashSet<(usize, usize)>,
-    input: &str,
-    nodes: &[Vec3A],
-) -> Result<(), InvalidNodeDataError> {
-    let mut new_edges: FxHashSet<(usize, usize)> = FxHashSet::default();
-
-    if input
-        .chars()
-        .filter(|c| !(c.is_whitespace() || *c == '('))
-        .collect::<String>()
-        .split(')')
-        .map(|edge| {
-            if !edge.is_empty() {
-                let indices: Vec<usize> = edge
-                    .split(',')
-                    .map(|val| match val.parse::<usize>() {
-                        Ok(index) => {
-                            if index > nodes.len() {
-                                usize::MAX
-                            } else {
-                                index
-                            }
-                        }
-                        Err(_) => usize::MAX,
-                    })
-                    .collect();
+fn describe_folding_order(steps: &[FoldingStep]) -> String {
+    let long_range: usize = steps.iter().filter(|step| step.long_range).count();
+    let trapped: usize = steps.iter().filter(|step| !step.traps.is_empty()).count();
+    steps.iter().fold(
+        format!(
+            "{} elements, {long_range} long range, {trapped} with possible traps:",
+            steps.len()
+        ),
+        |report, step| {
+            step.traps
+                .iter()
+                .fold(format!("{report}\n- {step}"), |report, trap| {
+                    format!("{report}\n    trap {trap}")
+                })
+        },
+    )
+}
 
-                if indices.contains(&usize::MAX) {
-                    Err(InvalidNodeDataError)
-                } else {
-                    new_edges.insert((*indices.first().unwrap(), *indices.last().unwrap()));
-                    Ok(())
-                }
-            } else {
-                Ok(())
-            }
-        })
-        .any(|res| res.is_err())
-    {
-        Err(InvalidNodeDataError)
+fn describe_off_targets(
+    sequence_text: &str,
+    structure_text: &str,
+    min_length: usize,
+    generated: Option<&GeneratedSequence>,
+) -> String {
+    let sequence: RnaSequence = match sequence_text.parse() {
+        Ok(sequence) => sequence,
+        Err(error) => return format!("Error: {error}"),
+    };
+    let partners: Vec<Option<usize>> = match parse_dot_bracket(structure_text) {
+        Ok(partners) => partners,
+        Err(error) => return format!("Error: {error}"),
+    };
+    if sequence.len() != partners.len() {
+        return format!(
+            "Error: Sequence of {} nt but structure of {} nt",
+            sequence.len(),
+            partners.len()
+        );
+    }
+
+    // Helices and motifs are only known if the sequence is the one last generated
+    let provenance: Option<&[NucleotideRecord]> = generated
+        .filter(|generated| generated.sequence() == sequence)
+        .map(|generated| generated.nucleotides.as_slice());
+    let reports: Vec<OffTargetReport> =
+        report_off_targets(&sequence, &partners, min_length, provenance);
+    if reports.is_empty() {
+        format!("No unintended stretch of {min_length} nt or more")
     } else {
-        *edges = new_edges;
-        Ok(())
+        reports.iter().fold(
+            format!("{} unintended stretches:", reports.len()),
+            |report, off_target| format!("{report}\n- {off_target}"),
+        )
+    }
+}
+
+// Vertices and edges typed in the text boxes
+fn parse_shape(
+    node_coordinates_text: &str,
+    edges_text: &str,
+) -> Result<(Vec<Vec3A>, Vec<EdgeRow>), String> {
+    let coordinates: Vec<Vec3A> = parse_node_coordinates(node_coordinates_text)
+        .map_err(|error| format!("Vertices: {error}"))?;
+    let rows: Vec<EdgeRow> =
+        parse_edges(edges_text, coordinates.len()).map_err(|error| format!("Edges: {error}"))?;
+    Ok((coordinates, rows))
+}
+
+// The structure is left unchanged if either text box is invalid
+fn try_update_shape(
+    node_coordinates: &mut Vec<Vec3A>,
+    edges: &mut Vec<(usize, usize)>,
+    edge_rows: &mut Vec<EdgeRow>,
+    node_coordinates_text: &str,
+    edges_text: &str,
+) -> Result<(), String> {
+    let (wireframe, rows) = parse_wireframe(node_coordinates_text, edges_text)?;
+    *node_coordinates = wireframe.vertices;
+    *edges = wireframe.edges;
+    *edge_rows = rows;
+    Ok(())
+}
+
+// Typed vertices and edges, with the edges in the order the tree is grown
+fn parse_wireframe(
+    node_coordinates_text: &str,
+    edges_text: &str,
+) -> Result<(Wireframe, Vec<EdgeRow>), String> {
+    let (coordinates, mut rows) = parse_shape(node_coordinates_text, edges_text)?;
+    let wireframe: Wireframe = Wireframe::from_rows(coordinates, &mut rows)
+        .ok_or("Edges: The edges are in several disconnected parts")?;
+    Ok((wireframe, rows))
+}
+
+// Settings with the pins and module insertions given as edge options added
+fn with_edge_options(
+    settings: &SequencerSettings,
+    edge_rows: &[EdgeRow],
+    custom_modules_text: &str,
+) -> Result<SequencerSettings, String> {
+    let mut design_settings: SequencerSettings = settings.clone();
+    design_settings.pins.extend(edge_pins(edge_rows));
+    design_settings.modules.extend(
+        edge_modules(edge_rows, &full_module_library(custom_modules_text)?)
+            .map_err(|_| "Unknown module in the edge options".to_string())?,
+    );
+    Ok(design_settings)
+}
+
+// Built-in modules followed by the custom ones
+fn full_module_library(custom_modules_text: &str) -> Result<Vec<FunctionalModule>, String> {
+    let mut library: Vec<FunctionalModule> = module_library();
+    custom_modules_text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .try_for_each(|(index, line)| {
+            library.push(
+                line.parse()
+                    .map_err(|_| format!("Invalid custom module on line {}", index + 1))?,
+            );
+            Ok::<(), String>(())
+        })?;
+    Ok(library)
+}
+
+// Parse custom modules, then the insertions of library and custom modules
+fn parse_module_insertions(
+    custom_modules_text: &str,
+    modules_text: &str,
+) -> Result<Vec<ModuleInsertion>, String> {
+    let library: Vec<FunctionalModule> = full_module_library(custom_modules_text)?;
+    modules_text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            ModuleInsertion::parse(line, &library)
+                .map_err(|_| format!("Invalid module insertion on line {}", index + 1))
+        })
+        .collect()
+}
+
+fn scale_target(scale_mode: u8, scale_value: f32) -> ScaleTarget {
+    match scale_mode {
+        0 => ScaleTarget::BoundingBox(scale_value),
+        1 => ScaleTarget::MeanEdgeLength(scale_value),
+        _ => ScaleTarget::SequenceLength(scale_value.max(0.0).round() as usize),
     }
 }
 
-struct InvalidNodeDataError;
+fn describe_length_estimate(
+    node_coordinates_text: &str,
+    edges_text: &str,
+    modules: &[ModuleInsertion],
+) -> String {
+    // Parse into temporaries, parsing errors are reported when generating or visualising
+    let Ok((wireframe, _)) = parse_wireframe(node_coordinates_text, edges_text) else {
+        return "Estimated length: unavailable".to_string();
+    };
+    if wireframe.edges.is_empty() {
+        return "Estimated length: unavailable".to_string();
+    }
+
+    let tree: Tree = construct_tree(&wireframe.edges);
+    let estimate: LengthEstimate =
+        estimate_sequence_length(&tree, &wireframe.vertices).with_modules(modules);
+    format!(
+        "Estimated length: {} nt\nHelices: {} | Hairpins: {} | Kissing loops: {}\nLinkers: {} | Junctions: {} | Modules: {}",
+        estimate.total(),
+        estimate.helices,
+        estimate.hairpins,
+        estimate.kissing_loops,
+        estimate.linkers,
+        estimate.junctions,
+        estimate.modules
+    )
+}
 
 fn update_rendered_object(
     rendered_nodes: &mut Vec<Gm<Mesh, PhysicalMaterial>>,
     nodes: &[Vec3A],
     rendered_edges: &mut Vec<Gm<Mesh, PhysicalMaterial>>,
-    edges: &FxHashSet<(usize, usize)>,
+    edges: &[(usize, usize)],
+    clashes: &[Clash],
     context: &Context,
 ) {
+    // Clashing edges are rendered in red, regardless of their direction
+    let clashing_edges: FxHashSet<(usize, usize)> = clashes
+        .iter()
+        .flat_map(|clash| clash.edges())
+        .map(|(origin, destination)| (origin.min(destination), origin.max(destination)))
+        .collect();
+
     rendered_nodes.clear();
     rendered_edges.clear();
     nodes.iter().for_each(|pos| {
@@ -254,25 +1159,36 @@ fn update_rendered_object(
                 ))
                 * Mat4::from_nonuniform_scale((p1 - p2).magnitude(), 0.5, 0.5);
 
-            let mut cylinder = create_cylinder(context);
+            let colour: Srgba =
+                if clashing_edges.contains(&(origin.min(destination), origin.max(destination))) {
+                    Srgba {
+                        r: 255,
+                        g: 0,
+                        b: 0,
+                        a: 255,
+                    }
+                } else {
+                    Srgba {
+                        r: 0,
+                        g: 0,
+                        b: 5,
+                        a: 255,
+                    }
+                };
+            let mut cylinder = create_cylinder(context, colour);
             cylinder.set_transformation(transform);
             rendered_edges.push(cylinder);
         }
     });
 }
 
-fn create_cylinder(context: &Context) -> Gm<Mesh, PhysicalMaterial> {
+fn create_cylinder(context: &Context, colour: Srgba) -> Gm<Mesh, PhysicalMaterial> {
     Gm::new(
         Mesh::new(context, &CpuMesh::cylinder(8)),
         PhysicalMaterial::new_opaque(
             context,
             &CpuMaterial {
-                albedo: three_d::Srgba {
-                    r: 0,
-                    g: 0,
-                    b: 5,
-                    a: 255,
-                },
+                albedo: colour,
                 ..Default::default()
             },
         ),
@@ -296,3 +1212,26 @@ fn create_sphere(context: &Context) -> Gm<Mesh, PhysicalMaterial> {
         ),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The estimate is updated on every keystroke, so every partly typed structure must be handled
+    #[test]
+    fn length_estimate_handles_partial_input() {
+        let vertices: &str = "[-10.0, 10.0, 0.0]\n[10.0, 10.0, 0.0]\n[10.0, -10.0, 0.0]\n[-10.0, -10.0, 0.0]\n[0.0, 0.0, 20.0]\n";
+        let edges: &str = "(4, 0) (4, 1) (4, 2) (4, 3)\n(0, 1) (1, 2) (2, 3) (3, 0)\n(0, 0) (2, 9)";
+        let complete: String = describe_length_estimate(vertices, edges.get(..55).unwrap(), &[]);
+        assert!(complete.starts_with("Estimated length: "), "{complete}");
+        assert!(!complete.contains("unavailable"), "{complete}");
+
+        vertices.char_indices().for_each(|(end, _)| {
+            describe_length_estimate(vertices.get(..end).unwrap(), edges, &[]);
+        });
+        edges.char_indices().for_each(|(end, _)| {
+            describe_length_estimate(vertices, edges.get(..end).unwrap(), &[]);
+        });
+        describe_length_estimate("1,2,3,4\n0", "0,1,pin=", &[]);
+    }
+}